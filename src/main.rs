@@ -1,27 +1,393 @@
 #![feature(string_remove_matches)]
-#![feature(asm)]
 
 mod pe;
-use std::{env, fs, error::Error};
+use std::{env, fs, error::Error, time::Duration};
 
 // TODO also refactor the header into an entire, PE loading/parsing function/module.
 
+/// How the file is printed by the default command.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+enum Format {
+    #[default]
+    Plain,
+    Markdown,
+}
+
+/// Flags that can appear anywhere on the command line, for any subcommand.
+#[derive(Default)]
+struct Options {
+    /// Where the PE starts within each input file.
+    offset: usize,
+    format: Format,
+    /// Print the one-line summaries as aligned columns under a header row.
+    summary_table: bool,
+    /// Follow the output with each section's CRC-32.
+    crc32: bool,
+    /// Print only this section, by its position in the section table.
+    section: Option<usize>,
+    /// Skip loading section data, for fast metadata scans.
+    no_data: bool,
+    /// The header fields `headers` prints, all of them if not given.
+    fields: Option<Vec<String>>,
+    /// How many bytes `yara-strings` dumps at most.
+    max: Option<usize>,
+}
+
+impl Options {
+    /// Pulls the flags out of `args`, leaving only the positional arguments.
+    fn extract(args: &mut Vec<String>) -> Result<Self, Box<dyn Error>> {
+        let mut options = Self::default();
+        let mut positional = Vec::new();
+        let mut iter = args.drain(..);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--offset" => {
+                    let value = iter.next().ok_or("--offset needs a value")?;
+                    options.offset = parse_number(&value)?;
+                }
+                "--format" => {
+                    options.format = match iter.next().as_deref() {
+                        Some("plain") => Format::Plain,
+                        Some("markdown") => Format::Markdown,
+                        _ => return Err("--format needs one of: plain, markdown".into()),
+                    };
+                }
+                "--section" => {
+                    let value = iter.next().ok_or("--section needs an index")?;
+                    options.section = Some(parse_number(value.strip_prefix('#').unwrap_or(&value))?);
+                }
+                "--max" => {
+                    let value = iter.next().ok_or("--max needs a byte count")?;
+                    options.max = Some(parse_number(&value)?);
+                }
+                "--summary-table" => options.summary_table = true,
+                "--crc32" => options.crc32 = true,
+                "--no-data" => options.no_data = true,
+                "--fields" => {
+                    let value = iter.next().ok_or("--fields needs a comma-separated list")?;
+                    let fields: Vec<String> = value.split(',').map(str::to_string).collect();
+                    if let Some(unknown) = fields.iter().find(|f| !pe::FIELD_NAMES.contains(&f.as_str())) {
+                        return Err(format!(
+                            "unknown field {}, expected one of: {}",
+                            unknown,
+                            pe::FIELD_NAMES.join(", ")
+                        )
+                        .into());
+                    }
+                    options.fields = Some(fields);
+                }
+                _ => positional.push(arg),
+            }
+        }
+        drop(iter);
+        *args = positional;
+        Ok(options)
+    }
+}
+
+/// Parses a decimal or `0x`-prefixed hex number.
+fn parse_number(s: &str) -> Result<usize, Box<dyn Error>> {
+    let n = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16)?,
+        None => s.parse()?,
+    };
+    Ok(n)
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        usage_and_exit();
+    let mut args: Vec<String> = env::args().collect();
+    let options = Options::extract(&mut args)?;
+    match args.get(1).map(String::as_str) {
+        None => usage_and_exit(),
+        #[cfg(feature = "hashes")]
+        Some("diff") => match &args[2..] {
+            [old, new] => diff(&options, old, new),
+            _ => usage_and_exit(),
+        },
+        Some("verify") => match &args[2..] {
+            [path] => verify(&options, path),
+            _ => usage_and_exit(),
+        },
+        Some("tree") => match &args[2..] {
+            [path] => tree(&options, path),
+            _ => usage_and_exit(),
+        },
+        Some("entropy") => match &args[2..] {
+            [path] => entropy(&options, path),
+            _ => usage_and_exit(),
+        },
+        Some("yara-strings") => match &args[2..] {
+            [path, section] => yara_strings(&options, path, section),
+            _ => usage_and_exit(),
+        },
+        Some("headers") => match &args[2..] {
+            [path] => headers(&options, path),
+            _ => usage_and_exit(),
+        },
+        Some(_) if args.len() > 2 || options.summary_table => {
+            summarize(&options, &args[1..]);
+            Ok(())
+        }
+        Some(input_path) => {
+            require_data(&options)?;
+            let file = load(&options, input_path)?;
+            if let Some(index) = options.section {
+                let section = file.section_at(index).ok_or_else(|| {
+                    format!("no section #{}, {} has {}", index, input_path, file.sections.len())
+                })?;
+                println!("{:#?}", section);
+                return Ok(());
+            }
+            match options.format {
+                Format::Plain => println!("{}", file),
+                Format::Markdown => print!("{}", file.to_markdown()),
+            }
+            if options.crc32 {
+                print_crc32s(&options, &file);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Fails for commands that read section data when `--no-data` skipped loading
+/// it, rather than letting them report empty results.
+fn require_data(options: &Options) -> Result<(), Box<dyn Error>> {
+    if options.no_data {
+        return Err("--no-data only applies to summaries and headers".into());
+    }
+    Ok(())
+}
+
+/// Reads and parses the file at `path`, printing the parse error
+/// if it isn't a PE file we understand.
+fn load(options: &Options, path: &str) -> Result<pe::File, Box<dyn Error>> {
+    let input = fs::read(path)?;
+    if options.offset > input.len() {
+        return Err(format!(
+            "offset {:#x} is past the end of {} ({:#x} bytes)",
+            options.offset,
+            path,
+            input.len()
+        )
+        .into());
+    }
+
+    let parse_options = pe::ParseOptions {
+        load_section_data: !options.no_data,
+    };
+    match pe::File::parse_at_or_print_error(&input[..], options.offset, &parse_options) {
+        Some(f) => Ok(f),
+        None => Err("not a PE file we understand".into()),
+    }
+}
+
+/// Prints a one-line summary of each file, carrying on past any that fail to load.
+fn summarize(options: &Options, paths: &[String]) {
+    let rows: Vec<Result<Vec<String>, String>> = paths
+        .iter()
+        .map(|path| match load(options, path) {
+            Ok(file) => Ok(summary_row(path, &file)),
+            Err(e) => Err(format!("{}: {}", path, e)),
+        })
+        .collect();
+
+    if !options.summary_table {
+        for row in rows {
+            match row {
+                Ok(columns) => println!("{}", columns.join("  ")),
+                Err(line) => println!("{}", line),
+            }
+        }
+        return;
+    }
+
+    let header: Vec<String> = [
+        "FILE",
+        "MACHINE",
+        "SUBSYSTEM",
+        "TYPE",
+        "SIGNED",
+        "SECTIONS",
+        "TIMESTAMP",
+        "AGE",
+    ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let mut widths: Vec<usize> = header.iter().map(String::len).collect();
+    for columns in rows.iter().flatten() {
+        for (width, column) in widths.iter_mut().zip(columns) {
+            *width = (*width).max(column.len());
+        }
+    }
+    let print_columns = |columns: &[String]| {
+        let padded: Vec<String> = columns
+            .iter()
+            .zip(&widths)
+            .map(|(column, &width)| format!("{:width$}", column, width = width))
+            .collect();
+        println!("{}", padded.join("  ").trim_end());
+    };
+
+    print_columns(&header);
+    for row in &rows {
+        match row {
+            Ok(columns) => print_columns(columns),
+            Err(line) => println!("{}", line),
+        }
+    }
+}
+
+fn summary_row(path: &str, file: &pe::File) -> Vec<String> {
+    let header = &file.header;
+    let subsystem = match &header.optional_header {
+        Some(oh) => format!("{:?}", oh.windows_header.subsystem),
+        None => "-".to_string(),
+    };
+    vec![
+        path.to_string(),
+        format!("{:?}", header.machine),
+        subsystem,
+        header.characteristics.describe(),
+        if file.is_signed() { "signed" } else { "unsigned" }.to_string(),
+        header.number_of_sections().to_string(),
+        format!("{:#010x}", header.time_date_stamp),
+        header.age().map_or_else(|| "-".to_string(), format_age),
+    ]
+}
+
+/// Formats an age in its largest whole unit, e.g. `3 years ago`.
+fn format_age(age: Duration) -> String {
+    const UNITS: &[(&str, u64)] = &[
+        ("year", 365 * 24 * 60 * 60),
+        ("month", 30 * 24 * 60 * 60),
+        ("day", 24 * 60 * 60),
+        ("hour", 60 * 60),
+        ("minute", 60),
+    ];
+    let secs = age.as_secs();
+    match UNITS.iter().find(|&&(_, unit)| secs >= unit) {
+        Some(&(name, unit)) => {
+            let n = secs / unit;
+            format!("{} {}{} ago", n, name, if n == 1 { "" } else { "s" })
+        }
+        None => "just now".to_string(),
+    }
+}
+
+fn print_crc32s(options: &Options, file: &pe::File) {
+    match options.format {
+        Format::Plain => {
+            println!("Section CRC-32s:");
+            for section in &file.sections {
+                println!("    {:<8} {:08x}", section.name.as_str(), section.crc32());
+            }
+        }
+        Format::Markdown => {
+            println!("\n| Name | CRC-32 |\n| --- | --- |");
+            for section in &file.sections {
+                println!("| `{}` | `{:08x}` |", section.name, section.crc32());
+            }
+        }
+    }
+}
+
+/// Prints header fields one per line: just the values of the `--fields` selected,
+/// or every field as `name=value`. Fields an object file lacks print as `-`.
+fn headers(options: &Options, path: &str) -> Result<(), Box<dyn Error>> {
+    let file = load(options, path)?;
+    let value = |name: &str| file.field(name).unwrap_or_else(|| "-".to_string());
+    match &options.fields {
+        Some(fields) => {
+            for name in fields {
+                println!("{}", value(name));
+            }
+        }
+        None => {
+            for name in pe::FIELD_NAMES {
+                println!("{}={}", name, value(name));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints the entropy of the whole file, then of each section.
+fn entropy(options: &Options, path: &str) -> Result<(), Box<dyn Error>> {
+    require_data(options)?;
+    let file = load(options, path)?;
+    let raw = fs::read(path)?;
+    println!("{:<10} {:>7}", "SECTION", "ENTROPY");
+    println!("{:<10} {:>7.3}", "(file)", pe::File::file_entropy(&raw[options.offset..]));
+    for section in &file.sections {
+        println!("{:<10} {:>7.3}", section.name.as_str(), section.entropy());
     }
-    let input_path = &args[1];
-    let input = fs::read(input_path)?;
+    Ok(())
+}
 
-    let file = match pe::File::parse_or_print_error(&input[..]) {
-        Some(f) => f,
-        None => std::process::exit(1),
+/// Prints a section's data as a YARA hex string, for writing signatures.
+/// `section` is a name like `.text`, or `#N` for the Nth in the table.
+fn yara_strings(options: &Options, path: &str, section: &str) -> Result<(), Box<dyn Error>> {
+    require_data(options)?;
+    let file = load(options, path)?;
+    let found = match section.strip_prefix('#') {
+        Some(index) => file.section_at(parse_number(index)?),
+        None => file.sections.iter().find(|s| s.name.as_str() == section),
     };
+    let section = found.ok_or_else(|| format!("no section {} in {}", section, path))?;
+    let len = options.max.map_or(section.data.len(), |max| max.min(section.data.len()));
+    println!(
+        "// {}: {:#x} of {:#x} bytes from file offset {:#x}",
+        section.name,
+        len,
+        section.data.len(),
+        section.pointer_to_raw_data.0
+    );
+    println!("{}", section.yara_hex(options.max));
+    Ok(())
+}
 
-    println!("{}", file);
+/// Prints the resource directory as an indented tree.
+fn tree(options: &Options, path: &str) -> Result<(), Box<dyn Error>> {
+    require_data(options)?;
+    match load(options, path)?.resource_tree() {
+        Some(tree) => print!("{}", tree),
+        None => println!("no resources"),
+    }
+    Ok(())
+}
 
+/// Prints every structural anomaly found in the file.
+fn verify(options: &Options, path: &str) -> Result<(), Box<dyn Error>> {
+    require_data(options)?;
+    let anomalies = load(options, path)?.verify();
+    if anomalies.is_empty() {
+        println!("no anomalies found");
+    }
+    for anomaly in anomalies {
+        println!("{}", anomaly);
+    }
+    Ok(())
+}
 
+/// Reports which sections were added, removed or changed between two builds.
+#[cfg(feature = "hashes")]
+fn diff(options: &Options, old_path: &str, new_path: &str) -> Result<(), Box<dyn Error>> {
+    require_data(options)?;
+    let old = load(options, old_path)?.section_hashes();
+    let new = load(options, new_path)?.section_hashes();
+
+    for (name, hash) in &old {
+        match new.get(name) {
+            None => println!("section {} removed", name),
+            Some(new_hash) if new_hash != hash => println!("section {} changed", name),
+            Some(_) => {}
+        }
+    }
+    for name in new.keys().filter(|name| !old.contains_key(*name)) {
+        println!("section {} added", name);
+    }
 
     Ok(())
 }
@@ -43,7 +409,16 @@ const PAGE_READONLY: u32 = 0x02;
 #[allow(dead_code)]
 const PAGE_READWRITE: u32 = 0x04;
 
-fn usage_and_exit() {
-    println!("usage: squige FILE");
+fn usage_and_exit() -> ! {
+    println!("usage: squige [--offset N] [--format plain|markdown] [--crc32] FILE");
+    println!("       squige [--offset N] --section N FILE");
+    println!("       squige [--offset N] [--summary-table] [--no-data] FILE...");
+    println!("       squige [--offset N] verify FILE");
+    println!("       squige [--offset N] tree FILE");
+    println!("       squige [--offset N] entropy FILE");
+    println!("       squige [--offset N] [--max N] yara-strings FILE SECTION");
+    println!("       squige [--offset N] [--no-data] headers [--fields NAME,...] FILE");
+    #[cfg(feature = "hashes")]
+    println!("       squige [--offset N] diff OLD NEW");
     std::process::exit(1);
-}
\ No newline at end of file
+}
@@ -0,0 +1,196 @@
+//! Values from the PE/COFF specification that the parsers match against.
+//!
+//! Listed in full, so not every value has a user yet.
+#![allow(dead_code)]
+
+/// The data directories, in the order the optional header lists them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum DataDirectoryKind {
+    Export = 0,
+    Import = 1,
+    Resource = 2,
+    Exception = 3,
+    Certificate = 4,
+    BaseRelocation = 5,
+    Debug = 6,
+    /// Reserved, must be zero.
+    Architecture = 7,
+    GlobalPtr = 8,
+    Tls = 9,
+    LoadConfig = 10,
+    BoundImport = 11,
+    Iat = 12,
+    DelayImportDescriptor = 13,
+    ClrRuntimeHeader = 14,
+    /// Reserved, must be zero.
+    Reserved = 15,
+}
+
+impl DataDirectoryKind {
+    pub const ALL: [Self; 16] = [
+        Self::Export,
+        Self::Import,
+        Self::Resource,
+        Self::Exception,
+        Self::Certificate,
+        Self::BaseRelocation,
+        Self::Debug,
+        Self::Architecture,
+        Self::GlobalPtr,
+        Self::Tls,
+        Self::LoadConfig,
+        Self::BoundImport,
+        Self::Iat,
+        Self::DelayImportDescriptor,
+        Self::ClrRuntimeHeader,
+        Self::Reserved,
+    ];
+
+    /// The directory at `index` in the optional header, `None` past the standard 16.
+    pub fn from_index(index: usize) -> Option<Self> {
+        Self::ALL.get(index).copied()
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The directory's name as the specification's table of them gives it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Export => "Export Table",
+            Self::Import => "Import Table",
+            Self::Resource => "Resource Table",
+            Self::Exception => "Exception Table",
+            Self::Certificate => "Certificate Table",
+            Self::BaseRelocation => "Base Relocation Table",
+            Self::Debug => "Debug",
+            Self::Architecture => "Architecture",
+            Self::GlobalPtr => "Global Ptr",
+            Self::Tls => "TLS Table",
+            Self::LoadConfig => "Load Config Table",
+            Self::BoundImport => "Bound Import",
+            Self::Iat => "IAT",
+            Self::DelayImportDescriptor => "Delay Import Descriptor",
+            Self::ClrRuntimeHeader => "CLR Runtime Header",
+            Self::Reserved => "Reserved",
+        }
+    }
+}
+
+pub const RT_CURSOR: u16 = 1;
+pub const RT_BITMAP: u16 = 2;
+pub const RT_ICON: u16 = 3;
+pub const RT_MENU: u16 = 4;
+pub const RT_DIALOG: u16 = 5;
+pub const RT_STRING: u16 = 6;
+pub const RT_FONTDIR: u16 = 7;
+pub const RT_FONT: u16 = 8;
+pub const RT_ACCELERATOR: u16 = 9;
+pub const RT_RCDATA: u16 = 10;
+pub const RT_MESSAGETABLE: u16 = 11;
+pub const RT_GROUP_CURSOR: u16 = 12;
+pub const RT_GROUP_ICON: u16 = 14;
+pub const RT_VERSION: u16 = 16;
+pub const RT_DLGINCLUDE: u16 = 17;
+pub const RT_PLUGPLAY: u16 = 19;
+pub const RT_VXD: u16 = 20;
+pub const RT_ANICURSOR: u16 = 21;
+pub const RT_ANIICON: u16 = 22;
+pub const RT_HTML: u16 = 23;
+pub const RT_MANIFEST: u16 = 24;
+
+/// The `RT_*` names of the predefined resource types, by id.
+pub const RT_NAMES: &[(u16, &str)] = &[
+    (RT_CURSOR, "RT_CURSOR"),
+    (RT_BITMAP, "RT_BITMAP"),
+    (RT_ICON, "RT_ICON"),
+    (RT_MENU, "RT_MENU"),
+    (RT_DIALOG, "RT_DIALOG"),
+    (RT_STRING, "RT_STRING"),
+    (RT_FONTDIR, "RT_FONTDIR"),
+    (RT_FONT, "RT_FONT"),
+    (RT_ACCELERATOR, "RT_ACCELERATOR"),
+    (RT_RCDATA, "RT_RCDATA"),
+    (RT_MESSAGETABLE, "RT_MESSAGETABLE"),
+    (RT_GROUP_CURSOR, "RT_GROUP_CURSOR"),
+    (RT_GROUP_ICON, "RT_GROUP_ICON"),
+    (RT_VERSION, "RT_VERSION"),
+    (RT_DLGINCLUDE, "RT_DLGINCLUDE"),
+    (RT_PLUGPLAY, "RT_PLUGPLAY"),
+    (RT_VXD, "RT_VXD"),
+    (RT_ANICURSOR, "RT_ANICURSOR"),
+    (RT_ANIICON, "RT_ANIICON"),
+    (RT_HTML, "RT_HTML"),
+    (RT_MANIFEST, "RT_MANIFEST"),
+];
+
+pub const IMAGE_DEBUG_TYPE_UNKNOWN: u32 = 0;
+pub const IMAGE_DEBUG_TYPE_COFF: u32 = 1;
+/// A CodeView record, pointing at the PDB.
+pub const IMAGE_DEBUG_TYPE_CODEVIEW: u32 = 2;
+pub const IMAGE_DEBUG_TYPE_FPO: u32 = 3;
+pub const IMAGE_DEBUG_TYPE_MISC: u32 = 4;
+pub const IMAGE_DEBUG_TYPE_EXCEPTION: u32 = 5;
+pub const IMAGE_DEBUG_TYPE_FIXUP: u32 = 6;
+pub const IMAGE_DEBUG_TYPE_OMAP_TO_SRC: u32 = 7;
+pub const IMAGE_DEBUG_TYPE_OMAP_FROM_SRC: u32 = 8;
+pub const IMAGE_DEBUG_TYPE_BORLAND: u32 = 9;
+pub const IMAGE_DEBUG_TYPE_CLSID: u32 = 11;
+/// Counts of the objects built with each MSVC hardening feature.
+pub const IMAGE_DEBUG_TYPE_VC_FEATURE: u32 = 12;
+pub const IMAGE_DEBUG_TYPE_POGO: u32 = 13;
+/// Present when the image was built with link-time code generation.
+pub const IMAGE_DEBUG_TYPE_ILTCG: u32 = 14;
+pub const IMAGE_DEBUG_TYPE_MPX: u32 = 15;
+/// Marks a reproducible build, whose timestamps are hashes.
+pub const IMAGE_DEBUG_TYPE_REPRO: u32 = 16;
+pub const IMAGE_DEBUG_TYPE_EX_DLLCHARACTERISTICS: u32 = 20;
+
+/// Padding to keep blocks 32-bit aligned, skipped by the loader.
+pub const IMAGE_REL_BASED_ABSOLUTE: u8 = 0;
+pub const IMAGE_REL_BASED_HIGH: u8 = 1;
+pub const IMAGE_REL_BASED_LOW: u8 = 2;
+/// The full 32 bits at the offset get the difference in image base added.
+pub const IMAGE_REL_BASED_HIGHLOW: u8 = 3;
+pub const IMAGE_REL_BASED_HIGHADJ: u8 = 4;
+/// The full 64 bits at the offset get the difference in image base added.
+pub const IMAGE_REL_BASED_DIR64: u8 = 10;
+
+pub const IMAGE_REL_AMD64_ABSOLUTE: u16 = 0x0000;
+pub const IMAGE_REL_AMD64_ADDR64: u16 = 0x0001;
+pub const IMAGE_REL_AMD64_ADDR32: u16 = 0x0002;
+pub const IMAGE_REL_AMD64_ADDR32NB: u16 = 0x0003;
+pub const IMAGE_REL_AMD64_REL32: u16 = 0x0004;
+pub const IMAGE_REL_AMD64_SECTION: u16 = 0x000a;
+pub const IMAGE_REL_AMD64_SECREL: u16 = 0x000b;
+
+pub const IMAGE_REL_I386_ABSOLUTE: u16 = 0x0000;
+pub const IMAGE_REL_I386_DIR32: u16 = 0x0006;
+pub const IMAGE_REL_I386_DIR32NB: u16 = 0x0007;
+pub const IMAGE_REL_I386_SECTION: u16 = 0x000a;
+pub const IMAGE_REL_I386_SECREL: u16 = 0x000b;
+pub const IMAGE_REL_I386_REL32: u16 = 0x0014;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_match_the_specification() {
+        assert_eq!(DataDirectoryKind::ClrRuntimeHeader.index(), 14);
+        assert_eq!(DataDirectoryKind::from_index(5), Some(DataDirectoryKind::BaseRelocation));
+        assert_eq!(DataDirectoryKind::from_index(16), None);
+        assert_eq!(DataDirectoryKind::Iat.name(), "IAT");
+        for (index, kind) in DataDirectoryKind::ALL.iter().enumerate() {
+            assert_eq!(kind.index(), index);
+        }
+
+        assert_eq!((RT_ICON, RT_GROUP_ICON, RT_VERSION, RT_MANIFEST), (3, 14, 16, 24));
+        assert_eq!(IMAGE_DEBUG_TYPE_CODEVIEW, 2);
+        assert_eq!(IMAGE_DEBUG_TYPE_REPRO, 16);
+        assert_eq!((IMAGE_REL_BASED_HIGHLOW, IMAGE_REL_BASED_DIR64), (3, 10));
+        assert_eq!(IMAGE_REL_AMD64_REL32, 4);
+    }
+}
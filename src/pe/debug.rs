@@ -0,0 +1,269 @@
+use super::constants::*;
+use super::util::*;
+use super::File;
+
+/// Size of one `IMAGE_DEBUG_DIRECTORY` record.
+const DEBUG_ENTRY_SIZE: usize = 28;
+
+/// How many objects the MSVC toolchain built with each hardening feature,
+/// from an `IMAGE_DEBUG_TYPE_VC_FEATURE` entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VcFeature {
+    /// Objects built by compilers older than VC++ 11, which predate these counters.
+    pub pre_vc11: u32,
+    /// Objects built from C/C++ by a compiler that keeps the counters.
+    pub c_cpp: u32,
+    /// Objects built with `/GS` stack cookies.
+    pub gs: u32,
+    /// Objects built with `/sdl` additional security checks.
+    pub sdl: u32,
+    /// Objects built with `/guard:cf` but excluded from its checks via `/guard:cf-`.
+    pub guard_n: u32,
+}
+
+impl VcFeature {
+    fn parse(i: Input) -> Result<Self> {
+        use nom::{error::context, number::complete::*, sequence::tuple};
+        let (i, (pre_vc11, c_cpp, gs, sdl, guard_n)) = tuple((
+            context("PreVC11", le_u32),
+            context("C/C++", le_u32),
+            context("/GS", le_u32),
+            context("/sdl", le_u32),
+            context("GuardN", le_u32),
+        ))(i)?;
+        Ok((
+            i,
+            Self {
+                pre_vc11,
+                c_cpp,
+                gs,
+                sdl,
+                guard_n,
+            },
+        ))
+    }
+}
+
+/// An entry in the debug directory, describing one blob of debugging information.
+#[derive(Debug, PartialEq)]
+pub struct DebugEntry {
+    pub characteristics: u32,
+    pub time_date_stamp: u32,
+    pub major_version: u16,
+    pub minor_version: u16,
+    /// One of the `IMAGE_DEBUG_TYPE_*` constants.
+    pub debug_type: u32,
+    pub size_of_data: u32,
+    /// Zero if the data isn't mapped into memory.
+    pub address_of_raw_data: Addr32,
+    pub pointer_to_raw_data: Addr32,
+    /// The decoded counters, for `IMAGE_DEBUG_TYPE_VC_FEATURE` entries.
+    pub vc_feature: Option<VcFeature>,
+}
+
+impl DebugEntry {
+    fn parse(i: Input) -> Result<Self> {
+        use nom::{error::context, number::complete::*, sequence::tuple};
+        let (
+            i,
+            (
+                characteristics,
+                time_date_stamp,
+                major_version,
+                minor_version,
+                debug_type,
+                size_of_data,
+                address_of_raw_data,
+                pointer_to_raw_data,
+            ),
+        ) = tuple((
+            context("Characteristics", le_u32),
+            context("TimeDateStamp", le_u32),
+            context("MajorVersion", le_u16),
+            context("MinorVersion", le_u16),
+            context("Type", le_u32),
+            context("SizeOfData", le_u32),
+            context("AddressOfRawData", Addr32::parse),
+            context("PointerToRawData", Addr32::parse),
+        ))(i)?;
+        Ok((
+            i,
+            Self {
+                characteristics,
+                time_date_stamp,
+                major_version,
+                minor_version,
+                debug_type,
+                size_of_data,
+                address_of_raw_data,
+                pointer_to_raw_data,
+                vc_feature: None,
+            },
+        ))
+    }
+
+    /// Whether the image was linked with incremental link-time code generation.
+    /// The entry itself carries no data, its presence is the marker.
+    #[allow(dead_code)]
+    pub fn is_iltcg(&self) -> bool {
+        self.debug_type == IMAGE_DEBUG_TYPE_ILTCG
+    }
+}
+
+impl File {
+    /// Parses the entries of the debug directory, decoding the data of the types we understand.
+    ///
+    /// Returns an empty list if the image has no debug directory.
+    #[allow(dead_code)]
+    pub fn debug_entries(&self) -> Vec<DebugEntry> {
+        let dir = match &self.header.optional_header {
+            Some(oh) => &oh.data_directories.debug_data,
+            None => return Vec::new(),
+        };
+        let bytes = match dir.resolve_into(self) {
+            Some(bytes) => bytes,
+            None => return Vec::new(),
+        };
+
+        let mut entries = Vec::new();
+        for record in bytes.chunks_exact(DEBUG_ENTRY_SIZE) {
+            let mut entry = match DebugEntry::parse(record) {
+                Ok((_, entry)) => entry,
+                Err(_) => break,
+            };
+            if entry.debug_type == IMAGE_DEBUG_TYPE_VC_FEATURE {
+                entry.vc_feature = self
+                    .read_at_rva(entry.address_of_raw_data.0, entry.size_of_data as usize)
+                    .and_then(|data| VcFeature::parse(&data).ok().map(|(_, vc)| vc));
+            }
+            entries.push(entry);
+        }
+        entries
+    }
+
+    /// Whether the debug directory's timestamps agree with the COFF header's,
+    /// as the linker writes the same value to both. Entries with a zero timestamp
+    /// are left out, and `None` if there are none left to compare.
+    pub fn timestamp_consistency(&self) -> Option<bool> {
+        let stamps: Vec<u32> = self
+            .debug_entries()
+            .iter()
+            .map(|entry| entry.time_date_stamp)
+            .filter(|&stamp| stamp != 0)
+            .collect();
+        if stamps.is_empty() {
+            return None;
+        }
+        Some(stamps.iter().all(|&stamp| stamp == self.header.time_date_stamp))
+    }
+
+    /// The type and data of each debug directory entry, undecoded, in the same
+    /// order as [`File::debug_entries`].
+    ///
+    /// The data is found through `address_of_raw_data`, so entries whose data
+    /// isn't mapped, or runs past the end of its section, get an empty slice.
+    #[allow(dead_code)]
+    pub fn debug_entries_raw(&self) -> Vec<(u32, &[u8])> {
+        self.debug_entries()
+            .iter()
+            .map(|entry| {
+                let rva = entry.address_of_raw_data.0;
+                let data = match self.section_for_rva(rva) {
+                    Some(section) if rva != 0 => section
+                        .data_slice((rva - section.virtual_address.0) as usize, entry.size_of_data as usize)
+                        .unwrap_or(&[]),
+                    _ => &[],
+                };
+                (entry.debug_type, data)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::*;
+    use super::*;
+
+    /// Where the fixtures' debug directory is mapped.
+    const RDATA_RVA: u32 = 0x2000;
+
+    /// An image whose debug directory has an entry of each `(type, timestamp,
+    /// data)`, the data placed after the directory in the same section.
+    fn debug_image(entries: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        let size = (entries.len() * DEBUG_ENTRY_SIZE) as u32;
+        let mut rdata = vec![0; size as usize];
+        for (n, (debug_type, stamp, data)) in entries.iter().enumerate() {
+            let at = n * DEBUG_ENTRY_SIZE;
+            let rva = RDATA_RVA + rdata.len() as u32;
+            put(&mut rdata, at + 4, &stamp.to_le_bytes());
+            put(&mut rdata, at + 12, &debug_type.to_le_bytes());
+            put(&mut rdata, at + 16, &(data.len() as u32).to_le_bytes());
+            put(&mut rdata, at + 20, &rva.to_le_bytes());
+            // Raw data starts at 0x600, after .text's.
+            put(&mut rdata, at + 24, &(0x600 + rva - RDATA_RVA).to_le_bytes());
+            rdata.extend_from_slice(data);
+        }
+        PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x200], CODE)
+            .section(".rdata", RDATA_RVA, rdata, RDATA)
+            .directory(DataDirectoryKind::Debug, RDATA_RVA, size)
+            .build()
+    }
+
+    #[test]
+    fn vc_feature_counters_and_iltcg_are_decoded() {
+        let mut counters = Vec::new();
+        for count in [0_u32, 42, 40, 3, 0] {
+            counters.extend_from_slice(&count.to_le_bytes());
+        }
+        let raw = debug_image(&[
+            (IMAGE_DEBUG_TYPE_VC_FEATURE, 0, &counters),
+            (IMAGE_DEBUG_TYPE_ILTCG, 0, &[]),
+        ]);
+        let (_, file) = File::parse(&raw).unwrap();
+        let entries = file.debug_entries();
+
+        assert_eq!(
+            entries[0].vc_feature,
+            Some(VcFeature { pre_vc11: 0, c_cpp: 42, gs: 40, sdl: 3, guard_n: 0 })
+        );
+        assert!(!entries[0].is_iltcg());
+        assert!(entries[1].is_iltcg());
+        assert_eq!(entries[1].vc_feature, None);
+    }
+
+    #[test]
+    fn raw_entries_hold_the_codeview_record() {
+        let mut codeview = b"RSDS".to_vec();
+        codeview.extend_from_slice(&[0x11; 16]);
+        codeview.extend_from_slice(&1_u32.to_le_bytes());
+        codeview.extend_from_slice(b"C:\\build\\app.pdb\0");
+        let raw = debug_image(&[
+            (IMAGE_DEBUG_TYPE_CODEVIEW, 0x5f00_0000, &codeview),
+            (IMAGE_DEBUG_TYPE_ILTCG, 0x5f00_0000, &[]),
+        ]);
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let entries = file.debug_entries_raw();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], (IMAGE_DEBUG_TYPE_CODEVIEW, &codeview[..]));
+        assert_eq!(entries[1], (IMAGE_DEBUG_TYPE_ILTCG, &[][..]));
+    }
+
+    #[test]
+    fn mismatched_timestamps_are_flagged() {
+        let raw = debug_image(&[(IMAGE_DEBUG_TYPE_CODEVIEW, 0x5f00_0000, &[]), (16, 0, &[])]);
+        let (_, file) = File::parse(&raw).unwrap();
+        assert_eq!(file.timestamp_consistency(), Some(true));
+
+        let raw = debug_image(&[(IMAGE_DEBUG_TYPE_CODEVIEW, 0x6000_0000, &[])]);
+        let (_, file) = File::parse(&raw).unwrap();
+        assert_eq!(file.timestamp_consistency(), Some(false));
+        let expected = "debug directory timestamps don't match the COFF header's 0x5f000000";
+        assert!(file.verify().iter().any(|a| a.message == expected));
+
+        let raw = debug_image(&[(16, 0, &[])]);
+        assert_eq!(File::parse(&raw).unwrap().1.timestamp_consistency(), None);
+    }
+}
@@ -0,0 +1,189 @@
+use super::header::DataDirectory;
+use super::util::*;
+use super::File;
+
+/// The assembly contains only IL, no native code.
+pub const COMIMAGE_FLAGS_ILONLY: u32 = 0x1;
+
+/// `RTR\0` read little-endian, the signature of a ReadyToRun header.
+const READYTORUN_SIGNATURE: u32 = 0x0052_5452;
+
+/// `IMAGE_COR20_HEADER`, which the CLR runtime header data directory points at
+/// in .NET assemblies.
+#[derive(Debug, PartialEq)]
+pub struct ClrHeader {
+    pub major_runtime_version: u16,
+    pub minor_runtime_version: u16,
+    pub metadata: DataDirectory,
+    /// `COMIMAGE_FLAGS_*` values.
+    pub flags: u32,
+    pub entry_point_token: u32,
+    pub resources: DataDirectory,
+    pub strong_name_signature: DataDirectory,
+    pub code_manager_table: DataDirectory,
+    pub vtable_fixups: DataDirectory,
+    pub export_address_table_jumps: DataDirectory,
+    /// Points at the ReadyToRun header in precompiled assemblies.
+    pub managed_native_header: DataDirectory,
+}
+
+impl ClrHeader {
+    fn parse(i: Input) -> Result<Self> {
+        use nom::{error::context, number::complete::*, sequence::tuple};
+        let (
+            i,
+            (
+                _cb,
+                major_runtime_version,
+                minor_runtime_version,
+                metadata,
+                flags,
+                entry_point_token,
+                resources,
+                strong_name_signature,
+                code_manager_table,
+                vtable_fixups,
+                export_address_table_jumps,
+                managed_native_header,
+            ),
+        ) = tuple((
+            context("Cb", le_u32),
+            context("MajorRuntimeVersion", le_u16),
+            context("MinorRuntimeVersion", le_u16),
+            context("MetaData", DataDirectory::parse),
+            context("Flags", le_u32),
+            context("EntryPointToken", le_u32),
+            context("Resources", DataDirectory::parse),
+            context("StrongNameSignature", DataDirectory::parse),
+            context("CodeManagerTable", DataDirectory::parse),
+            context("VTableFixups", DataDirectory::parse),
+            context("ExportAddressTableJumps", DataDirectory::parse),
+            context("ManagedNativeHeader", DataDirectory::parse),
+        ))(i)?;
+        Ok((
+            i,
+            Self {
+                major_runtime_version,
+                minor_runtime_version,
+                metadata,
+                flags,
+                entry_point_token,
+                resources,
+                strong_name_signature,
+                code_manager_table,
+                vtable_fixups,
+                export_address_table_jumps,
+                managed_native_header,
+            },
+        ))
+    }
+}
+
+/// How a .NET binary's code is shipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DotNetFlavor {
+    /// Plain IL, compiled by the JIT at runtime.
+    IlOnly,
+    /// IL alongside native code, as produced by C++/CLI.
+    Mixed,
+    /// IL with precompiled native code the runtime can use instead of JITting.
+    ReadyToRun,
+    /// Compiled ahead of time to a native image with no IL or CLR header left.
+    NativeAot,
+}
+
+impl File {
+    /// Parses the CLR runtime header, present in every IL-bearing .NET assembly.
+    pub fn clr_header(&self) -> Option<ClrHeader> {
+        let dir = &self
+            .header
+            .optional_header
+            .as_ref()?
+            .data_directories
+            .clr_runtime_header;
+        let bytes = dir.resolve_into(self)?;
+        ClrHeader::parse(&bytes).ok().map(|(_, clr)| clr)
+    }
+
+    /// Tells the kinds of .NET binary apart, `None` if it isn't one.
+    ///
+    /// Native AOT images are recognised by the `.managed` section the compiler
+    /// emits their code into, since there's no CLR header to go on.
+    #[allow(dead_code)]
+    pub fn dotnet_flavor(&self) -> Option<DotNetFlavor> {
+        let clr = match self.clr_header() {
+            Some(clr) => clr,
+            None if self.sections.iter().any(|s| s.name.as_str() == ".managed") => {
+                return Some(DotNetFlavor::NativeAot)
+            }
+            None => return None,
+        };
+
+        let ready_to_run = clr
+            .managed_native_header
+            .resolve_into(self)
+            .filter(|header| header.len() >= 4)
+            .is_some_and(|header| {
+                u32::from_le_bytes([header[0], header[1], header[2], header[3]])
+                    == READYTORUN_SIGNATURE
+            });
+        Some(if ready_to_run {
+            DotNetFlavor::ReadyToRun
+        } else if clr.flags & COMIMAGE_FLAGS_ILONLY != 0 {
+            DotNetFlavor::IlOnly
+        } else {
+            DotNetFlavor::Mixed
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::constants::DataDirectoryKind;
+    use super::super::fixtures::*;
+    use super::*;
+
+    /// Where the fixtures' `.text` section, holding the CLR header, is mapped.
+    const TEXT: u32 = 0x2000;
+
+    /// An assembly with `flags`, and a ReadyToRun header right after its CLR
+    /// header if `ready_to_run`.
+    fn assembly(flags: u32, ready_to_run: bool) -> Vec<u8> {
+        let mut text = vec![0; 72];
+        put(&mut text, 0, &72_u32.to_le_bytes());
+        put(&mut text, 4, &[2, 0, 5, 0]);
+        put(&mut text, 16, &flags.to_le_bytes());
+        if ready_to_run {
+            put(&mut text, 64, &(TEXT + 72).to_le_bytes());
+            put(&mut text, 68, &16_u32.to_le_bytes());
+        }
+        text.extend_from_slice(b"RTR\0\x05\0\x02\0");
+        text.resize(88, 0);
+        PeBuilder::default()
+            .section(".text", TEXT, text, CODE)
+            .directory(DataDirectoryKind::ClrRuntimeHeader, TEXT, 72)
+            .build()
+    }
+
+    fn flavor(raw: &[u8]) -> Option<DotNetFlavor> {
+        let (_, file) = File::parse(raw).unwrap();
+        file.dotnet_flavor()
+    }
+
+    #[test]
+    fn ready_to_run_assemblies_are_told_apart() {
+        assert_eq!(flavor(&assembly(COMIMAGE_FLAGS_ILONLY, true)), Some(DotNetFlavor::ReadyToRun));
+        assert_eq!(flavor(&assembly(COMIMAGE_FLAGS_ILONLY, false)), Some(DotNetFlavor::IlOnly));
+        assert_eq!(flavor(&assembly(0, false)), Some(DotNetFlavor::Mixed));
+
+        let native_aot = PeBuilder::default()
+            .section(".managed", 0x1000, vec![0xc3; 0x10], CODE)
+            .build();
+        assert_eq!(flavor(&native_aot), Some(DotNetFlavor::NativeAot));
+        assert_eq!(flavor(&simple_image(vec![0xc3])), None);
+
+        let (_, file) = File::parse(&assembly(COMIMAGE_FLAGS_ILONLY, true)).unwrap();
+        let clr = file.clr_header().unwrap();
+        assert_eq!((clr.major_runtime_version, clr.minor_runtime_version), (2, 5));
+    }
+}
@@ -0,0 +1,259 @@
+use super::util::*;
+use super::File;
+
+/// Size of one `RUNTIME_FUNCTION` entry in `.pdata`.
+const RUNTIME_FUNCTION_SIZE: usize = 12;
+
+/// The function has an exception handler, called while looking for one to handle an exception.
+pub const UNW_FLAG_EHANDLER: u8 = 0x1;
+/// The function has a termination handler, called while unwinding.
+pub const UNW_FLAG_UHANDLER: u8 = 0x2;
+/// The unwind info continues that of another function (e.g. a separately placed cold path).
+pub const UNW_FLAG_CHAININFO: u8 = 0x4;
+
+/// Sanity limit on `__C_specific_handler` scope tables, as the count is read
+/// from handler data whose format we can only guess at.
+const MAX_SCOPE_RECORDS: u32 = 1024;
+
+/// One `__try` block, as recorded for `__C_specific_handler`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScopeRecord {
+    pub begin_address: Addr32,
+    pub end_address: Addr32,
+    /// The `__except` filter, or `1` for `EXCEPTION_EXECUTE_HANDLER`.
+    /// For a `__finally` block this is the termination handler and `jump_target` is zero.
+    pub handler_address: Addr32,
+    /// Where the `__except` block starts.
+    pub jump_target: Addr32,
+}
+
+/// The x64 `UNWIND_INFO` describing how to unwind a function's stack frame.
+#[derive(Debug, PartialEq)]
+pub struct UnwindInfo {
+    pub version: u8,
+    /// `UNW_FLAG_*` values.
+    pub flags: u8,
+    pub size_of_prolog: u8,
+    pub frame_register: u8,
+    /// Scaled by 16 to get the offset applied to the frame register.
+    pub frame_offset: u8,
+    /// The raw unwind code slots, some operations taking two or three of them.
+    pub unwind_codes: Vec<u16>,
+    /// RVA of the language-specific handler, if either handler flag is set.
+    pub handler: Option<Addr32>,
+    /// The `__try` regions, when the handler data looks like a `__C_specific_handler` scope table.
+    pub scope_table: Option<Vec<ScopeRecord>>,
+}
+
+/// An entry of the exception table: a function and where its unwind info is.
+#[derive(Debug, PartialEq)]
+pub struct RuntimeFunction {
+    pub begin_address: Addr32,
+    pub end_address: Addr32,
+    pub unwind_info_address: Addr32,
+    /// `None` if the unwind info couldn't be read.
+    pub unwind_info: Option<UnwindInfo>,
+}
+
+impl RuntimeFunction {
+    fn parse(i: Input) -> Result<Self> {
+        use nom::{error::context, sequence::tuple};
+        let (i, (begin_address, end_address, unwind_info_address)) = tuple((
+            context("BeginAddress", Addr32::parse),
+            context("EndAddress", Addr32::parse),
+            context("UnwindInfoAddress", Addr32::parse),
+        ))(i)?;
+        Ok((
+            i,
+            Self {
+                begin_address,
+                end_address,
+                unwind_info_address,
+                unwind_info: None,
+            },
+        ))
+    }
+
+    /// The `__try` regions of this function, if it uses `__C_specific_handler`.
+    #[allow(dead_code)]
+    pub fn scope_table(&self) -> Option<&[ScopeRecord]> {
+        self.unwind_info.as_ref()?.scope_table.as_deref()
+    }
+}
+
+impl File {
+    /// Parses the exception table (`.pdata`) along with each function's unwind info.
+    ///
+    /// Table-based exception handling is specific to 64-bit images: 32-bit x86
+    /// registers its handlers at run time instead (see [`File::safe_seh_handlers`]).
+    /// Returns an empty list for PE32 images and if the image has no exception table.
+    #[allow(dead_code)]
+    pub fn runtime_functions(&self) -> Vec<RuntimeFunction> {
+        let dir = match &self.header.optional_header {
+            Some(oh) if oh.base_of_data.is_none() => &oh.data_directories.exception_table,
+            _ => return Vec::new(),
+        };
+        let bytes = match dir.resolve_into(self) {
+            Some(bytes) => bytes,
+            None => return Vec::new(),
+        };
+
+        bytes
+            .chunks_exact(RUNTIME_FUNCTION_SIZE)
+            .filter_map(|entry| RuntimeFunction::parse(entry).ok())
+            .map(|(_, mut function)| {
+                function.unwind_info = self.unwind_info(&function);
+                function
+            })
+            .collect()
+    }
+
+    fn unwind_info(&self, function: &RuntimeFunction) -> Option<UnwindInfo> {
+        let rva = function.unwind_info_address.0;
+        let header = self.read_at_rva(rva, 4)?;
+        let (version, flags) = (header[0] & 0x7, header[0] >> 3);
+        let count_of_codes = header[2] as usize;
+
+        let codes = self.read_at_rva(rva.wrapping_add(4), count_of_codes * 2)?;
+        let unwind_codes = codes
+            .chunks_exact(2)
+            .map(|code| u16::from_le_bytes([code[0], code[1]]))
+            .collect();
+
+        // The code array is padded to an even number of slots.
+        let after_codes = rva.wrapping_add(4 + ((count_of_codes + 1) & !1) as u32 * 2);
+        let mut handler = None;
+        let mut scope_table = None;
+        if flags & (UNW_FLAG_EHANDLER | UNW_FLAG_UHANDLER) != 0 && flags & UNW_FLAG_CHAININFO == 0 {
+            let b = self.read_at_rva(after_codes, 4)?;
+            handler = Some(Addr32(u32::from_le_bytes([b[0], b[1], b[2], b[3]])));
+            scope_table = self.scope_table(after_codes.wrapping_add(4), function);
+        }
+
+        Some(UnwindInfo {
+            version,
+            flags,
+            size_of_prolog: header[1],
+            frame_register: header[3] & 0xf,
+            frame_offset: header[3] >> 4,
+            unwind_codes,
+            handler,
+            scope_table,
+        })
+    }
+
+    /// Reads the handler data at `rva` as a `__C_specific_handler` scope table.
+    ///
+    /// Other handlers (C++'s `__CxxFrameHandler`, GCC's personality routine) use
+    /// their own formats, which we can't tell apart from here without resolving the
+    /// handler. So a table is only returned if every scope lies within `function`.
+    fn scope_table(&self, rva: u32, function: &RuntimeFunction) -> Option<Vec<ScopeRecord>> {
+        let b = self.read_at_rva(rva, 4)?;
+        let count = u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+        if count == 0 || count > MAX_SCOPE_RECORDS {
+            return None;
+        }
+
+        let bytes = self.read_at_rva(rva.wrapping_add(4), count as usize * 16)?;
+        let dword = |at: usize| {
+            Addr32(u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]))
+        };
+        let function_range = function.begin_address..=function.end_address;
+        (0..count as usize)
+            .map(|n| {
+                let at = n * 16;
+                let record = ScopeRecord {
+                    begin_address: dword(at),
+                    end_address: dword(at + 4),
+                    handler_address: dword(at + 8),
+                    jump_target: dword(at + 12),
+                };
+                let plausible = record.begin_address < record.end_address
+                    && function_range.contains(&record.begin_address)
+                    && function_range.contains(&record.end_address);
+                plausible.then_some(record)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::constants::DataDirectoryKind;
+    use super::super::fixtures::*;
+    use super::*;
+
+    /// Where the fixtures' `.pdata` section is mapped.
+    const PDATA: u32 = 0x2000;
+
+    /// The unwind info of a function with a `__C_specific_handler` at `0x1100`,
+    /// with one unwind code and the `scope` table after it.
+    fn unwind_info(scope: &[[u32; 4]]) -> Vec<u8> {
+        let mut out = vec![1 | UNW_FLAG_EHANDLER << 3, 4, 1, 0];
+        out.extend_from_slice(&[0x04, 0x42, 0, 0]);
+        out.extend_from_slice(&0x1100_u32.to_le_bytes());
+        out.extend_from_slice(&(scope.len() as u32).to_le_bytes());
+        for dword in scope.iter().flatten() {
+            out.extend_from_slice(&dword.to_le_bytes());
+        }
+        out
+    }
+
+    /// An image whose exception table covers two functions, `0x1000..0x1040`
+    /// and `0x1040..0x1080`, the second with a scope table outside it.
+    fn pdata_image(builder: PeBuilder) -> Vec<u8> {
+        let first = unwind_info(&[[0x1008, 0x1020, 1, 0x1030]]);
+        let second = unwind_info(&[[0x3000, 0x3010, 1, 0x3020]]);
+        let mut pdata = Vec::new();
+        let first_rva = PDATA + 24;
+        let second_rva = first_rva + first.len() as u32;
+        for dword in [0x1000, 0x1040, first_rva, 0x1040, 0x1080, second_rva] {
+            pdata.extend_from_slice(&dword.to_le_bytes());
+        }
+        pdata.extend(first);
+        pdata.extend(second);
+        builder
+            .section(".text", 0x1000, vec![0xcc; 0x200], CODE)
+            .section(".pdata", PDATA, pdata, RDATA)
+            .directory(DataDirectoryKind::Exception, PDATA, 24)
+            .build()
+    }
+
+    #[test]
+    fn scope_tables_are_decoded_for_c_specific_handlers() {
+        let raw = pdata_image(PeBuilder::default());
+        let (_, file) = File::parse(&raw).unwrap();
+        let functions = file.runtime_functions();
+
+        assert_eq!(functions.len(), 2);
+        let info = functions[0].unwind_info.as_ref().unwrap();
+        assert_eq!((info.version, info.flags, info.size_of_prolog), (1, UNW_FLAG_EHANDLER, 4));
+        assert_eq!(info.unwind_codes, [0x4204]);
+        assert_eq!(info.handler, Some(Addr32(0x1100)));
+        assert_eq!(
+            functions[0].scope_table(),
+            Some(
+                &[ScopeRecord {
+                    begin_address: Addr32(0x1008),
+                    end_address: Addr32(0x1020),
+                    handler_address: Addr32(1),
+                    jump_target: Addr32(0x1030),
+                }][..]
+            )
+        );
+        // Handler data with scopes outside the function is some other handler's.
+        assert_eq!(functions[1].unwind_info.as_ref().unwrap().handler, Some(Addr32(0x1100)));
+        assert_eq!(functions[1].scope_table(), None);
+    }
+
+    #[test]
+    fn pe32_images_have_no_runtime_functions() {
+        // The same table a PE32+ image would have decoded is ignored in a PE32 one.
+        let raw = pdata_image(PeBuilder::pe32());
+        let (_, file) = File::parse(&raw).unwrap();
+        assert_eq!(file.runtime_functions(), []);
+
+        let (_, file) = File::parse(&simple_image(vec![0xc3])).unwrap();
+        assert_eq!(file.runtime_functions(), []);
+    }
+}
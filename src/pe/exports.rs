@@ -0,0 +1,398 @@
+use super::util::*;
+use super::File;
+
+/// The `IMAGE_EXPORT_DIRECTORY` at the start of the export data directory.
+#[derive(Debug, PartialEq)]
+pub struct ExportDirectory {
+    pub time_date_stamp: u32,
+    pub major_version: u16,
+    pub minor_version: u16,
+    /// RVA of the DLL's own name.
+    pub name: Addr32,
+    /// The ordinal of the first entry in the export address table.
+    pub ordinal_base: u32,
+    pub number_of_functions: u32,
+    pub number_of_names: u32,
+    pub address_of_functions: Addr32,
+    /// RVA of the array of name RVAs, sorted for binary search by the loader.
+    pub address_of_names: Addr32,
+    pub address_of_name_ordinals: Addr32,
+}
+
+impl ExportDirectory {
+    fn parse(i: Input) -> Result<Self> {
+        use nom::{error::context, number::complete::*, sequence::tuple};
+        let (
+            i,
+            (
+                _characteristics,
+                time_date_stamp,
+                major_version,
+                minor_version,
+                name,
+                ordinal_base,
+                number_of_functions,
+                number_of_names,
+                address_of_functions,
+                address_of_names,
+                address_of_name_ordinals,
+            ),
+        ) = tuple((
+            context("Characteristics", le_u32),
+            context("TimeDateStamp", le_u32),
+            context("MajorVersion", le_u16),
+            context("MinorVersion", le_u16),
+            context("Name", Addr32::parse),
+            context("OrdinalBase", le_u32),
+            context("NumberOfFunctions", le_u32),
+            context("NumberOfNames", le_u32),
+            context("AddressOfFunctions", Addr32::parse),
+            context("AddressOfNames", Addr32::parse),
+            context("AddressOfNameOrdinals", Addr32::parse),
+        ))(i)?;
+        Ok((
+            i,
+            Self {
+                time_date_stamp,
+                major_version,
+                minor_version,
+                name,
+                ordinal_base,
+                number_of_functions,
+                number_of_names,
+                address_of_functions,
+                address_of_names,
+                address_of_name_ordinals,
+            },
+        ))
+    }
+}
+
+/// What a forwarded export's symbol is, in the DLL it's forwarded to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ForwardSymbol {
+    Name(String),
+    /// From a `DLL.#123` forwarder string.
+    Ordinal(u16),
+}
+
+/// An export that the loader resolves from another DLL instead, like
+/// `NTDLL.RtlAllocateHeap` for `kernel32!HeapAlloc`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForwardedExport {
+    /// Without the `.dll` extension, which forwarder strings leave out.
+    pub target_dll: String,
+    pub target_symbol: ForwardSymbol,
+}
+
+impl ForwardedExport {
+    /// Splits a forwarder string on its last `.`, as DLL names may contain dots too.
+    pub fn parse(forwarder: &str) -> Option<Self> {
+        let (target_dll, symbol) = forwarder.rsplit_once('.')?;
+        let target_symbol = match symbol.strip_prefix('#') {
+            Some(ordinal) => ForwardSymbol::Ordinal(ordinal.parse().ok()?),
+            None => ForwardSymbol::Name(symbol.to_string()),
+        };
+        Some(Self {
+            target_dll: target_dll.to_string(),
+            target_symbol,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportTarget {
+    Rva(Addr32),
+    Forwarded(ForwardedExport),
+}
+
+/// One entry of the export address table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    /// Already biased by the directory's `ordinal_base`.
+    pub ordinal: u32,
+    /// `None` for exports only reachable by ordinal.
+    pub name: Option<String>,
+    pub target: ExportTarget,
+}
+
+
+impl File {
+    /// Parses the header of the export directory, if the image has one.
+    pub fn export_directory(&self) -> Option<ExportDirectory> {
+        let dir = &self.header.optional_header.as_ref()?.data_directories.export_table;
+        let bytes = dir.resolve_into(self)?;
+        ExportDirectory::parse(&bytes).ok().map(|(_, exports)| exports)
+    }
+
+    /// `NumberOfFunctions` from the export directory, without reading any of
+    /// the tables. Unlike [`File::exports`] this counts unused ordinals too.
+    #[allow(dead_code)]
+    pub fn export_count(&self) -> usize {
+        self.export_directory()
+            .map_or(0, |exports| exports.number_of_functions as usize)
+    }
+
+    /// The ordinal of the first export address table entry, which
+    /// [`File::exports`] adds to each entry's index. `None` without an export
+    /// directory.
+    #[allow(dead_code)]
+    pub fn export_ordinal_base(&self) -> Option<u32> {
+        self.export_directory().map(|exports| exports.ordinal_base)
+    }
+
+    /// The names of the exported functions, in name-table order.
+    ///
+    /// Only the name pointer array is read, without matching names up with their
+    /// ordinals and addresses, which makes this cheap enough for bulk scanning.
+    #[allow(dead_code)]
+    pub fn export_names(&self) -> Vec<String> {
+        let exports = match self.export_directory() {
+            Some(exports) => exports,
+            None => return Vec::new(),
+        };
+        let pointers = match self.read_at_rva(
+            exports.address_of_names.0,
+            (exports.number_of_names as usize).saturating_mul(4),
+        ) {
+            Some(pointers) => pointers,
+            None => return Vec::new(),
+        };
+        pointers
+            .chunks_exact(4)
+            .filter_map(|rva| {
+                self.read_c_string_at_rva(u32::from_le_bytes([rva[0], rva[1], rva[2], rva[3]]))
+            })
+            .collect()
+    }
+
+    /// Parses every entry of the export address table, matched up with its name.
+    ///
+    /// Addresses pointing back into the export directory are forwarder strings,
+    /// which are returned as [`ExportTarget::Forwarded`]; unused (zero) slots
+    /// in the address table are skipped.
+    #[allow(dead_code)]
+    pub fn exports(&self) -> Vec<Export> {
+        let (dir, exports) = match (&self.header.optional_header, self.export_directory()) {
+            (Some(oh), Some(exports)) => (&oh.data_directories.export_table, exports),
+            _ => return Vec::new(),
+        };
+        let read_u32s = |rva: Addr32, n: u32| -> Vec<u32> {
+            self.read_at_rva(rva.0, (n as usize).saturating_mul(4))
+                .unwrap_or_default()
+                .chunks_exact(4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect()
+        };
+        let functions = read_u32s(exports.address_of_functions, exports.number_of_functions);
+        let names = read_u32s(exports.address_of_names, exports.number_of_names);
+        let ordinals: Vec<u16> = self
+            .read_at_rva(
+                exports.address_of_name_ordinals.0,
+                (exports.number_of_names as usize).saturating_mul(2),
+            )
+            .unwrap_or_default()
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect();
+
+        // The name slot of each address table entry, looked up once here as both
+        // counts come from the file: searching the ordinals per entry is quadratic.
+        let mut name_slots: Vec<Option<u32>> = vec![None; functions.len()];
+        for (n, &ordinal) in ordinals.iter().enumerate() {
+            if let Some(slot @ None) = name_slots.get_mut(ordinal as usize) {
+                *slot = Some(n as u32);
+            }
+        }
+
+        let forwarders = dir.virtual_addr.0..dir.virtual_addr.0.wrapping_add(dir.size);
+        functions
+            .iter()
+            .enumerate()
+            .filter(|&(_, &rva)| rva != 0)
+            .map(|(index, &rva)| {
+                let name = name_slots[index]
+                    .and_then(|n| names.get(n as usize))
+                    .and_then(|&name| self.read_c_string_at_rva(name));
+                let forwarded = forwarders
+                    .contains(&rva)
+                    .then(|| self.read_c_string_at_rva(rva))
+                    .flatten()
+                    .and_then(|s| ForwardedExport::parse(&s));
+                Export {
+                    ordinal: exports.ordinal_base.wrapping_add(index as u32),
+                    name,
+                    target: match forwarded {
+                        Some(forwarded) => ExportTarget::Forwarded(forwarded),
+                        None => ExportTarget::Rva(Addr32(rva)),
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::constants::DataDirectoryKind;
+    use super::super::fixtures::*;
+    use super::*;
+
+    /// Where the fixtures' `.edata` section is mapped.
+    const EDATA: u32 = 0x2000;
+
+    enum Target<'a> {
+        Rva(u32),
+        Forward(&'a str),
+    }
+
+    /// An image exporting `functions` from ordinal `ordinal_base` on, with
+    /// `names` giving the name of some by their index in `functions`.
+    fn export_image(ordinal_base: u32, functions: &[Target], names: &[(&str, u16)]) -> Vec<u8> {
+        let mut edata = vec![0; 0x28];
+        let string = |edata: &mut Vec<u8>, s: &str| {
+            let rva = EDATA + edata.len() as u32;
+            edata.extend_from_slice(s.as_bytes());
+            edata.push(0);
+            rva
+        };
+        let dll_name = string(&mut edata, "test.dll");
+        let function_rvas: Vec<u32> = functions
+            .iter()
+            .map(|f| match f {
+                Target::Rva(rva) => *rva,
+                Target::Forward(forwarder) => string(&mut edata, forwarder),
+            })
+            .collect();
+        let name_rvas: Vec<u32> = names.iter().map(|(name, _)| string(&mut edata, name)).collect();
+
+        let address_of_functions = EDATA + edata.len() as u32;
+        for rva in &function_rvas {
+            edata.extend_from_slice(&rva.to_le_bytes());
+        }
+        let address_of_names = EDATA + edata.len() as u32;
+        for rva in &name_rvas {
+            edata.extend_from_slice(&rva.to_le_bytes());
+        }
+        let address_of_name_ordinals = EDATA + edata.len() as u32;
+        for (_, index) in names {
+            edata.extend_from_slice(&index.to_le_bytes());
+        }
+
+        let mut dir = vec![0; 12];
+        dir.extend_from_slice(&dll_name.to_le_bytes());
+        for field in [
+            ordinal_base,
+            functions.len() as u32,
+            names.len() as u32,
+            address_of_functions,
+            address_of_names,
+            address_of_name_ordinals,
+        ] {
+            dir.extend_from_slice(&field.to_le_bytes());
+        }
+        edata[..0x28].copy_from_slice(&dir);
+
+        let size = edata.len() as u32;
+        PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x100], CODE)
+            .section(".edata", EDATA, edata, RDATA)
+            .directory(DataDirectoryKind::Export, EDATA, size)
+            .build()
+    }
+
+    #[test]
+    fn forwarders_to_an_ordinal_are_parsed() {
+        let raw = export_image(
+            1,
+            &[Target::Rva(0x1000), Target::Forward("NTDLL.#12"), Target::Forward("NTDLL.RtlFoo")],
+            &[("Local", 0), ("ByOrdinal", 1), ("ByName", 2)],
+        );
+        let (_, file) = File::parse(&raw).unwrap();
+        let exports = file.exports();
+
+        assert_eq!(exports.len(), 3);
+        assert_eq!(exports[0].target, ExportTarget::Rva(Addr32(0x1000)));
+        assert_eq!(exports[1].name.as_deref(), Some("ByOrdinal"));
+        assert_eq!(
+            exports[1].target,
+            ExportTarget::Forwarded(ForwardedExport {
+                target_dll: "NTDLL".to_string(),
+                target_symbol: ForwardSymbol::Ordinal(12),
+            })
+        );
+        assert_eq!(
+            ForwardedExport::parse("api-ms-win-core.1.RtlFoo").map(|f| f.target_dll),
+            Some("api-ms-win-core.1".to_string())
+        );
+    }
+
+    #[test]
+    fn names_are_matched_through_the_ordinal_table() {
+        // Names are sorted, so their order differs from the address table's.
+        let raw = export_image(
+            1,
+            &[Target::Rva(0x1000), Target::Rva(0), Target::Rva(0x1010), Target::Rva(0x1020)],
+            &[("Alpha", 3), ("Beta", 0)],
+        );
+        let (_, file) = File::parse(&raw).unwrap();
+        let exports = file.exports();
+
+        let summary: Vec<_> = exports.iter().map(|e| (e.ordinal, e.name.as_deref())).collect();
+        assert_eq!(summary, [(1, Some("Beta")), (3, None), (4, Some("Alpha"))]);
+    }
+
+    #[test]
+    fn export_names_has_one_name_per_name_table_entry() {
+        let raw = export_image(
+            1,
+            &[Target::Rva(0x1000), Target::Rva(0x1010), Target::Forward("NTDLL.RtlFoo")],
+            &[("Alpha", 2), ("Beta", 0)],
+        );
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let names = file.export_names();
+        assert_eq!(names.len(), file.export_directory().unwrap().number_of_names as usize);
+        assert_eq!(names, ["Alpha", "Beta"]);
+        assert_eq!(file.export_count(), 3);
+    }
+
+    #[test]
+    fn export_count_includes_unused_ordinals() {
+        let raw = export_image(
+            1,
+            &[Target::Rva(0x1000), Target::Rva(0), Target::Rva(0x1010)],
+            &[("Alpha", 0)],
+        );
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.exports().len(), 2);
+        let directory = file.export_directory().unwrap();
+        assert_eq!(file.export_count(), directory.number_of_functions as usize);
+        assert_eq!(file.export_count(), 3);
+
+        let (_, file) = File::parse(&simple_image(vec![0xc3])).unwrap();
+        assert_eq!(file.export_count(), 0);
+    }
+
+    #[test]
+    fn ordinals_start_at_the_ordinal_base() {
+        // The name table's ordinals index the address table, so they stay unbiased.
+        let raw = export_image(
+            100,
+            &[Target::Rva(0x1000), Target::Rva(0x1010), Target::Rva(0x1020)],
+            &[("First", 0), ("Last", 2)],
+        );
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.export_ordinal_base(), Some(100));
+        let summary: Vec<_> = file.exports().iter().map(|e| (e.ordinal, e.name.clone())).collect();
+        assert_eq!(
+            summary,
+            [(100, Some("First".to_string())), (101, None), (102, Some("Last".to_string()))]
+        );
+
+        let (_, file) = File::parse(&simple_image(vec![0xc3])).unwrap();
+        assert_eq!(file.export_ordinal_base(), None);
+    }
+}
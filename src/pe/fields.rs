@@ -0,0 +1,84 @@
+use super::File;
+
+/// The names accepted by [`File::field`], in the order the headers define them.
+pub const FIELD_NAMES: &[&str] = &[
+    "machine",
+    "number_of_sections",
+    "time_date_stamp",
+    "pointer_to_symbol_table",
+    "number_of_symbols",
+    "size_of_optional_header",
+    "characteristics",
+    "magic",
+    "linker_version",
+    "size_of_code",
+    "entry_point",
+    "base_of_code",
+    "base_of_data",
+    "image_base",
+    "section_alignment",
+    "file_alignment",
+    "os_version",
+    "image_version",
+    "subsystem_version",
+    "size_of_image",
+    "size_of_headers",
+    "checksum",
+    "subsystem",
+    "dll_characteristics",
+    "size_of_stack_reserve",
+    "size_of_heap_reserve",
+    "number_of_rva_and_sizes",
+];
+
+impl File {
+    /// A single header field formatted for scripts, by its name in [`FIELD_NAMES`].
+    ///
+    /// `None` for unknown names, for optional header fields of object files,
+    /// and for `base_of_data` in PE32+ images.
+    pub fn field(&self, name: &str) -> Option<String> {
+        let header = &self.header;
+        let coff = match name {
+            "machine" => Some(format!("{:?}", header.machine)),
+            "number_of_sections" => Some(header.number_of_sections.to_string()),
+            "time_date_stamp" => Some(format!("{:#x}", header.time_date_stamp)),
+            "pointer_to_symbol_table" => Some(format!("{:#x}", header.pointer_to_sym_table.0)),
+            "number_of_symbols" => Some(header.number_of_symbols.to_string()),
+            "size_of_optional_header" => Some(header.size_of_optional_header.to_string()),
+            "characteristics" => Some(format!("{:?}", header.characteristics)),
+            _ => None,
+        };
+        if coff.is_some() {
+            return coff;
+        }
+
+        let oh = header.optional_header.as_ref()?;
+        let wh = &oh.windows_header;
+        let value = match name {
+            "magic" => format!("{:#x}", oh.magic),
+            "linker_version" => format!("{}.{}", oh.major_linker_version, oh.minor_linker_version),
+            "size_of_code" => format!("{:#x}", oh.size_of_code),
+            "entry_point" => format!("{:#x}", oh.entry_point.0),
+            "base_of_code" => format!("{:#x}", oh.base_of_code),
+            "base_of_data" => format!("{:#x}", oh.base_of_data?),
+            "image_base" => format!("{:#x}", wh.image_base),
+            "section_alignment" => format!("{:#x}", wh.section_alignment),
+            "file_alignment" => format!("{:#x}", wh.file_alignment),
+            "os_version" => format!("{}.{}", wh.major_os_version, wh.minor_os_version),
+            "image_version" => format!("{}.{}", wh.major_image_version, wh.minor_image_version),
+            "subsystem_version" => {
+                format!("{}.{}", wh.major_subsystem_version, wh.minor_subsystem_version)
+            }
+            "size_of_image" => format!("{:#x}", wh.size_of_image),
+            "size_of_headers" => format!("{:#x}", wh.size_of_headers),
+            "checksum" => format!("{:#x}", wh.checksum),
+            "subsystem" => format!("{:?}", wh.subsystem),
+            "dll_characteristics" => format!("{:?}", wh.dll_characteristics),
+            "size_of_stack_reserve" => format!("{:#x}", wh.size_of_stack_reserve),
+            "size_of_heap_reserve" => format!("{:#x}", wh.size_of_heap_reserve),
+            "number_of_rva_and_sizes" => wh.number_of_rva_and_sizes.to_string(),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
@@ -0,0 +1,91 @@
+use super::header::{Machine, Subsystem};
+use super::File;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// The build traits most likely to be shared by samples of the same family,
+/// bundled up so two files can be compared (or grouped) in one go.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    pub imphash: Option<String>,
+    pub rich_hash: Option<String>,
+    pub machine: Machine,
+    /// `None` for object files.
+    pub subsystem: Option<Subsystem>,
+    pub section_names: BTreeSet<String>,
+}
+
+impl fmt::Display for Fingerprint {
+    /// A short form for listings: the hashes are cut down to 8 digits.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let short = |hash: &Option<String>| match hash {
+            Some(hash) => hash[..8.min(hash.len())].to_string(),
+            None => "-".to_string(),
+        };
+        let subsystem = match self.subsystem {
+            Some(subsystem) => format!("{:?}", subsystem),
+            None => "-".to_string(),
+        };
+        let sections: Vec<&str> = self.section_names.iter().map(String::as_str).collect();
+        write!(
+            f,
+            "{:?}/{} imp:{} rich:{} [{}]",
+            self.machine,
+            subsystem,
+            short(&self.imphash),
+            short(&self.rich_hash),
+            sections.join(",")
+        )
+    }
+}
+
+impl File {
+    #[allow(dead_code)]
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint {
+            imphash: self.imphash(),
+            rich_hash: self.rich_hash(),
+            machine: self.header.machine,
+            subsystem: self
+                .header
+                .optional_header
+                .as_ref()
+                .map(|oh| oh.windows_header.subsystem),
+            section_names: self.sections.iter().map(|s| s.name.to_string()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::*;
+    use super::*;
+    use std::collections::HashSet;
+
+    fn sample(data_name: &str) -> Vec<u8> {
+        PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x10], CODE)
+            .section(data_name, 0x2000, vec![0; 0x10], DATA)
+            .imports(0x3000, &["KERNEL32.dll"], &[BY_ORDINAL | 7], false)
+            .build()
+    }
+
+    #[test]
+    fn copies_of_a_file_have_equal_fingerprints() {
+        let (original, copy) = (sample(".data"), sample(".data"));
+        let (_, original) = File::parse(&original).unwrap();
+        let (_, copy) = File::parse(&copy).unwrap();
+
+        let fingerprint = original.fingerprint();
+        assert_eq!(fingerprint, copy.fingerprint());
+        assert!(fingerprint.imphash.is_some());
+        assert_eq!(fingerprint.rich_hash, None);
+        let set: HashSet<Fingerprint> =
+            vec![fingerprint.clone(), copy.fingerprint()].into_iter().collect();
+        assert_eq!(set.len(), 1);
+        assert!(fingerprint.to_string().ends_with("rich:- [.data,.idata,.text]"));
+
+        let (_, renamed) = File::parse(&sample(".bss")).unwrap();
+        assert_ne!(renamed.fingerprint(), fingerprint);
+    }
+}
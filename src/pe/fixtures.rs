@@ -0,0 +1,450 @@
+//! Small synthetic images for the unit tests, laid out the way a linker would:
+//! headers in the first `0x400` bytes, then each section's raw data in order.
+#![allow(dead_code)]
+
+use super::constants::DataDirectoryKind;
+use super::sections::Characteristics as SectionCharacteristics;
+
+pub const CODE: SectionCharacteristics = SectionCharacteristics::from_bits_truncate(
+    SectionCharacteristics::IMAGE_SCN_CNT_CODE.bits()
+        | SectionCharacteristics::EXECUTE.bits()
+        | SectionCharacteristics::READ.bits(),
+);
+pub const RDATA: SectionCharacteristics = SectionCharacteristics::READ;
+pub const DATA: SectionCharacteristics = SectionCharacteristics::from_bits_truncate(
+    SectionCharacteristics::READ.bits() | SectionCharacteristics::WRITE.bits(),
+);
+
+const DOS_HEADER_SIZE: usize = 0x40;
+const HEADERS_SIZE: u32 = 0x400;
+
+pub struct FixtureSection {
+    /// The raw 8-byte name field.
+    pub name: [u8; 8],
+    pub virtual_address: u32,
+    pub virtual_size: u32,
+    pub data: Vec<u8>,
+    /// Where the raw data goes, after the previous section's if `None`.
+    pub pointer_to_raw_data: Option<u32>,
+    pub characteristics: SectionCharacteristics,
+}
+
+/// Builds a PE32+ (or with `pe32`, PE32) image. Every field is public so tests
+/// can set just what they're about.
+pub struct PeBuilder {
+    pub pe32: bool,
+    pub machine: u16,
+    pub time_date_stamp: u32,
+    pub characteristics: u16,
+    pub entry_point: u32,
+    pub image_base: u64,
+    pub section_alignment: u32,
+    pub file_alignment: u32,
+    pub major_subsystem_version: u16,
+    pub minor_subsystem_version: u16,
+    pub win32_version_value: u32,
+    pub checksum: u32,
+    pub subsystem: u16,
+    pub dll_characteristics: u16,
+    pub loader_flags: u32,
+    pub number_of_rva_and_sizes: u32,
+    /// Defaults to exactly what the declared directories need.
+    pub size_of_optional_header: Option<u16>,
+    /// Entries past the standard 16 are written too.
+    pub directories: Vec<(u32, u32)>,
+    pub sections: Vec<FixtureSection>,
+    /// Appended after the last section's raw data.
+    pub overlay: Vec<u8>,
+    /// Between the DOS header and the PE signature, where a Rich header goes.
+    pub dos_stub: Vec<u8>,
+}
+
+impl Default for PeBuilder {
+    fn default() -> Self {
+        Self {
+            pe32: false,
+            machine: 0x8664,
+            time_date_stamp: 0x5f00_0000,
+            // IMAGE_FILE_EXECUTABLE_IMAGE | IMAGE_FILE_LARGE_ADDRESS_AWARE
+            characteristics: 0x22,
+            entry_point: 0,
+            image_base: 0x1_4000_0000,
+            section_alignment: 0x1000,
+            file_alignment: 0x200,
+            major_subsystem_version: 6,
+            minor_subsystem_version: 0,
+            win32_version_value: 0,
+            checksum: 0,
+            // WindowsCui
+            subsystem: 3,
+            dll_characteristics: 0,
+            loader_flags: 0,
+            number_of_rva_and_sizes: 16,
+            size_of_optional_header: None,
+            directories: vec![(0, 0); 16],
+            sections: Vec::new(),
+            overlay: Vec::new(),
+            dos_stub: Vec::new(),
+        }
+    }
+}
+
+pub fn name(name: &str) -> [u8; 8] {
+    let mut raw = [0; 8];
+    raw[..name.len()].copy_from_slice(name.as_bytes());
+    raw
+}
+
+impl PeBuilder {
+    /// A PE32 image for an I386 machine.
+    pub fn pe32() -> Self {
+        Self {
+            pe32: true,
+            machine: 0x14c,
+            // IMAGE_FILE_EXECUTABLE_IMAGE | IMAGE_FILE_32BIT_MACHINE
+            characteristics: 0x102,
+            image_base: 0x40_0000,
+            ..Self::default()
+        }
+    }
+
+    /// Adds a section whose virtual size is the length of `data`.
+    pub fn section(
+        mut self,
+        section_name: &str,
+        virtual_address: u32,
+        data: Vec<u8>,
+        characteristics: SectionCharacteristics,
+    ) -> Self {
+        self.sections.push(FixtureSection {
+            name: name(section_name),
+            virtual_address,
+            virtual_size: data.len() as u32,
+            data,
+            pointer_to_raw_data: None,
+            characteristics,
+        });
+        self
+    }
+
+    /// Points data directory `kind` at `rva`.
+    pub fn directory(mut self, kind: DataDirectoryKind, rva: u32, size: u32) -> Self {
+        self.directories[kind.index()] = (rva, size);
+        self
+    }
+
+    /// Adds an `.idata` section at `rva` importing from `dlls` by name, each
+    /// DLL with its own lookup table of `thunks`, or all sharing the first
+    /// DLL's table if `shared`.
+    ///
+    /// `thunks` are written 4 bytes wide in PE32 images, [`BY_ORDINAL`] moving
+    /// down to bit 31.
+    pub fn imports(self, rva: u32, dlls: &[&str], thunks: &[u64], shared: bool) -> Self {
+        const DESCRIPTOR_SIZE: usize = 20;
+        let descriptors = DESCRIPTOR_SIZE * (dlls.len() + 1);
+        let mut idata = vec![0; descriptors];
+        let mut tables = Vec::new();
+        for (n, dll) in dlls.iter().enumerate() {
+            let name = rva + idata.len() as u32;
+            idata.extend_from_slice(dll.as_bytes());
+            idata.push(0);
+            let table = match tables.first() {
+                Some(&first) if shared => first,
+                _ => {
+                    let table = rva + idata.len() as u32;
+                    for &thunk in thunks.iter().chain(&[0]) {
+                        if self.pe32 {
+                            let flag = if thunk & BY_ORDINAL != 0 { 1 << 31 } else { 0 };
+                            idata.extend_from_slice(&(flag | thunk as u32).to_le_bytes());
+                        } else {
+                            idata.extend_from_slice(&thunk.to_le_bytes());
+                        }
+                    }
+                    table
+                }
+            };
+            tables.push(table);
+            put(&mut idata, n * DESCRIPTOR_SIZE, &table.to_le_bytes());
+            put(&mut idata, n * DESCRIPTOR_SIZE + 12, &name.to_le_bytes());
+            put(&mut idata, n * DESCRIPTOR_SIZE + 16, &table.to_le_bytes());
+        }
+        self.section(".idata", rva, idata, RDATA)
+            .directory(DataDirectoryKind::Import, rva, descriptors as u32)
+    }
+
+    /// Adds an `.rsrc` section at `rva` holding `tree`, the root directory.
+    pub fn resources(self, rva: u32, tree: &[(ResId, Res)]) -> Self {
+        let rsrc = resource_section(rva, tree);
+        let size = rsrc.len() as u32;
+        self.section(".rsrc", rva, rsrc, RDATA)
+            .directory(DataDirectoryKind::Resource, rva, size)
+    }
+
+    pub fn entry_point(mut self, rva: u32) -> Self {
+        self.entry_point = rva;
+        self
+    }
+
+    /// Where the PE signature starts.
+    pub fn e_lfanew(&self) -> usize {
+        DOS_HEADER_SIZE + align(self.dos_stub.len() as u32, 8) as usize
+    }
+
+    /// Where the COFF header's fields start.
+    pub fn coff_offset(&self) -> usize {
+        self.e_lfanew() + 4
+    }
+
+    pub fn optional_header_offset(&self) -> usize {
+        self.coff_offset() + 20
+    }
+
+    pub fn section_table_offset(&self) -> usize {
+        self.optional_header_offset() + self.optional_header_size() as usize
+    }
+
+    fn optional_header_size(&self) -> u16 {
+        let fixed = if self.pe32 { 96 } else { 112 };
+        self.size_of_optional_header
+            .unwrap_or(fixed + 8 * self.number_of_rva_and_sizes as u16)
+    }
+
+    /// Where each section's raw data is placed.
+    pub fn raw_pointers(&self) -> Vec<u32> {
+        let mut next = HEADERS_SIZE;
+        self.sections
+            .iter()
+            .map(|s| {
+                let at = s.pointer_to_raw_data.unwrap_or(next);
+                let end = at + s.data.len() as u32;
+                next = next.max(align(end, self.file_alignment));
+                at
+            })
+            .collect()
+    }
+
+    /// Where the overlay starts: after the last section's raw data, file-aligned.
+    pub fn overlay_offset(&self) -> u32 {
+        let end = self
+            .sections
+            .iter()
+            .zip(self.raw_pointers())
+            .map(|(s, at)| at + s.data.len() as u32)
+            .fold(HEADERS_SIZE, u32::max);
+        align(end, self.file_alignment)
+    }
+
+    /// Appends a `WIN_CERTIFICATE` holding `blob` (as if PKCS#7) to the overlay
+    /// and points the certificate directory at it. Add sections first.
+    pub fn certificate(mut self, blob: &[u8]) -> Self {
+        let at = self.overlay_offset() + self.overlay.len() as u32;
+        let mut certificate = (8 + blob.len() as u32).to_le_bytes().to_vec();
+        // WIN_CERT_REVISION_2_0, WIN_CERT_TYPE_PKCS_SIGNED_DATA
+        certificate.extend_from_slice(&[0, 2, 2, 0]);
+        certificate.extend_from_slice(blob);
+        certificate.resize(align(certificate.len() as u32, 8) as usize, 0);
+        let size = certificate.len() as u32;
+        self.overlay.extend(certificate);
+        self.directory(DataDirectoryKind::Certificate, at, size)
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut out = vec![0; HEADERS_SIZE as usize];
+        out[..2].copy_from_slice(b"MZ");
+        put(&mut out, 0x3c, &(self.e_lfanew() as u32).to_le_bytes());
+        put(&mut out, DOS_HEADER_SIZE, &self.dos_stub);
+        put(&mut out, self.e_lfanew(), b"PE\0\0");
+
+        let mut coff = Vec::new();
+        coff.extend_from_slice(&self.machine.to_le_bytes());
+        coff.extend_from_slice(&(self.sections.len() as u16).to_le_bytes());
+        coff.extend_from_slice(&self.time_date_stamp.to_le_bytes());
+        coff.extend_from_slice(&[0; 8]);
+        coff.extend_from_slice(&self.optional_header_size().to_le_bytes());
+        coff.extend_from_slice(&self.characteristics.to_le_bytes());
+        put(&mut out, self.coff_offset(), &coff);
+
+        let size_of_image = self
+            .sections
+            .iter()
+            .map(|s| align(s.virtual_address.saturating_add(s.virtual_size), self.section_alignment))
+            .max()
+            .unwrap_or(self.section_alignment);
+        let mut oh = Vec::new();
+        let magic: u16 = if self.pe32 { 0x10b } else { 0x20b };
+        oh.extend_from_slice(&magic.to_le_bytes());
+        oh.extend_from_slice(&[14, 0]);
+        oh.extend_from_slice(&[0; 12]);
+        oh.extend_from_slice(&self.entry_point.to_le_bytes());
+        oh.extend_from_slice(&0x1000_u32.to_le_bytes());
+        let word = |oh: &mut Vec<u8>, n: u64| {
+            if self.pe32 {
+                oh.extend_from_slice(&(n as u32).to_le_bytes());
+            } else {
+                oh.extend_from_slice(&n.to_le_bytes());
+            }
+        };
+        if self.pe32 {
+            // BaseOfData
+            oh.extend_from_slice(&0x2000_u32.to_le_bytes());
+        }
+        word(&mut oh, self.image_base);
+        oh.extend_from_slice(&self.section_alignment.to_le_bytes());
+        oh.extend_from_slice(&self.file_alignment.to_le_bytes());
+        for version in [6_u16, 0, 0, 0, self.major_subsystem_version, self.minor_subsystem_version] {
+            oh.extend_from_slice(&version.to_le_bytes());
+        }
+        oh.extend_from_slice(&self.win32_version_value.to_le_bytes());
+        oh.extend_from_slice(&size_of_image.to_le_bytes());
+        oh.extend_from_slice(&HEADERS_SIZE.to_le_bytes());
+        oh.extend_from_slice(&self.checksum.to_le_bytes());
+        oh.extend_from_slice(&self.subsystem.to_le_bytes());
+        oh.extend_from_slice(&self.dll_characteristics.to_le_bytes());
+        for size in [0x10_0000, 0x1000, 0x10_0000, 0x1000] {
+            word(&mut oh, size);
+        }
+        oh.extend_from_slice(&self.loader_flags.to_le_bytes());
+        oh.extend_from_slice(&self.number_of_rva_and_sizes.to_le_bytes());
+        for n in 0..self.number_of_rva_and_sizes as usize {
+            let (rva, size) = self.directories.get(n).copied().unwrap_or((0, 0));
+            oh.extend_from_slice(&rva.to_le_bytes());
+            oh.extend_from_slice(&size.to_le_bytes());
+        }
+        oh.resize(self.optional_header_size() as usize, 0);
+        put(&mut out, self.optional_header_offset(), &oh);
+
+        let pointers = self.raw_pointers();
+        for (n, (section, &pointer)) in self.sections.iter().zip(&pointers).enumerate() {
+            let mut entry = section.name.to_vec();
+            entry.extend_from_slice(&section.virtual_size.to_le_bytes());
+            entry.extend_from_slice(&section.virtual_address.to_le_bytes());
+            entry.extend_from_slice(&(section.data.len() as u32).to_le_bytes());
+            let pointer = if section.data.is_empty() { 0 } else { pointer };
+            entry.extend_from_slice(&pointer.to_le_bytes());
+            entry.extend_from_slice(&[0; 12]);
+            entry.extend_from_slice(&section.characteristics.bits().to_le_bytes());
+            put(&mut out, self.section_table_offset() + n * 40, &entry);
+        }
+        for (section, &pointer) in self.sections.iter().zip(&pointers) {
+            put(&mut out, pointer as usize, &section.data);
+        }
+        let end = align(out.len() as u32, self.file_alignment) as usize;
+        out.resize(end, 0);
+        out.extend_from_slice(&self.overlay);
+        out
+    }
+}
+
+/// Copies `bytes` into `out` at `at`, growing `out` if needed.
+pub fn put(out: &mut Vec<u8>, at: usize, bytes: &[u8]) {
+    if out.len() < at + bytes.len() {
+        out.resize(at + bytes.len(), 0);
+    }
+    out[at..at + bytes.len()].copy_from_slice(bytes);
+}
+
+fn align(value: u32, alignment: u32) -> u32 {
+    (u64::from(value).div_ceil(u64::from(alignment)) * u64::from(alignment)) as u32
+}
+
+/// How a [`Res`] is identified in its directory.
+pub enum ResId {
+    Id(u16),
+    Name(&'static str),
+}
+
+/// A resource directory or leaf, for [`PeBuilder::resources`].
+pub enum Res {
+    Dir(Vec<(ResId, Res)>),
+    Data(Vec<u8>),
+}
+
+/// Lays out a resource tree the way `rc` does, as it would be mapped at `rva`:
+/// each directory's subdirectories after it, with strings, data entries and the
+/// data itself placed as they come. Named entries should come first.
+pub fn resource_section(rva: u32, tree: &[(ResId, Res)]) -> Vec<u8> {
+    fn directory(out: &mut Vec<u8>, rva: u32, entries: &[(ResId, Res)]) -> u32 {
+        let at = out.len();
+        let named = entries.iter().filter(|(id, _)| matches!(id, ResId::Name(_))).count();
+        out.resize(at + 16 + 8 * entries.len(), 0);
+        put(out, at + 12, &(named as u16).to_le_bytes());
+        put(out, at + 14, &((entries.len() - named) as u16).to_le_bytes());
+        for (n, (id, node)) in entries.iter().enumerate() {
+            let name = match id {
+                ResId::Id(id) => u32::from(*id),
+                ResId::Name(name) => {
+                    let offset = out.len() as u32;
+                    let units: Vec<u16> = name.encode_utf16().collect();
+                    out.extend_from_slice(&(units.len() as u16).to_le_bytes());
+                    for unit in units {
+                        out.extend_from_slice(&unit.to_le_bytes());
+                    }
+                    out.resize(align(out.len() as u32, 4) as usize, 0);
+                    0x8000_0000 | offset
+                }
+            };
+            let target = match node {
+                Res::Dir(children) => 0x8000_0000 | directory(out, rva, children),
+                Res::Data(data) => {
+                    let entry = out.len() as u32;
+                    let data_rva = rva + entry + 16;
+                    for field in [data_rva, data.len() as u32, 0, 0] {
+                        out.extend_from_slice(&field.to_le_bytes());
+                    }
+                    out.extend_from_slice(data);
+                    out.resize(align(out.len() as u32, 4) as usize, 0);
+                    entry
+                }
+            };
+            put(out, at + 16 + 8 * n, &name.to_le_bytes());
+            put(out, at + 20 + 8 * n, &target.to_le_bytes());
+        }
+        at as u32
+    }
+
+    let mut out = Vec::new();
+    directory(&mut out, rva, tree);
+    out
+}
+
+/// A single-language resource of type `kind` with id `id`.
+pub fn resource(kind: u16, id: u16, data: Vec<u8>) -> (ResId, Res) {
+    (
+        ResId::Id(kind),
+        Res::Dir(vec![(ResId::Id(id), Res::Dir(vec![(ResId::Id(0x409), Res::Data(data))]))]),
+    )
+}
+
+/// Set in a 64-bit lookup table entry that imports by ordinal.
+pub const BY_ORDINAL: u64 = 1 << 63;
+
+/// A 64-bit image with a single code section at `0x1000`, holding `code`.
+pub fn simple_image(code: Vec<u8>) -> Vec<u8> {
+    PeBuilder::default()
+        .section(".text", 0x1000, code, CODE)
+        .entry_point(0x1000)
+        .build()
+}
+
+/// A COFF object file for `machine`, with `sections` given as name, raw data
+/// and characteristics, and no symbols.
+pub fn object(machine: u16, sections: &[(&str, &[u8], SectionCharacteristics)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&machine.to_le_bytes());
+    out.extend_from_slice(&(sections.len() as u16).to_le_bytes());
+    out.extend_from_slice(&[0; 16]);
+    let mut pointer = 20 + 40 * sections.len() as u32;
+    for (section_name, data, characteristics) in sections {
+        out.extend_from_slice(&name(section_name));
+        out.extend_from_slice(&[0; 8]);
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(if data.is_empty() { 0 } else { pointer }).to_le_bytes());
+        out.extend_from_slice(&[0; 12]);
+        out.extend_from_slice(&characteristics.bits().to_le_bytes());
+        pointer += data.len() as u32;
+    }
+    for (_, data, _) in sections {
+        out.extend_from_slice(data);
+    }
+    out
+}
@@ -1,14 +1,39 @@
 ﻿use super::util::*;
+use super::constants::DataDirectoryKind;
+use super::File;
 use bitflags::*;
+use nom::number::Endianness;
 use std::convert::TryFrom;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u16)]
 pub enum Machine {
     Unknown = 0,
     AMD64 = 0x8664,
     IA64 = 0x200,
     I386 = 0x14C,
+    /// MIPS R3000, big-endian.
+    R3000BE = 0x160,
+    /// PowerPC, big-endian.
+    PowerPCBE = 0x1F2,
+}
+
+impl Machine {
+    /// Object files for these machines store the COFF headers big-endian,
+    /// machine field included.
+    pub fn is_big_endian(self) -> bool {
+        matches!(self, Self::R3000BE | Self::PowerPCBE)
+    }
+
+    /// The size of an address on this machine, in bytes.
+    pub fn pointer_size(self) -> usize {
+        match self {
+            Self::AMD64 | Self::IA64 => 8,
+            Self::Unknown | Self::I386 | Self::R3000BE | Self::PowerPCBE => 4,
+        }
+    }
 }
 
 impl TryFrom<u16> for Machine {
@@ -20,14 +45,17 @@ impl TryFrom<u16> for Machine {
             0x8664 => Ok(Self::AMD64),
             0x200 => Ok(Self::IA64),
             0x14C => Ok(Self::I386),
+            0x160 => Ok(Self::R3000BE),
+            0x1F2 => Ok(Self::PowerPCBE),
             _ => Err(n),
         }
     }
 }
 
-impl_parse_for_enum!(Machine, le_u16);
+impl_parse_for_enum!(endian Machine, u16);
+impl_from_str_for_enum!(Machine, [Unknown, AMD64, IA64, I386, R3000BE, PowerPCBE]);
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u16)]
 pub enum Subsystem {
     Unknown = 0,
@@ -81,12 +109,31 @@ impl TryFrom<u16> for Subsystem {
 }
 
 impl_parse_for_enum!(Subsystem, le_u16);
-
-#[derive(PartialEq, Debug)]
+impl_from_str_for_enum!(
+    Subsystem,
+    [
+        Unknown,
+        Native,
+        WindowsGui,
+        WindowsCui,
+        Os2Cui,
+        PosixCui,
+        NativeWindows,
+        WindowsCeGui,
+        EfiApplication,
+        EfiBootServiceDriver,
+        EfiRuntimeDriver,
+        EfiRom,
+        Xbox,
+        WindowsBootApplication,
+    ]
+);
+
+#[derive(PartialEq, Debug, Default)]
 #[repr(C)]
 pub struct DataDirectory {
-    virtual_addr: Addr32,
-    size: u32,
+    pub virtual_addr: Addr32,
+    pub size: u32,
 }
 
 impl DataDirectory {
@@ -98,6 +145,28 @@ impl DataDirectory {
         ))(i)?;
         Ok((i, Self { virtual_addr, size }))
     }
+
+    /// An all-zero entry means the directory is absent from the image.
+    pub fn is_present(&self) -> bool {
+        self.virtual_addr.0 != 0 && self.size != 0
+    }
+
+    /// The bytes this directory points at in `file`, or `None` if it's absent
+    /// or doesn't resolve to a section.
+    ///
+    /// Not for the certificate table, whose address is a file offset rather than an RVA.
+    pub fn resolve_into(&self, file: &File) -> Option<Vec<u8>> {
+        if !self.is_present() {
+            return None;
+        }
+        file.read_at_rva(self.virtual_addr.0, self.size as usize)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.virtual_addr.0.to_le_bytes().to_vec();
+        out.extend_from_slice(&self.size.to_le_bytes());
+        out
+    }
 }
 
 bitflags! {
@@ -136,9 +205,36 @@ bitflags! {
         }
 }
 
-impl_parse_for_enumflags!(Characteristics, le_u16);
+impl_parse_for_enumflags!(endian Characteristics, u16);
+
+impl Characteristics {
+    /// A short phrase naming the flags that matter when triaging a file,
+    /// e.g. `Executable, DLL, Large-address-aware`. Deprecated flags are left out.
+    pub fn describe(&self) -> String {
+        let phrases = [
+            (Self::IMAGE_FILE_EXECUTABLE_IMAGE, "Executable"),
+            (Self::IMAGE_FILE_DLL, "DLL"),
+            (Self::IMAGE_FILE_SYSTEM, "System"),
+            (Self::IMAGE_FILE_LARGE_ADDRESS_AWARE, "Large-address-aware"),
+            (Self::IMAGE_FILE_32BIT_MACHINE, "32-bit"),
+            (Self::IMAGE_FILE_RELOCS_STRIPPED, "Relocs-stripped"),
+            (Self::IMAGE_FILE_DEBUG_STRIPPED, "Debug-stripped"),
+            (Self::IMAGE_FILE_UP_SYSTEM_ONLY, "Uniprocessor-only"),
+        ];
+        let set: Vec<&str> = phrases
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|&(_, phrase)| phrase)
+            .collect();
+        if set.is_empty() {
+            "-".to_string()
+        } else {
+            set.join(", ")
+        }
+    }
+}
 
-#[derive(Debug)]
+#[derive(PartialEq, Debug)]
 pub struct PeHeader64 {
     /// the architecture of the machine, `0x8664`
     /// is AMD64 and `0x14C0` is i386.
@@ -168,22 +264,51 @@ pub struct PeHeader64 {
     pub characteristics: Characteristics,
 
     /// The optional header!
-    pub optional_header: OptionalHeader64,
+    /// Absent in object files, where `size_of_optional_header` is zero.
+    pub optional_header: Option<OptionalHeader64>,
+
+    /// The byte order of the COFF and section headers, which is only ever
+    /// big-endian for objects of a [big-endian machine](Machine::is_big_endian).
+    /// Serializing always writes little-endian.
+    pub endianness: Endianness,
+
+    /// The file offset of the COFF header, just past the `PE\0\0` signature,
+    /// or zero in object files, which start with it.
+    pub coff_offset: usize,
 }
 
+/// The MS-DOS header, up to and including `e_lfanew`.
+pub(super) const DOS_HEADER_SIZE: usize = 0x40;
+/// The COFF file header, without the optional header that follows it.
+const COFF_HEADER_SIZE: usize = 20;
+
+/// Context of the failure when `e_lfanew` doesn't lead to a `PE\0\0` signature,
+/// turned into [`ParseErrorKind::PeSignatureNotFound`](super::ParseErrorKind).
+pub(super) const PE_SIGNATURE_NOT_FOUND: &str = "PeSignatureNotFound";
+/// Context of the failure when the input ends inside a header, turned into
+/// [`ParseErrorKind::TooSmall`](super::ParseErrorKind).
+pub(super) const TOO_SMALL: &str = "TooSmall";
+
 impl PeHeader64 {
     const MAGIC: &'static [u8] = &[0x50, 0x45, 0x00, 0x00];
 
     fn parse_from_pe_header(i: Input) -> Result<Self> {
-        use nom::{bytes::complete::tag, error::context, number::complete::*, sequence::tuple};
+        use nom::{bytes::complete::tag, error::context};
+        let (i, _) = context("Magic", tag(Self::MAGIC))(i)?;
+        Self::parse_coff_header(i, Endianness::Little)
+    }
 
-        // _tODO: parse all the below fields into u16, u64's etc.MAGIC
-        // Do this via the blog post.
+    /// Parses the COFF file header, which in an object file is the very first thing in it.
+    fn parse_coff_header(i: Input, endianness: Endianness) -> Result<Self> {
+        use nom::{
+            error::context,
+            number::complete::{u16, u32},
+            sequence::tuple,
+        };
 
         let (
             i,
             (
-                _,
                 machine,
                 number_of_sections,
                 time_date_stamp,
@@ -191,19 +316,28 @@ impl PeHeader64 {
                 number_of_symbols,
                 size_of_optional_header,
                 characteristics,
-                optional_header,
             ),
         ) = tuple((
-            context("Magic", tag(Self::MAGIC)),
-            context("Machine", Machine::parse),
-            context("NumberOfSections", le_u16),
-            context("TimeDateStamp", le_u32),
-            context("PointerToSymbolTable", Addr32::parse),
-            context("NumberOfSymbols", le_u32),
-            context("SizeOfOptionalHeader", le_u16),
-            context("Characteristics", Characteristics::parse),
-            context("OptionalHeader", OptionalHeader64::parse),
+            context("Machine", Machine::parse_endian(endianness)),
+            context("NumberOfSections", u16(endianness)),
+            context("TimeDateStamp", u32(endianness)),
+            context("PointerToSymbolTable", Addr32::parse_endian(endianness)),
+            context("NumberOfSymbols", u32(endianness)),
+            context("SizeOfOptionalHeader", u16(endianness)),
+            context("Characteristics", Characteristics::parse_endian(endianness)),
         ))(i)?;
+
+        // Object files have no optional header, so the section table follows directly.
+        let (i, optional_header) = match size_of_optional_header {
+            0 => (i, None),
+            _ => {
+                let (i, oh) = context("OptionalHeader", |i| {
+                    OptionalHeader64::parse(i, size_of_optional_header)
+                })(i)?;
+                (i, Some(oh))
+            }
+        };
+
         Ok((
             i,
             Self {
@@ -215,20 +349,103 @@ impl PeHeader64 {
                 size_of_optional_header,
                 characteristics,
                 optional_header,
+                endianness,
+                coff_offset: 0,
             },
         ))
     }
 
     /// _this parses assuming it has to skip over the MS-DOS header, and begins
     /// parsing at the offset contained in `0x3C`.
-    pub fn parse(i: Input) -> Result<Self> {
-        let offset = i[0x3c] as usize;
-        Self::parse_from_pe_header(&i[offset..])
+    ///
+    /// Input without the `MZ` signature is treated as a COFF object file instead.
+    pub fn parse<'a>(i: Input<'a>) -> Result<'a, Self> {
+        use nom::{
+            bytes::complete::take,
+            error::{context, ContextError, ErrorKind, ParseError, VerboseError},
+            number::complete::le_u32,
+            sequence::preceded,
+        };
+        let failure = |at: Input<'a>, kind, ctx| {
+            let err = VerboseError::from_error_kind(at, kind);
+            nom::Err::Failure(VerboseError::add_context(at, ctx, err))
+        };
+
+        if !i.starts_with(b"MZ") {
+            if i.len() < COFF_HEADER_SIZE {
+                return Err(failure(i, ErrorKind::Eof, TOO_SMALL));
+            }
+            // A little-endian machine field never reads as a big-endian machine
+            // when byte-swapped, so this can't misfire.
+            let big_endian = match i {
+                [hi, lo, ..] => Machine::try_from(u16::from_be_bytes([*hi, *lo]))
+                    .is_ok_and(Machine::is_big_endian),
+                _ => false,
+            };
+            let endianness = if big_endian { Endianness::Big } else { Endianness::Little };
+            return Self::parse_coff_header(i, endianness);
+        }
+        if i.len() < DOS_HEADER_SIZE {
+            return Err(failure(i, ErrorKind::Eof, TOO_SMALL));
+        }
+        let (_, offset) = context("e_lfanew", preceded(take(0x3c_usize), le_u32))(i)?;
+        let offset = offset as usize;
+
+        // A bad e_lfanew would otherwise only show up as a failed tag or EOF,
+        // so name it: at the field itself if it points past the end of the
+        // input, or at what it points to if that isn't the signature.
+        match i.get(offset..) {
+            Some(rest) if rest.starts_with(Self::MAGIC) => {
+                if rest.len() < Self::MAGIC.len() + COFF_HEADER_SIZE {
+                    return Err(failure(rest, ErrorKind::Eof, TOO_SMALL));
+                }
+            }
+            Some(rest) if rest.len() >= Self::MAGIC.len() => {
+                return Err(failure(rest, ErrorKind::Tag, PE_SIGNATURE_NOT_FOUND))
+            }
+            _ => return Err(failure(&i[0x3c..], ErrorKind::Eof, PE_SIGNATURE_NOT_FOUND)),
+        }
+
+        let (rest, mut header) = Self::parse_from_pe_header(&i[offset..])?;
+        header.coff_offset = offset + Self::MAGIC.len();
+        Ok((rest, header))
     }
 
     pub fn number_of_sections(&self) -> u16 {
         self.number_of_sections
     }
+
+    /// How long ago the file was linked, going by `time_date_stamp`.
+    pub fn age(&self) -> Option<Duration> {
+        self.age_at(SystemTime::now())
+    }
+
+    /// Like [`PeHeader64::age`], measured from `now`.
+    ///
+    /// `None` for a zero timestamp or one later than `now`: reproducible builds
+    /// fill the field with a hash instead, which is as likely to land in the future.
+    pub fn age_at(&self, now: SystemTime) -> Option<Duration> {
+        if self.time_date_stamp == 0 {
+            return None;
+        }
+        let linked = UNIX_EPOCH + Duration::from_secs(self.time_date_stamp.into());
+        now.duration_since(linked).ok()
+    }
+
+    /// Serializes the COFF file header, not including the `PE\0\0` signature
+    /// or the optional header, in the byte order it was parsed in.
+    pub fn coff_bytes(&self) -> Vec<u8> {
+        let e = self.endianness;
+        let mut out = Vec::with_capacity(20);
+        put_u16(&mut out, self.machine as u16, e);
+        put_u16(&mut out, self.number_of_sections, e);
+        put_u32(&mut out, self.time_date_stamp, e);
+        put_u32(&mut out, self.pointer_to_sym_table.0, e);
+        put_u32(&mut out, self.number_of_symbols, e);
+        put_u16(&mut out, self.size_of_optional_header, e);
+        put_u16(&mut out, self.characteristics.bits(), e);
+        out
+    }
 }
 
 /// _the optional header for PE32 and PE64 are split
@@ -248,10 +465,16 @@ impl PeHeader64 {
 /// 112-Variable (on 64-bit).
 /// Data directories, address size pairs.
 ///
+/// Both layouts are parsed into this one struct, with `magic` telling them apart.
+///
 /// RVA in the headers refers to 'Relative Virtual Address'
 #[derive(PartialEq, Debug)]
 pub struct OptionalHeader64 {
     // ------ COFF ------
+    /// Tells PE32 ([`OptionalHeader64::PE32_MAGIC`]) and PE32+
+    /// ([`OptionalHeader64::PE32PLUS_MAGIC`]) images apart.
+    pub magic: u16,
+
     pub major_linker_version: u8,
 
     pub minor_linker_version: u8,
@@ -271,7 +494,9 @@ pub struct OptionalHeader64 {
     /// when it is loaded into memory.
     pub base_of_code: u32,
 
-    // Note there is a `base_of_data` field present in PE32, not present here in the 64-bit version.
+    /// _the address relative to the image base of the beginning-of-data section
+    /// when it is loaded into memory. Only present in PE32, `None` in PE32+.
+    pub base_of_data: Option<u32>,
 
     // ------ COFF ------
 
@@ -284,14 +509,43 @@ pub struct OptionalHeader64 {
 // TODO PE header bitflags and DLL Characteristics Bitflags aswell.
 
 impl OptionalHeader64 {
-    const MAGIC: &'static [u8] = &[0x0B, 0x02];
+    pub const PE32_MAGIC: u16 = 0x10b;
+    pub const PE32PLUS_MAGIC: u16 = 0x20b;
+
+    /// Where the data directories start: after the Windows fields, whose size
+    /// depends on the bitness.
+    pub fn data_directories_offset(pe32: bool) -> usize {
+        if pe32 {
+            96
+        } else {
+            112
+        }
+    }
 
-    fn parse(i: Input) -> Result<Self> {
-        use nom::{bytes::complete::tag, error::context, number::complete::*, sequence::tuple};
+    /// How many data directories fit before the end of an optional header of
+    /// `size_of_optional_header` bytes.
+    pub fn data_directory_capacity(&self, size_of_optional_header: u16) -> u32 {
+        let offset = Self::data_directories_offset(self.base_of_data.is_some());
+        ((size_of_optional_header as usize).saturating_sub(offset) / 8) as u32
+    }
+
+    fn parse(i: Input, size_of_optional_header: u16) -> Result<Self> {
+        use nom::{
+            combinator::{cond, verify},
+            error::context,
+            number::complete::*,
+            sequence::tuple,
+        };
+        let (i, magic) = context(
+            "Magic",
+            verify(le_u16, |&magic| {
+                magic == Self::PE32_MAGIC || magic == Self::PE32PLUS_MAGIC
+            }),
+        )(i)?;
+        let pe32 = magic == Self::PE32_MAGIC;
         let (
             i,
             (
-                _,
                 major_linker_version,
                 minor_linker_version,
                 size_of_code,
@@ -299,12 +553,11 @@ impl OptionalHeader64 {
                 size_of_uninitialized_data,
                 entry_point,
                 base_of_code,
+                base_of_data,
                 windows_header,
-                data_directories,
             ),
         ) = tuple((
             // COFF-standard
-            context("Magic", tag(Self::MAGIC)),
             context("MajorLinkerVersion", le_u8),
             context("MinorLinkerVersion", le_u8),
             context("SizeOfCode", le_u32),
@@ -312,14 +565,22 @@ impl OptionalHeader64 {
             context("SizeOfUninitializedData", le_u32),
             context("AddressOfEntryPoint", Addr32::parse),
             context("BaseOfCode", le_u32),
+            context("BaseOfData", cond(pe32, le_u32)),
             // Windows
-            context("Windows", WindowsFields::parse),
-            // Data Directories
-            context("DataDirectories", DataDirectories::parse),
+            context("Windows", WindowsFields::parse(pe32)),
         ))(i)?;
+        // Data Directories, as many as are declared and also fit, since reading
+        // more would run into the section table.
+        let capacity = (size_of_optional_header as usize)
+            .saturating_sub(Self::data_directories_offset(pe32))
+            / 8;
+        let count = windows_header.number_of_rva_and_sizes.min(capacity as u32);
+        let (i, data_directories) =
+            context("DataDirectories", |i| DataDirectories::parse(i, count))(i)?;
         Ok((
             i,
             Self {
+                magic,
                 major_linker_version,
                 minor_linker_version,
                 size_of_code,
@@ -327,17 +588,41 @@ impl OptionalHeader64 {
                 size_of_uninitialized_data,
                 entry_point,
                 base_of_code,
+                base_of_data,
                 windows_header,
                 data_directories,
             },
         ))
     }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.magic.to_le_bytes().to_vec();
+        out.push(self.major_linker_version);
+        out.push(self.minor_linker_version);
+        out.extend_from_slice(&self.size_of_code.to_le_bytes());
+        out.extend_from_slice(&self.size_of_initialized_data.to_le_bytes());
+        out.extend_from_slice(&self.size_of_uninitialized_data.to_le_bytes());
+        out.extend_from_slice(&self.entry_point.0.to_le_bytes());
+        out.extend_from_slice(&self.base_of_code.to_le_bytes());
+        if let Some(base_of_data) = self.base_of_data {
+            out.extend_from_slice(&base_of_data.to_le_bytes());
+        }
+        out.extend_from_slice(&self.windows_header.to_bytes(self.base_of_data.is_some()));
+        out.extend_from_slice(
+            &self
+                .data_directories
+                .to_bytes(self.windows_header.number_of_rva_and_sizes),
+        );
+        out
+    }
 }
 
 #[derive(PartialEq, Debug)]
 pub struct WindowsFields {
     /// _the preferred address of the first byte
-    /// of the image when loaded into memory
+    /// of the image when loaded into memory.
+    ///
+    /// This and the stack and heap sizes are 32-bit in PE32, and widened here.
     pub image_base: u64,
 
     /// _the alignment of sections when they are loaded into memory.
@@ -421,9 +706,148 @@ bitflags! {
 
 impl_parse_for_enumflags!(DllCharacteristics, le_u16);
 
+/// Parses flag names separated by `,` or `|`, such as `NX_COMPAT|DYNAMIC_BASE`,
+/// with or without the `IMAGE_DLLCHARACTERISTICS_` prefix and in any case.
+impl std::str::FromStr for DllCharacteristics {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        const PREFIX: &str = "IMAGE_DLLCHARACTERISTICS_";
+        // Each defined flag's name, as its `Debug` output.
+        let flags: Vec<(String, Self)> = (0..16)
+            .filter_map(|bit| Self::from_bits(1 << bit))
+            .map(|flag| (format!("{:?}", flag), flag))
+            .collect();
+
+        let mut parsed = Self::empty();
+        for name in s.split([',', '|']).map(str::trim).filter(|n| !n.is_empty()) {
+            let name = name.to_ascii_uppercase();
+            let name = name.strip_prefix(PREFIX).unwrap_or(&name);
+            match flags.iter().find(|(flag_name, _)| flag_name[PREFIX.len()..] == *name) {
+                Some(&(_, flag)) => parsed |= flag,
+                None => {
+                    let names: Vec<&str> =
+                        flags.iter().map(|(flag_name, _)| &flag_name[PREFIX.len()..]).collect();
+                    return Err(format!(
+                        "unknown DLL characteristic {:?}, expected any of: {}",
+                        name,
+                        names.join(", ")
+                    ));
+                }
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// A broken alignment invariant, found by [`WindowsFields::validate_alignment`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlignmentError {
+    SectionAlignmentNotPowerOfTwo(u32),
+    FileAlignmentNotPowerOfTwo(u32),
+    /// Should be between 512 and 64K. Smaller values matching a SectionAlignment
+    /// below the page size are accepted by the loader, so aren't reported.
+    FileAlignmentOutOfRange(u32),
+    SectionAlignmentBelowFileAlignment { section: u32, file: u32 },
+    /// A SectionAlignment below the page size requires FileAlignment to match.
+    SubPageAlignmentMismatch { section: u32, file: u32 },
+}
+
+impl fmt::Display for AlignmentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::SectionAlignmentNotPowerOfTwo(a) => {
+                write!(f, "SectionAlignment {:#x} is not a power of two", a)
+            }
+            Self::FileAlignmentNotPowerOfTwo(a) => {
+                write!(f, "FileAlignment {:#x} is not a power of two", a)
+            }
+            Self::FileAlignmentOutOfRange(a) => {
+                write!(f, "FileAlignment {:#x} is outside 0x200-0x10000", a)
+            }
+            Self::SectionAlignmentBelowFileAlignment { section, file } => write!(
+                f,
+                "SectionAlignment {:#x} is less than FileAlignment {:#x}",
+                section, file
+            ),
+            Self::SubPageAlignmentMismatch { section, file } => write!(
+                f,
+                "SectionAlignment {:#x} is below the page size but FileAlignment {:#x} differs",
+                section, file
+            ),
+        }
+    }
+}
+
+/// The sizes in bytes, as stored. `File`'s `Display` rounds them down to KB.
 impl WindowsFields {
-    fn parse(i: Input) -> Result<Self> {
-        use nom::{error::context, number::complete::*, sequence::tuple};
+    /// The size of the image once loaded, including all headers, in bytes.
+    pub fn size_of_image_bytes(&self) -> u64 {
+        self.size_of_image.into()
+    }
+
+    /// The size of the headers rounded up to `file_alignment`, in bytes.
+    pub fn size_of_headers_bytes(&self) -> u64 {
+        self.size_of_headers.into()
+    }
+
+    pub fn size_of_stack_reserve_bytes(&self) -> u64 {
+        self.size_of_stack_reserve
+    }
+
+    pub fn size_of_stack_commit_bytes(&self) -> u64 {
+        self.size_of_stack_commit
+    }
+
+    pub fn size_of_heap_reserve_bytes(&self) -> u64 {
+        self.size_of_heap_reserve
+    }
+
+    pub fn size_of_heap_commit_bytes(&self) -> u64 {
+        self.size_of_heap_commit
+    }
+}
+
+impl WindowsFields {
+    /// Checks the alignment invariants the spec places on the optional header,
+    /// returning the first one broken.
+    pub fn validate_alignment(&self) -> std::result::Result<(), AlignmentError> {
+        let (section, file) = (self.section_alignment, self.file_alignment);
+        if !section.is_power_of_two() {
+            return Err(AlignmentError::SectionAlignmentNotPowerOfTwo(section));
+        }
+        if !file.is_power_of_two() {
+            return Err(AlignmentError::FileAlignmentNotPowerOfTwo(file));
+        }
+        if section < file {
+            return Err(AlignmentError::SectionAlignmentBelowFileAlignment { section, file });
+        }
+        // Images smaller than a page may have both alignments tiny, but equal.
+        if section < 0x1000 {
+            if file != section {
+                return Err(AlignmentError::SubPageAlignmentMismatch { section, file });
+            }
+        } else if !(0x200..=0x10000).contains(&file) {
+            return Err(AlignmentError::FileAlignmentOutOfRange(file));
+        }
+        Ok(())
+    }
+
+    /// Parses the PE32 layout if `pe32`, the PE32+ one otherwise.
+    fn parse<'a>(pe32: bool) -> impl Fn(Input<'a>) -> Result<'a, Self> {
+        move |i| Self::parse_fields(i, pe32)
+    }
+
+    fn parse_fields(i: Input, pe32: bool) -> Result<Self> {
+        use nom::{combinator::map, error::context, number::complete::*, sequence::tuple};
+        // The fields that are pointer-sized on the image's machine.
+        let word = |i| {
+            if pe32 {
+                map(le_u32, u64::from)(i)
+            } else {
+                le_u64(i)
+            }
+        };
 
         let (
             i,
@@ -451,7 +875,7 @@ impl WindowsFields {
                 number_of_rva_and_sizes,
             ),
         ) = tuple((
-            context("ImageBase", le_u64),
+            context("ImageBase", word),
             context("SectionAlignment", le_u32),
             context("FileAlignment", le_u32),
             context("MajorOperatingSystemVersion", le_u16),
@@ -466,10 +890,10 @@ impl WindowsFields {
             context("CheckSum", le_u32),
             context("Subsystem", Subsystem::parse),
             context("DllCharacteristics", DllCharacteristics::parse),
-            context("SizeOfStackReserve", le_u64),
-            context("SizeOfStackCommit", le_u64),
-            context("SizeOfHeapReserve", le_u64),
-            context("SizeOfHeapCommit", le_u64),
+            context("SizeOfStackReserve", word),
+            context("SizeOfStackCommit", word),
+            context("SizeOfHeapReserve", word),
+            context("SizeOfHeapCommit", word),
             context("LoaderFlags", le_u32),
             context("NumberOfRvaAndSizes", le_u32),
         ))(i)?;
@@ -501,57 +925,108 @@ impl WindowsFields {
             },
         ))
     }
+
+    /// Serializes the fields in the PE32 layout if `pe32`, truncating the
+    /// pointer-sized ones, or the PE32+ one otherwise.
+    pub fn to_bytes(&self, pe32: bool) -> Vec<u8> {
+        let word = |out: &mut Vec<u8>, n: u64| {
+            if pe32 {
+                out.extend_from_slice(&(n as u32).to_le_bytes())
+            } else {
+                out.extend_from_slice(&n.to_le_bytes())
+            }
+        };
+        let mut out = Vec::with_capacity(88);
+        word(&mut out, self.image_base);
+        out.extend_from_slice(&self.section_alignment.to_le_bytes());
+        out.extend_from_slice(&self.file_alignment.to_le_bytes());
+        out.extend_from_slice(&self.major_os_version.to_le_bytes());
+        out.extend_from_slice(&self.minor_os_version.to_le_bytes());
+        out.extend_from_slice(&self.major_image_version.to_le_bytes());
+        out.extend_from_slice(&self.minor_image_version.to_le_bytes());
+        out.extend_from_slice(&self.major_subsystem_version.to_le_bytes());
+        out.extend_from_slice(&self.minor_subsystem_version.to_le_bytes());
+        out.extend_from_slice(&self.win32_version_value.to_le_bytes());
+        out.extend_from_slice(&self.size_of_image.to_le_bytes());
+        out.extend_from_slice(&self.size_of_headers.to_le_bytes());
+        out.extend_from_slice(&self.checksum.to_le_bytes());
+        out.extend_from_slice(&(self.subsystem as u16).to_le_bytes());
+        out.extend_from_slice(&self.dll_characteristics.bits().to_le_bytes());
+        word(&mut out, self.size_of_stack_reserve);
+        word(&mut out, self.size_of_stack_commit);
+        word(&mut out, self.size_of_heap_reserve);
+        word(&mut out, self.size_of_heap_commit);
+        out.extend_from_slice(&self.loader_flags.to_le_bytes());
+        out.extend_from_slice(&self.number_of_rva_and_sizes.to_le_bytes());
+        out
+    }
 }
 
 #[derive(PartialEq, Debug)]
 pub struct DataDirectories {
     /// `.edata` - _the export table address and size.
-    export_table: DataDirectory,
+    pub export_table: DataDirectory,
 
     /// `.idata` - _the import table address and size.
-    import_table: DataDirectory,
+    pub import_table: DataDirectory,
 
     /// `.rsrc` - _the resource table address and size.
-    resource_table: DataDirectory,
+    pub resource_table: DataDirectory,
 
     /// `.pdata` - _the exception table address and size.
-    exception_table: DataDirectory,
+    pub exception_table: DataDirectory,
 
     /// _the certificate table address and size.
-    certificate_table: DataDirectory,
+    pub certificate_table: DataDirectory,
 
     /// `.reloc` - _the base relocation table address and size.
-    base_relocation_table: DataDirectory,
+    pub base_relocation_table: DataDirectory,
 
     /// `.debug` - _the debug data starting address and size.
-    debug_data: DataDirectory,
+    pub debug_data: DataDirectory,
+
+    /// Reserved, must be zero. Kept so files that don't comply round-trip.
+    pub architecture: DataDirectory,
 
     /// _the RVA of the value to be stored in the global pointer register.
-    global_ptr: Addr,
+    pub global_ptr: Addr,
 
     /// `.tls` - _the thread local storage (_tLS) table address and size.
-    tls_table: DataDirectory,
+    pub tls_table: DataDirectory,
 
     /// _the load configuration table address and size.
-    load_config_table: DataDirectory,
+    pub load_config_table: DataDirectory,
 
     /// _the bound import table address and size.
-    bound_import: DataDirectory,
+    pub bound_import: DataDirectory,
 
     /// _the import address table and size.
-    iat: DataDirectory,
+    pub iat: DataDirectory,
 
     /// _the delay import descriptor address and size.
-    delay_import_descriptor: DataDirectory,
+    pub delay_import_descriptor: DataDirectory,
 
     /// `.cormeta` (Object only) _the CLR runtime header address and size.
-    clr_runtime_header: DataDirectory,
+    pub clr_runtime_header: DataDirectory,
+
+    /// Reserved, must be zero. Kept so files that don't comply round-trip.
+    pub reserved: DataDirectory,
+
+    /// Entries past the standard 16, when `number_of_rva_and_sizes` declares more.
+    /// The loader ignores these, but they still sit before the section table.
+    pub extra: Vec<DataDirectory>,
 }
 
 impl DataDirectories {
-    fn parse(i: Input) -> Result<Self> {
-        use nom::{error::context, sequence::tuple};
+    /// The number of directories the format defines.
+    const STANDARD_COUNT: u32 = 16;
 
+    fn parse(i: Input, number_of_rva_and_sizes: u32) -> Result<Self> {
+        use nom::{combinator::cond, error::context, multi::count, sequence::tuple};
+
+        // Images can declare fewer than the standard 16, the rest are absent.
+        let declared = |index: u32| number_of_rva_and_sizes > index;
+        let dir = |index, name| context(name, cond(declared(index), DataDirectory::parse));
         let (
             i,
             (
@@ -562,7 +1037,7 @@ impl DataDirectories {
                 certificate_table,
                 base_relocation_table,
                 debug_data,
-                _,
+                architecture,
                 global_ptr,
                 tls_table,
                 load_config_table,
@@ -570,51 +1045,337 @@ impl DataDirectories {
                 iat,
                 delay_import_descriptor,
                 clr_runtime_header,
-                _,
+                reserved,
             ),
         ) = tuple((
-            context("ExportTable", DataDirectory::parse),
-            context("ImportTable", DataDirectory::parse),
-            context("ResourceTable", DataDirectory::parse),
-            context("ExceptionTable", DataDirectory::parse),
-            context("CertificateTable", DataDirectory::parse),
-            context("BaseRelocationTable", DataDirectory::parse),
-            context("Debug", DataDirectory::parse),
-            context(
-                "Architecture",
-                nom::bytes::complete::tag(&[0, 0, 0, 0, 0, 0, 0, 0]),
-            ), // NOTE: must be zero.
-            context("GlobalPtr", Addr::parse),
-            context("TlsTable", DataDirectory::parse),
-            context("LoadConfigTable", DataDirectory::parse),
-            context("BoundImport", DataDirectory::parse),
-            context("IAT", DataDirectory::parse),
-            context("DelayImportDescriptor", DataDirectory::parse),
-            context("ClrRuntimeHeader", DataDirectory::parse),
-            context(
-                "Padding",
-                nom::bytes::complete::tag(&[0, 0, 0, 0, 0, 0, 0, 0]),
-            ), // NOTE: must be zero.
+            dir(0, "ExportTable"),
+            dir(1, "ImportTable"),
+            dir(2, "ResourceTable"),
+            dir(3, "ExceptionTable"),
+            dir(4, "CertificateTable"),
+            dir(5, "BaseRelocationTable"),
+            dir(6, "Debug"),
+            dir(7, "Architecture"),
+            context("GlobalPtr", cond(declared(8), Addr::parse)),
+            dir(9, "TlsTable"),
+            dir(10, "LoadConfigTable"),
+            dir(11, "BoundImport"),
+            dir(12, "IAT"),
+            dir(13, "DelayImportDescriptor"),
+            dir(14, "ClrRuntimeHeader"),
+            dir(15, "Reserved"),
         ))(i)?;
 
+        // Capped so a bogus count fails to parse instead of reserving gigabytes up front;
+        // one more entry than could fit still errors out.
+        let extra_count = (number_of_rva_and_sizes.saturating_sub(Self::STANDARD_COUNT) as usize)
+            .min(i.len() / 8 + 1);
+        let (i, extra) = context("ExtraDirectories", count(DataDirectory::parse, extra_count))(i)?;
+
         Ok((
             i,
             Self {
-                export_table,
-                import_table,
-                resource_table,
-                exception_table,
-                certificate_table,
-                base_relocation_table,
-                debug_data,
-                global_ptr,
-                tls_table,
-                load_config_table,
-                bound_import,
-                iat,
-                delay_import_descriptor,
-                clr_runtime_header,
+                export_table: export_table.unwrap_or_default(),
+                import_table: import_table.unwrap_or_default(),
+                resource_table: resource_table.unwrap_or_default(),
+                exception_table: exception_table.unwrap_or_default(),
+                certificate_table: certificate_table.unwrap_or_default(),
+                base_relocation_table: base_relocation_table.unwrap_or_default(),
+                debug_data: debug_data.unwrap_or_default(),
+                architecture: architecture.unwrap_or_default(),
+                global_ptr: global_ptr.unwrap_or(Addr(0)),
+                tls_table: tls_table.unwrap_or_default(),
+                load_config_table: load_config_table.unwrap_or_default(),
+                bound_import: bound_import.unwrap_or_default(),
+                iat: iat.unwrap_or_default(),
+                delay_import_descriptor: delay_import_descriptor.unwrap_or_default(),
+                clr_runtime_header: clr_runtime_header.unwrap_or_default(),
+                reserved: reserved.unwrap_or_default(),
+                extra,
             },
         ))
     }
+
+    /// The entry for `kind`. The global pointer has no size, so its upper half
+    /// comes back as one.
+    pub fn get(&self, kind: DataDirectoryKind) -> DataDirectory {
+        use DataDirectoryKind as K;
+        let dir = match kind {
+            K::Export => &self.export_table,
+            K::Import => &self.import_table,
+            K::Resource => &self.resource_table,
+            K::Exception => &self.exception_table,
+            K::Certificate => &self.certificate_table,
+            K::BaseRelocation => &self.base_relocation_table,
+            K::Debug => &self.debug_data,
+            K::Architecture => &self.architecture,
+            K::GlobalPtr => {
+                return DataDirectory {
+                    virtual_addr: Addr32(self.global_ptr.0 as u32),
+                    size: (self.global_ptr.0 >> 32) as u32,
+                }
+            }
+            K::Tls => &self.tls_table,
+            K::LoadConfig => &self.load_config_table,
+            K::BoundImport => &self.bound_import,
+            K::Iat => &self.iat,
+            K::DelayImportDescriptor => &self.delay_import_descriptor,
+            K::ClrRuntimeHeader => &self.clr_runtime_header,
+            K::Reserved => &self.reserved,
+        };
+        DataDirectory {
+            virtual_addr: dir.virtual_addr,
+            size: dir.size,
+        }
+    }
+
+    /// Writes as many directories as `number_of_rva_and_sizes` declares.
+    pub fn to_bytes(&self, number_of_rva_and_sizes: u32) -> Vec<u8> {
+        let mut out = Vec::with_capacity((16 + self.extra.len()) * 8);
+        for dir in &[
+            &self.export_table,
+            &self.import_table,
+            &self.resource_table,
+            &self.exception_table,
+            &self.certificate_table,
+            &self.base_relocation_table,
+            &self.debug_data,
+            &self.architecture,
+        ] {
+            out.extend_from_slice(&dir.to_bytes());
+        }
+        out.extend_from_slice(&self.global_ptr.0.to_le_bytes());
+        for dir in &[
+            &self.tls_table,
+            &self.load_config_table,
+            &self.bound_import,
+            &self.iat,
+            &self.delay_import_descriptor,
+            &self.clr_runtime_header,
+            &self.reserved,
+        ] {
+            out.extend_from_slice(&dir.to_bytes());
+        }
+        out.truncate(number_of_rva_and_sizes.min(Self::STANDARD_COUNT) as usize * 8);
+        for dir in &self.extra {
+            out.extend_from_slice(&dir.to_bytes());
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::*;
+    use super::*;
+
+    type Alignment = std::result::Result<(), AlignmentError>;
+
+    fn alignment(section_alignment: u32, file_alignment: u32) -> Alignment {
+        let builder = PeBuilder {
+            section_alignment,
+            file_alignment,
+            ..PeBuilder::default()
+        };
+        let raw = builder.section(".text", 0x1000, vec![0xc3; 0x10], CODE).build();
+        let (_, file) = File::parse(&raw).unwrap();
+        file.header.optional_header.unwrap().windows_header.validate_alignment()
+    }
+
+    #[test]
+    fn alignment_invariants() {
+        assert_eq!(alignment(0x1000, 0x200), Ok(()));
+        assert_eq!(
+            alignment(0x1000, 0x300),
+            Err(AlignmentError::FileAlignmentNotPowerOfTwo(0x300))
+        );
+        assert_eq!(
+            alignment(0x1000, 0x100),
+            Err(AlignmentError::FileAlignmentOutOfRange(0x100))
+        );
+        assert_eq!(
+            alignment(0x200, 0x400),
+            Err(AlignmentError::SectionAlignmentBelowFileAlignment { section: 0x200, file: 0x400 })
+        );
+    }
+
+    #[test]
+    fn sub_page_alignments_must_match() {
+        assert_eq!(alignment(0x20, 0x20), Ok(()));
+        assert_eq!(
+            alignment(0x800, 0x200),
+            Err(AlignmentError::SubPageAlignmentMismatch { section: 0x800, file: 0x200 })
+        );
+    }
+
+    #[test]
+    fn directories_past_the_standard_sixteen_are_kept() {
+        let mut builder = PeBuilder {
+            number_of_rva_and_sizes: 18,
+            ..PeBuilder::default()
+        };
+        builder.directories.extend([(0x1111, 0x11), (0x2222, 0x22)]);
+        let raw = builder.section(".text", 0x1000, vec![0xc3; 0x10], CODE).build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let oh = file.header.optional_header.as_ref().unwrap();
+        assert_eq!(oh.windows_header.number_of_rva_and_sizes, 18);
+        let extra = &oh.data_directories.extra;
+        let extra: Vec<_> = extra.iter().map(|d| (d.virtual_addr.0, d.size)).collect();
+        assert_eq!(extra, [(0x1111, 0x11), (0x2222, 0x22)]);
+        // The section table is still found right after them.
+        assert_eq!(file.sections[0].name.as_str(), ".text");
+        assert_eq!(file.sections[0].data, [0xc3; 0x10]);
+    }
+
+    #[test]
+    fn directories_resolve_into_their_bytes() {
+        let tree = [resource(16, 1, b"version".to_vec())];
+        let raw = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x10], CODE)
+            .resources(0x2000, &tree)
+            .build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let dirs = &file.header.optional_header.as_ref().unwrap().data_directories;
+        assert_eq!(dirs.resource_table.resolve_into(&file), Some(resource_section(0x2000, &tree)));
+        assert_eq!(dirs.export_table.resolve_into(&file), None);
+    }
+
+    /// A PowerPC object with one `.text` section, its headers all big-endian.
+    fn big_endian_object(code: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&0x1f2_u16.to_be_bytes());
+        out.extend_from_slice(&1_u16.to_be_bytes());
+        out.extend_from_slice(&0x1234_5678_u32.to_be_bytes());
+        out.extend_from_slice(&[0; 12]);
+        out.extend_from_slice(&name(".text"));
+        out.extend_from_slice(&[0; 8]);
+        out.extend_from_slice(&(code.len() as u32).to_be_bytes());
+        out.extend_from_slice(&60_u32.to_be_bytes());
+        out.extend_from_slice(&[0; 12]);
+        out.extend_from_slice(&CODE.bits().to_be_bytes());
+        out.extend_from_slice(code);
+        out
+    }
+
+    #[test]
+    fn big_endian_objects_parse_and_write_back_big_endian() {
+        let raw = big_endian_object(&[0x4e, 0x80, 0x00, 0x20]);
+        let (_, mut file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.header.machine, Machine::PowerPCBE);
+        assert_eq!(file.header.endianness, Endianness::Big);
+        assert_eq!(file.header.time_date_stamp, 0x1234_5678);
+        let text = &file.sections[0];
+        assert_eq!(text.name.as_str(), ".text");
+        assert_eq!(text.characteristics, CODE);
+        assert_eq!(text.data, [0x4e, 0x80, 0x00, 0x20]);
+        assert_eq!(file.to_bytes(&raw), raw);
+
+        file.set_characteristic(Characteristics::IMAGE_FILE_LINE_NUMS_STRIPPED, true);
+        let written = file.to_bytes(&raw);
+        assert_eq!(written[18..20], Characteristics::IMAGE_FILE_LINE_NUMS_STRIPPED.bits().to_be_bytes());
+    }
+
+    #[test]
+    fn age_is_measured_from_the_given_now() {
+        let header = |time_date_stamp| {
+            let builder = PeBuilder {
+                time_date_stamp,
+                ..PeBuilder::default()
+            };
+            let raw = builder.section(".text", 0x1000, vec![0xc3; 0x10], CODE).build();
+            File::parse(&raw).unwrap().1.header
+        };
+        let now = UNIX_EPOCH + Duration::from_secs(0x6000_0000);
+
+        assert_eq!(header(0x5f00_0000).age_at(now), Some(Duration::from_secs(0x0100_0000)));
+        assert_eq!(header(0x6000_0000).age_at(now), Some(Duration::from_secs(0)));
+        assert_eq!(header(0x7000_0000).age_at(now), None);
+        assert_eq!(header(0).age_at(now), None);
+    }
+
+    #[test]
+    fn a_dll_is_described_in_one_line() {
+        let builder = PeBuilder {
+            // IMAGE_FILE_DLL | IMAGE_FILE_EXECUTABLE_IMAGE | IMAGE_FILE_LARGE_ADDRESS_AWARE
+            // | IMAGE_FILE_BYTES_REVERSED_LO, which is deprecated
+            characteristics: 0x20a2,
+            ..PeBuilder::default()
+        };
+        let raw = builder.section(".text", 0x1000, vec![0xc3; 0x10], CODE).build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.header.characteristics.describe(), "Executable, DLL, Large-address-aware");
+        assert_eq!(Characteristics::empty().describe(), "-");
+    }
+
+    #[test]
+    fn pe32_base_of_data_and_the_fields_after_it_parse() {
+        let builder = PeBuilder {
+            checksum: 0xabcd,
+            subsystem: 2,
+            ..PeBuilder::pe32()
+        };
+        let raw = builder.section(".text", 0x1000, vec![0xc3; 0x10], CODE).build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let oh = file.header.optional_header.as_ref().unwrap();
+        assert_eq!(oh.base_of_code, 0x1000);
+        assert_eq!(oh.base_of_data, Some(0x2000));
+        let wh = &oh.windows_header;
+        assert_eq!(wh.image_base, 0x40_0000);
+        assert_eq!((wh.section_alignment, wh.file_alignment), (0x1000, 0x200));
+        assert_eq!(wh.checksum, 0xabcd);
+        assert_eq!(wh.subsystem, Subsystem::WindowsGui);
+        assert_eq!(wh.size_of_stack_reserve, 0x10_0000);
+        assert_eq!(wh.number_of_rva_and_sizes, 16);
+        assert_eq!(file.sections[0].name.as_str(), ".text");
+        assert!(file.to_string().contains("Base of Data"));
+
+        let (_, pe32_plus) = File::parse(&simple_image(vec![0xc3])).unwrap();
+        assert_eq!(pe32_plus.header.optional_header.as_ref().unwrap().base_of_data, None);
+        assert!(!pe32_plus.to_string().contains("Base of Data"));
+    }
+
+    #[test]
+    fn size_getters_return_bytes_not_kb() {
+        let raw = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x10], CODE)
+            .section(".data", 0x2000, vec![1; 0x1801], DATA)
+            .build();
+        let (_, file) = File::parse(&raw).unwrap();
+        let wh = &file.header.optional_header.as_ref().unwrap().windows_header;
+
+        assert_eq!(wh.size_of_image_bytes(), 0x4000);
+        assert_eq!(wh.size_of_headers_bytes(), 0x400);
+        assert_eq!(wh.size_of_stack_reserve_bytes(), 0x10_0000);
+
+        let text = file.to_string();
+        let row = text.lines().find(|line| line.contains("Size of Image")).unwrap();
+        assert!(row.ends_with("16KB"), "{}", row);
+    }
+
+    #[test]
+    fn filter_names_parse_into_enums_and_flags() {
+        assert_eq!("WindowsGui".parse(), Ok(Subsystem::WindowsGui));
+        assert_eq!("windowsgui".parse(), Ok(Subsystem::WindowsGui));
+        assert_eq!("amd64".parse(), Ok(Machine::AMD64));
+
+        let err = "WindowsGooey".parse::<Subsystem>().unwrap_err();
+        let expected = "unknown Subsystem \"WindowsGooey\", expected one of: ";
+        assert!(err.starts_with(expected), "{}", err);
+        assert!(err.contains("WindowsGui, WindowsCui"), "{}", err);
+
+        use DllCharacteristics as D;
+        let flags = "nx_compat, IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE".parse();
+        assert_eq!(
+            flags,
+            Ok(D::IMAGE_DLLCHARACTERISTICS_NX_COMPAT | D::IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE)
+        );
+        let err = "NX_COMPAT|ASLR".parse::<D>().unwrap_err();
+        let expected = "unknown DLL characteristic \"ASLR\", expected any of: ";
+        assert!(err.starts_with(expected), "{}", err);
+    }
 }
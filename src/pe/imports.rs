@@ -0,0 +1,421 @@
+use super::hash::{md5, to_hex};
+use super::util::*;
+use super::File;
+use std::fmt;
+
+#[cfg(feature = "known-ordinals")]
+use super::ordinals::lookup as known_ordinal;
+
+#[cfg(not(feature = "known-ordinals"))]
+fn known_ordinal(_dll: &str, _ordinal: u16) -> Option<&'static str> {
+    None
+}
+
+/// Size of one `IMAGE_IMPORT_DESCRIPTOR`.
+const DESCRIPTOR_SIZE: usize = 20;
+
+/// How many lookup table entries are read across all descriptors, which may
+/// all point at the same section-sized table in a crafted image.
+const MAX_THUNKS: usize = 0x10000;
+
+/// How far DLL and function names are read looking for their NUL, as `pefile`
+/// does with its `MAX_IMPORT_NAME_LENGTH`.
+const MAX_NAME_LEN: usize = 0x200;
+
+/// How a function is imported from its DLL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportedFunction {
+    /// `hint` is where the loader starts looking in the DLL's export name table.
+    ByName { hint: u16, name: String },
+    ByOrdinal(u16),
+}
+
+impl ImportedFunction {
+    /// The name of a function imported by ordinal from `dll`, if it's one of
+    /// the few DLLs whose ordinals are well known, like `ws2_32.dll`.
+    ///
+    /// Always `None` unless built with the `known-ordinals` feature.
+    pub fn resolve_known_ordinal(&self, dll: &str) -> Option<&'static str> {
+        match *self {
+            Self::ByOrdinal(ordinal) => known_ordinal(dll, ordinal),
+            Self::ByName { .. } => None,
+        }
+    }
+}
+
+/// The functions imported from one DLL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    pub dll: String,
+    pub functions: Vec<ImportedFunction>,
+}
+
+/// `dll` without its `.dll`, `.ocx` or `.sys` extension, in any case.
+fn dll_stem(dll: &str) -> &str {
+    match dll.rfind('.') {
+        Some(dot) if ["dll", "ocx", "sys"].iter().any(|ext| dll[dot + 1..].eq_ignore_ascii_case(ext)) => {
+            &dll[..dot]
+        }
+        _ => dll,
+    }
+}
+
+/// One function per line, as `DLL.name (hint N)`, or `DLL.#ordinal` for
+/// imports by ordinal, `DLL.name (#ordinal)` if the name is known.
+impl fmt::Display for Import {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dll = dll_stem(&self.dll);
+        for function in &self.functions {
+            match function {
+                ImportedFunction::ByName { hint, name } => {
+                    writeln!(f, "{}.{} (hint {})", dll, name, hint)?
+                }
+                ImportedFunction::ByOrdinal(ordinal) => {
+                    match function.resolve_known_ordinal(&self.dll) {
+                        Some(name) => writeln!(f, "{}.{} (#{})", dll, name, ordinal)?,
+                        None => writeln!(f, "{}.#{}", dll, ordinal)?,
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct ImportDescriptor {
+    original_first_thunk: u32,
+    name: u32,
+    first_thunk: u32,
+}
+
+impl ImportDescriptor {
+    fn parse(i: Input) -> Result<Self> {
+        use nom::{error::context, number::complete::*, sequence::tuple};
+        let (i, (original_first_thunk, _time_date_stamp, _forwarder_chain, name, first_thunk)) =
+            tuple((
+                context("OriginalFirstThunk", le_u32),
+                context("TimeDateStamp", le_u32),
+                context("ForwarderChain", le_u32),
+                context("Name", le_u32),
+                context("FirstThunk", le_u32),
+            ))(i)?;
+        Ok((
+            i,
+            Self {
+                original_first_thunk,
+                name,
+                first_thunk,
+            },
+        ))
+    }
+}
+
+impl File {
+    /// Parses the import directory.
+    ///
+    /// Both the descriptor list and each lookup table end at an all-zero entry,
+    /// or wherever they run off the end of their section. No more than
+    /// [`MAX_THUNKS`] functions are read in total; later DLLs get cut short.
+    pub fn imports(&self) -> Vec<Import> {
+        self.imports_iter().collect()
+    }
+
+    /// Like [`File::imports`], but walks the descriptors as it goes, so finding
+    /// one DLL doesn't mean parsing every lookup table.
+    pub fn imports_iter(&self) -> impl Iterator<Item = Import> + '_ {
+        let mut remaining = MAX_THUNKS;
+        self.import_descriptors().map(move |descriptor| {
+            let functions = self.imported_functions(&descriptor, remaining);
+            remaining -= functions.len();
+            Import {
+                dll: self
+                    .read_c_string_at_rva_within(descriptor.name, MAX_NAME_LEN)
+                    .unwrap_or_default(),
+                functions,
+            }
+        })
+    }
+
+    /// The names of the imported DLLs, in descriptor order, without walking
+    /// any lookup tables. Enough for mapping dependencies.
+    #[allow(dead_code)]
+    pub fn imported_dlls(&self) -> Vec<String> {
+        self.import_descriptors()
+            .map(|descriptor| {
+                self.read_c_string_at_rva_within(descriptor.name, MAX_NAME_LEN)
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    fn import_descriptors(&self) -> impl Iterator<Item = ImportDescriptor> + '_ {
+        let mut rva = match &self.header.optional_header {
+            Some(oh) if oh.data_directories.import_table.is_present() => {
+                Some(oh.data_directories.import_table.virtual_addr.0)
+            }
+            _ => None,
+        };
+
+        std::iter::from_fn(move || {
+            let bytes = self.read_at_rva(rva?, DESCRIPTOR_SIZE)?;
+            let descriptor = match ImportDescriptor::parse(&bytes) {
+                Ok((_, d)) if d.name != 0 || d.first_thunk != 0 => d,
+                _ => {
+                    rva = None;
+                    return None;
+                }
+            };
+            rva = rva.map(|rva| rva.wrapping_add(DESCRIPTOR_SIZE as u32));
+            Some(descriptor)
+        })
+    }
+
+    /// The number of functions imported across all DLLs, counting lookup table
+    /// entries without reading any names. Capped like [`File::imports`].
+    #[allow(dead_code)]
+    pub fn import_count(&self) -> usize {
+        self.import_descriptors().fold(0, |count, descriptor| {
+            count + self.thunks(&descriptor).take(MAX_THUNKS - count).count()
+        })
+    }
+
+    /// At most `limit` of the functions in `descriptor`'s lookup table.
+    fn imported_functions(
+        &self,
+        descriptor: &ImportDescriptor,
+        limit: usize,
+    ) -> Vec<ImportedFunction> {
+        // Set in the top bit of an entry that imports by ordinal rather than by name.
+        let ordinal_flag = 1 << (self.thunk_size() * 8 - 1);
+
+        self.thunks(descriptor)
+            .take(limit)
+            .map(|thunk| {
+                if thunk & ordinal_flag != 0 {
+                    return ImportedFunction::ByOrdinal(thunk as u16);
+                }
+                let hint_rva = thunk as u32;
+                let hint = self
+                    .read_at_rva(hint_rva, 2)
+                    .map_or(0, |b| u16::from_le_bytes([b[0], b[1]]));
+                let name = self
+                    .read_c_string_at_rva_within(hint_rva.wrapping_add(2), MAX_NAME_LEN)
+                    .unwrap_or_default();
+                ImportedFunction::ByName { hint, name }
+            })
+            .collect()
+    }
+
+    /// The entries of a descriptor's lookup table, up to the terminating zero.
+    fn thunks<'a>(&'a self, descriptor: &ImportDescriptor) -> impl Iterator<Item = u64> + 'a {
+        // The lookup table is left untouched by binding, unlike the IAT,
+        // but some linkers omit it.
+        let mut rva = match descriptor.original_first_thunk {
+            0 => descriptor.first_thunk,
+            rva => rva,
+        };
+        let thunk_size = self.thunk_size();
+
+        std::iter::from_fn(move || {
+            let bytes = self.read_at_rva(rva, thunk_size)?;
+            let thunk = bytes.iter().rev().fold(0u64, |thunk, &b| thunk << 8 | u64::from(b));
+            if thunk == 0 {
+                return None;
+            }
+            rva = rva.wrapping_add(thunk_size as u32);
+            Some(thunk)
+        })
+    }
+
+    /// The "imphash": MD5 of the lowercased `dll.function` list, as computed by
+    /// `pefile`, for clustering samples built from the same code.
+    ///
+    /// Ordinal imports are hashed as `ordN`. `pefile` resolves a few well-known
+    /// DLLs' ordinals to names first, which only the `known-ordinals` feature
+    /// does here, and only for the Winsock 1.1 ones.
+    /// `None` when nothing is imported.
+    #[allow(dead_code)]
+    pub fn imphash(&self) -> Option<String> {
+        let mut entries = Vec::new();
+        for import in self.imports() {
+            let dll = import.dll.to_lowercase();
+            let dll = dll_stem(&dll);
+            for function in &import.functions {
+                let function = match function {
+                    ImportedFunction::ByName { name, .. } => name.to_lowercase(),
+                    ImportedFunction::ByOrdinal(n) => {
+                        match function.resolve_known_ordinal(&import.dll) {
+                            Some(name) => name.to_lowercase(),
+                            None => format!("ord{}", n),
+                        }
+                    }
+                };
+                entries.push(format!("{}.{}", dll, function));
+            }
+        }
+        if entries.is_empty() {
+            return None;
+        }
+        Some(to_hex(&md5(entries.join(",").as_bytes())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::*;
+    use super::*;
+
+    fn import_image(dlls: &[&str], thunks: &[u64], shared: bool) -> Vec<u8> {
+        PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x100], CODE)
+            .imports(0x2000, dlls, thunks, shared)
+            .build()
+    }
+
+    #[test]
+    fn imports_by_name_and_ordinal() {
+        // A hint/name entry for the first thunk to point at, in `.text`.
+        let dlls = ["KERNEL32.dll", "WS2_32.dll"];
+        let mut raw = import_image(&dlls, &[0x1010, BY_ORDINAL | 23], false);
+        put(&mut raw, 0x410, b"\x07\x00ExitProcess\0");
+        let (_, file) = File::parse(&raw).unwrap();
+        let imports = file.imports();
+
+        assert_eq!(file.imported_dlls(), ["KERNEL32.dll", "WS2_32.dll"]);
+        assert_eq!(
+            imports[0].functions,
+            [
+                ImportedFunction::ByName { hint: 7, name: "ExitProcess".to_string() },
+                ImportedFunction::ByOrdinal(23),
+            ]
+        );
+        assert_eq!(file.import_count(), 4);
+        assert_eq!(imports[0].to_string(), "KERNEL32.ExitProcess (hint 7)\nKERNEL32.#23\n");
+    }
+
+    #[test]
+    fn a_shared_lookup_table_is_capped() {
+        let dlls = ["a.dll"; 8];
+        let raw = import_image(&dlls, &vec![BY_ORDINAL | 1; MAX_THUNKS / 4], true);
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let functions: usize = file.imports().iter().map(|i| i.functions.len()).sum();
+        assert_eq!(functions, MAX_THUNKS);
+        assert_eq!(file.import_count(), MAX_THUNKS);
+        assert_eq!(file.imports().last().unwrap().functions.len(), 0);
+    }
+
+    #[test]
+    fn unterminated_names_are_cut_short() {
+        let raw = import_image(&[&"x".repeat(0x1000)], &[BY_ORDINAL | 1], false);
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.imported_dlls()[0].len(), MAX_NAME_LEN);
+    }
+
+    #[test]
+    fn imports_iter_can_stop_early() {
+        let dlls: Vec<String> = (0..40).map(|n| format!("lib{}.dll", n)).collect();
+        let dlls: Vec<&str> = dlls.iter().map(String::as_str).collect();
+        let raw = import_image(&dlls, &[BY_ORDINAL | 1, BY_ORDINAL | 2], false);
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let first: Vec<Import> = file.imports_iter().take(1).collect();
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].dll, "lib0.dll");
+        assert_eq!(
+            first[0].functions,
+            [ImportedFunction::ByOrdinal(1), ImportedFunction::ByOrdinal(2)]
+        );
+        let found = file.imports_iter().find(|i| i.dll == "lib25.dll").unwrap();
+        assert_eq!(found.functions.len(), 2);
+        assert_eq!(file.imports().len(), 40);
+    }
+
+    #[test]
+    fn ordinal_only_imports_are_shown_by_number() {
+        let raw = import_image(&["MFC42.DLL"], &[BY_ORDINAL | 6453, BY_ORDINAL | 0x10], false);
+        let (_, file) = File::parse(&raw).unwrap();
+        let imports = file.imports();
+
+        assert_eq!(
+            imports[0].functions,
+            [ImportedFunction::ByOrdinal(6453), ImportedFunction::ByOrdinal(0x10)]
+        );
+        assert_eq!(imports[0].to_string(), "MFC42.#6453\nMFC42.#16\n");
+    }
+
+    #[test]
+    fn thunks_are_read_at_the_image_bitness() {
+        for builder in [PeBuilder::pe32(), PeBuilder::default()] {
+            let pe32 = builder.pe32;
+            let mut raw = builder
+                .section(".text", 0x1000, vec![0xc3; 0x100], CODE)
+                .imports(0x2000, &["KERNEL32.dll"], &[0x1010, BY_ORDINAL | 23, 0x1010], false)
+                .build();
+            put(&mut raw, 0x410, b"\x07\x00ExitProcess\0");
+            let (_, file) = File::parse(&raw).unwrap();
+
+            assert_eq!(file.thunk_size(), if pe32 { 4 } else { 8 });
+            let exit_process = ImportedFunction::ByName {
+                hint: 7,
+                name: "ExitProcess".to_string(),
+            };
+            assert_eq!(
+                file.imports()[0].functions,
+                [exit_process.clone(), ImportedFunction::ByOrdinal(23), exit_process]
+            );
+        }
+    }
+
+    #[test]
+    fn imported_dlls_reads_only_the_descriptors() {
+        let dlls = ["KERNEL32.dll", "USER32.dll", "ADVAPI32.dll"];
+        let mut raw = import_image(&dlls, &[BY_ORDINAL | 1], false);
+        // Lookup tables that lead nowhere don't matter for the names.
+        let idata = 0x600; // After .text's raw data.
+        for n in 0..dlls.len() {
+            put(&mut raw, idata + n * 20, &0x7fff_0000_u32.to_le_bytes());
+            put(&mut raw, idata + n * 20 + 16, &0x7fff_0000_u32.to_le_bytes());
+        }
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.imported_dlls(), dlls);
+        assert!(file.imports().iter().all(|i| i.functions.is_empty()));
+    }
+
+    #[cfg(feature = "known-ordinals")]
+    #[test]
+    fn well_known_ordinals_are_named() {
+        let raw = import_image(&["WS2_32.dll"], &[BY_ORDINAL | 115, BY_ORDINAL | 999], false);
+        let (_, file) = File::parse(&raw).unwrap();
+        let imports = file.imports();
+
+        let startup = ImportedFunction::ByOrdinal(115);
+        assert_eq!(startup.resolve_known_ordinal("ws2_32.dll"), Some("WSAStartup"));
+        assert_eq!(startup.resolve_known_ordinal("mfc42.dll"), None);
+        assert_eq!(imports[0].to_string(), "WS2_32.WSAStartup (#115)\nWS2_32.#999\n");
+    }
+
+    #[cfg(not(feature = "known-ordinals"))]
+    #[test]
+    fn ordinals_stay_unnamed_without_the_table() {
+        let startup = ImportedFunction::ByOrdinal(115);
+        assert_eq!(startup.resolve_known_ordinal("ws2_32.dll"), None);
+    }
+
+    #[test]
+    fn import_count_matches_the_full_parse() {
+        let dlls = ["KERNEL32.dll", "WS2_32.dll", "USER32.dll"];
+        let raw = import_image(&dlls, &[BY_ORDINAL | 23, BY_ORDINAL | 3], false);
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let functions: usize = file.imports().iter().map(|import| import.functions.len()).sum();
+        assert_eq!(functions, 6);
+        assert_eq!(file.import_count(), functions);
+
+        let raw = import_image(&[], &[], false);
+        let (_, file) = File::parse(&raw).unwrap();
+        assert_eq!(file.import_count(), 0);
+    }
+}
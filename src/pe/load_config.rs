@@ -0,0 +1,142 @@
+use super::util::*;
+use super::File;
+
+/// Offset of `SecurityCookie` in the 32-bit `IMAGE_LOAD_CONFIG_DIRECTORY`,
+/// after which come the SafeSEH fields.
+const SECURITY_COOKIE_OFFSET: usize = 0x3c;
+
+/// The start of the 32-bit `IMAGE_LOAD_CONFIG_DIRECTORY`, up to the SafeSEH
+/// handler table. Later fields were added by newer toolchains and are left out.
+#[derive(Debug, PartialEq)]
+pub struct LoadConfig32 {
+    /// The size of the structure, which has grown over time. The data directory's
+    /// size is unreliable, older linkers always write `0x40` there.
+    pub size: u32,
+    pub time_date_stamp: u32,
+    /// The VA of the `/GS` stack cookie.
+    pub security_cookie: u32,
+    /// The VA of the sorted table of handler RVAs registered with `/SAFESEH`.
+    pub se_handler_table: Option<u32>,
+    pub se_handler_count: Option<u32>,
+}
+
+impl LoadConfig32 {
+    fn parse(i: Input) -> Result<Self> {
+        use nom::{
+            bytes::complete::take, combinator::cond, error::context, number::complete::le_u32,
+            sequence::tuple,
+        };
+        let (i, (size, time_date_stamp)) =
+            tuple((context("Size", le_u32), context("TimeDateStamp", le_u32)))(i)?;
+        let (i, _) = take(SECURITY_COOKIE_OFFSET - 8)(i)?;
+        let (i, security_cookie) = context("SecurityCookie", le_u32)(i)?;
+
+        // Structures from before SafeSEH end at the cookie.
+        let has_seh = size as usize >= SECURITY_COOKIE_OFFSET + 12;
+        let (i, (se_handler_table, se_handler_count)) = tuple((
+            context("SEHandlerTable", cond(has_seh, le_u32)),
+            context("SEHandlerCount", cond(has_seh, le_u32)),
+        ))(i)?;
+        Ok((
+            i,
+            Self {
+                size,
+                time_date_stamp,
+                security_cookie,
+                se_handler_table,
+                se_handler_count,
+            },
+        ))
+    }
+}
+
+impl File {
+    /// Parses the load configuration of a PE32 image. `None` for PE32+ images,
+    /// whose layout differs, and when there's no load config directory.
+    #[allow(dead_code)]
+    pub fn load_config32(&self) -> Option<LoadConfig32> {
+        let oh = self.header.optional_header.as_ref()?;
+        oh.base_of_data?;
+        let dir = &oh.data_directories.load_config_table;
+        if !dir.is_present() {
+            return None;
+        }
+        let rva = dir.virtual_addr.0;
+        let size = self.read_at_rva(rva, 4)?;
+        let size = u32::from_le_bytes([size[0], size[1], size[2], size[3]]);
+        let bytes = self.read_at_rva(rva, size as usize)?;
+        LoadConfig32::parse(&bytes).ok().map(|(_, lc)| lc)
+    }
+
+    /// The RVAs of the exception handlers registered with `/SAFESEH`, which are
+    /// the only ones the loader lets a 32-bit image dispatch to.
+    ///
+    /// `None` for PE32+ images, which have table-based exception handling instead,
+    /// and for images built without SafeSEH.
+    #[allow(dead_code)]
+    pub fn safe_seh_handlers(&self) -> Option<Vec<u32>> {
+        let lc = self.load_config32()?;
+        let table = lc.se_handler_table?;
+        let count = lc.se_handler_count?;
+        let image_base = self.header.optional_header.as_ref()?.windows_header.image_base;
+        let rva = u64::from(table).checked_sub(image_base)? as u32;
+        let bytes = self.read_at_rva(rva, (count as usize).checked_mul(4)?)?;
+        Some(
+            bytes
+                .chunks_exact(4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::constants::DataDirectoryKind;
+    use super::super::fixtures::*;
+    use super::*;
+
+    /// Where the fixtures' load config is mapped.
+    const LOAD_CONFIG: u32 = 0x2000;
+
+    /// A PE32 image with a load config of `size` bytes, registering `handlers`
+    /// in a table right after it if the structure is large enough to say so.
+    fn safe_seh_image(builder: PeBuilder, size: u32, handlers: &[u32]) -> Vec<u8> {
+        let mut rdata = vec![0; size as usize];
+        put(&mut rdata, 0, &size.to_le_bytes());
+        put(&mut rdata, SECURITY_COOKIE_OFFSET, &0x40_3000_u32.to_le_bytes());
+        if size >= 0x48 {
+            let table = builder.image_base as u32 + LOAD_CONFIG + size;
+            put(&mut rdata, SECURITY_COOKIE_OFFSET + 4, &table.to_le_bytes());
+            put(&mut rdata, SECURITY_COOKIE_OFFSET + 8, &(handlers.len() as u32).to_le_bytes());
+        }
+        for handler in handlers {
+            rdata.extend_from_slice(&handler.to_le_bytes());
+        }
+        builder
+            .section(".text", 0x1000, vec![0xc3; 0x100], CODE)
+            .section(".rdata", LOAD_CONFIG, rdata, RDATA)
+            .directory(DataDirectoryKind::LoadConfig, LOAD_CONFIG, 0x40)
+            .build()
+    }
+
+    #[test]
+    fn safe_seh_handlers_are_listed() {
+        let raw = safe_seh_image(PeBuilder::pe32(), 0x48, &[0x1010, 0x1040]);
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let lc = file.load_config32().unwrap();
+        assert_eq!(lc.security_cookie, 0x40_3000);
+        assert_eq!(lc.se_handler_count, Some(2));
+        assert_eq!(file.safe_seh_handlers(), Some(vec![0x1010, 0x1040]));
+
+        // From before SafeSEH, ending at the cookie.
+        let raw = safe_seh_image(PeBuilder::pe32(), 0x40, &[]);
+        let (_, file) = File::parse(&raw).unwrap();
+        assert_eq!(file.load_config32().unwrap().se_handler_table, None);
+        assert_eq!(file.safe_seh_handlers(), None);
+
+        let raw = safe_seh_image(PeBuilder::default(), 0x48, &[0x1010]);
+        assert_eq!(File::parse(&raw).unwrap().1.safe_seh_handlers(), None);
+    }
+}
@@ -0,0 +1,70 @@
+use super::File;
+
+/// Escapes characters that would break out of a Markdown table cell.
+fn cell(s: &str) -> String {
+    s.replace('|', "\\|")
+}
+
+impl File {
+    /// Renders the headers and section table as GitHub-flavored Markdown tables,
+    /// for pasting into issues and wikis.
+    pub fn to_markdown(&self) -> String {
+        let header = &self.header;
+        let mut rows = vec![
+            ("Machine", format!("{:?}", header.machine)),
+            ("Sections", header.number_of_sections.to_string()),
+            ("Time Date Stamp", format!("{:#x}", header.time_date_stamp)),
+            ("Characteristics", format!("{:?}", header.characteristics)),
+        ];
+        if let Some(oh) = &header.optional_header {
+            let wh = &oh.windows_header;
+            rows.extend(vec![
+                ("Entry Point", format!("{:#x}", oh.entry_point.0)),
+                ("Image Base", format!("{:#x}", wh.image_base)),
+                ("Subsystem", format!("{:?}", wh.subsystem)),
+                ("DLL Characteristics", format!("{:?}", wh.dll_characteristics)),
+                ("Size of Image", format!("{:#x}", wh.size_of_image)),
+                ("Size of Headers", format!("{:#x}", wh.size_of_headers)),
+                ("Checksum", format!("{:#x}", wh.checksum)),
+            ]);
+        }
+
+        let mut out = String::from("| Field | Value |\n| --- | --- |\n");
+        for (field, value) in rows {
+            out += &format!("| {} | `{}` |\n", field, cell(&value));
+        }
+
+        out += "\n| Name | VA | Virtual Size | Raw Offset | Raw Size | Permissions |\n";
+        out += "| --- | --- | --- | --- | --- | --- |\n";
+        for section in &self.sections {
+            out += &format!(
+                "| `{}` | `{:#x}` | `{:#x}` | `{:#x}` | `{:#x}` | `{}` |\n",
+                cell(section.name.as_str()),
+                section.virtual_address.0,
+                section.virtual_size,
+                section.pointer_to_raw_data.0,
+                section.size_of_raw_data,
+                section.permissions(),
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::*;
+    use super::*;
+
+    #[test]
+    fn sections_are_rendered_as_a_table() {
+        let mut builder = PeBuilder::default().section(".text", 0x1000, vec![0xc3; 0x10], CODE);
+        builder.sections[0].name = name("a|b");
+        let (_, file) = File::parse(&builder.build()).unwrap();
+        let markdown = file.to_markdown();
+
+        assert!(markdown.starts_with("| Field | Value |\n| --- | --- |\n"));
+        assert!(markdown.contains("\n| Name | VA | Virtual Size | Raw Offset | Raw Size | Permissions |\n"));
+        assert!(markdown.contains("| `a\\|b` | `0x1000` | `0x10` | `0x400` | `0x10` | `R-X` |\n"));
+    }
+}
@@ -0,0 +1,98 @@
+use super::sections::Characteristics;
+use super::util::*;
+use super::File;
+
+/// A run of pages the loader maps with the same protection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemRegion {
+    /// The virtual address the region starts at, with the image at its preferred base.
+    pub start: u64,
+    pub size: u32,
+    /// Only `READ`, `WRITE` and `EXECUTE` are ever set.
+    pub permissions: Characteristics,
+}
+
+impl MemRegion {
+    pub fn end(&self) -> u64 {
+        self.start.wrapping_add(u64::from(self.size))
+    }
+}
+
+impl File {
+    /// The protection of each part of the image once mapped at its preferred
+    /// base, as an emulator would set it up: the headers read-only, then each
+    /// section by its characteristics, sized up to `SectionAlignment`.
+    /// Neighbouring regions with the same protection are merged.
+    ///
+    /// Empty for object files, which aren't mapped.
+    #[allow(dead_code)]
+    pub fn memory_map(&self) -> Vec<MemRegion> {
+        let wh = match &self.header.optional_header {
+            Some(oh) => &oh.windows_header,
+            None => return Vec::new(),
+        };
+        let alignment = wh.section_alignment;
+        let protection = Characteristics::READ | Characteristics::WRITE | Characteristics::EXECUTE;
+
+        let headers = MemRegion {
+            start: wh.image_base,
+            size: align_up(wh.size_of_headers, alignment),
+            permissions: Characteristics::READ,
+        };
+        let sections = self.sections.iter().map(|section| {
+            // The loader falls back to the raw size when the virtual size is zero.
+            let size = match section.virtual_size {
+                0 => section.size_of_raw_data,
+                size => size,
+            };
+            MemRegion {
+                start: wh.image_base.wrapping_add(u64::from(section.virtual_address.0)),
+                size: align_up(size, alignment),
+                permissions: section.characteristics & protection,
+            }
+        });
+
+        let mut regions: Vec<MemRegion> = Vec::new();
+        for region in std::iter::once(headers).chain(sections) {
+            match regions.last_mut() {
+                Some(last) if last.end() == region.start && last.permissions == region.permissions => {
+                    last.size = last.size.wrapping_add(region.size);
+                }
+                _ => regions.push(region),
+            }
+        }
+        regions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::*;
+    use super::*;
+
+    #[test]
+    fn sections_map_with_their_protection() {
+        let raw = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x10], CODE)
+            .section(".rdata", 0x2000, vec![1; 0x10], RDATA)
+            .section(".pdata", 0x3000, vec![2; 0x1800], RDATA)
+            .section(".data", 0x5000, vec![3; 0x10], DATA)
+            .build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        use Characteristics as C;
+        let region = |start, size, permissions| MemRegion { start, size, permissions };
+        assert_eq!(
+            file.memory_map(),
+            [
+                region(0x1_4000_0000, 0x1000, C::READ),
+                region(0x1_4000_1000, 0x1000, C::READ | C::EXECUTE),
+                region(0x1_4000_2000, 0x3000, C::READ),
+                region(0x1_4000_5000, 0x1000, C::READ | C::WRITE),
+            ]
+        );
+
+        let raw = object(0x8664, &[(".text", &[0xc3], CODE)]);
+        assert_eq!(File::parse(&raw).unwrap().1.memory_map(), []);
+    }
+}
@@ -1,13 +1,49 @@
 #[macro_use]
 pub mod util;
+pub mod constants;
+mod debug;
+mod dotnet;
+mod exceptions;
+mod exports;
+mod fields;
+#[cfg(test)]
+mod fixtures;
+mod fingerprint;
+mod hash;
 mod header;
+mod imports;
+mod load_config;
+mod markdown;
+mod memory;
+#[cfg(feature = "known-ordinals")]
+mod ordinals;
+mod overlay;
+pub mod parsed;
+mod relocations;
+mod report;
+mod resources;
+mod rich;
 mod sections;
+mod strings;
+mod symbols;
+mod verify;
+mod write;
 
-use header::PeHeader64;
+pub use fields::FIELD_NAMES;
+use header::{OptionalHeader64, PeHeader64};
+pub use report::ReportBuilder;
+use rich::RichHeader;
 use sections::Section;
+use symbols::Symbol;
 use util::*;
 
-/// Represents an entire PE64 file.
+#[cfg(feature = "hashes")]
+use std::collections::BTreeMap;
+
+/// DLLs only kernel-mode code imports from, lowercased.
+const KERNEL_DLLS: &[&str] = &["ntoskrnl.exe", "hal.dll", "ndis.sys", "wdfldr.sys", "fltmgr.sys"];
+
+/// Represents an entire PE32 or PE32+ file, or a COFF object.
 ///
 /// Note: currently does not `fmt::Display` all fields by default,
 /// such as alignment numbers.
@@ -15,17 +51,116 @@ use util::*;
 pub struct File {
     pub header: PeHeader64,
     pub sections: Vec<Section>,
+    pub symbols: Vec<Symbol>,
+    pub rich_header: Option<RichHeader>,
+}
+
+/// The failures callers may want to tell apart, rather than read from
+/// [`ParseError::contexts`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// `e_lfanew` doesn't lead to a `PE\0\0` signature. `at` is where it points,
+    /// or the offset of `e_lfanew` itself if that's past the end of the input.
+    PeSignatureNotFound { at: usize },
+    /// The input ends inside the header starting at `at`.
+    TooSmall { at: usize },
+}
+
+/// An owned summary of a nom error, which otherwise borrows the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The file offset parsing failed at.
+    pub offset: usize,
+    /// The parser contexts that failed, innermost first.
+    pub contexts: Vec<String>,
+    /// `None` for failures that are only described by their contexts.
+    pub kind: Option<ParseErrorKind>,
+}
+
+impl ParseError {
+    fn new(full_input: Input, err: nom::error::VerboseError<Input>) -> Self {
+        use nom::{error::VerboseErrorKind, Offset};
+        let offset = err.errors.first().map_or(0, |(input, _)| full_input.offset(input));
+        let kind = err.errors.iter().find_map(|(input, kind)| {
+            let at = full_input.offset(input);
+            match kind {
+                VerboseErrorKind::Context(header::PE_SIGNATURE_NOT_FOUND) => {
+                    Some(ParseErrorKind::PeSignatureNotFound { at })
+                }
+                VerboseErrorKind::Context(header::TOO_SMALL) => Some(ParseErrorKind::TooSmall { at }),
+                _ => None,
+            }
+        });
+        let contexts = err
+            .errors
+            .iter()
+            .map(|(_, kind)| match kind {
+                VerboseErrorKind::Context(context) => context.to_string(),
+                kind => format!("{:?}", kind),
+            })
+            .collect();
+        Self { offset, contexts, kind }
+    }
+
+    /// For `nom::Err::Incomplete`, which the `complete` parsers used here don't
+    /// return, but which isn't worth a panic: reported at the end of the input.
+    fn incomplete(full_input: Input) -> Self {
+        Self {
+            offset: full_input.len(),
+            contexts: vec!["Incomplete".to_string()],
+            kind: None,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "parsing failed at {:#x}: {}", self.offset, self.contexts.join(" in "))
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// What [`File::parse_with`] reads beyond the headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Copy each section's raw data into [`Section::data`]. Without it `data` is left
+    /// empty, which makes scanning large numbers of files for header metadata
+    /// much cheaper; the raw data is still checked to lie within the file.
+    /// Anything read through RVAs (imports, resources, ...) is then unavailable.
+    pub load_section_data: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self { load_section_data: true }
+    }
+}
+
+/// Why a section was left out by [`File::parse_lenient`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionError {
+    /// Position of the entry in the section table.
+    pub index: usize,
+    pub error: ParseError,
 }
 
 impl File {
+    #[allow(dead_code)]
     pub fn parse_or_print_error(i: Input) -> Option<Self> {
-        match Self::parse(i) {
+        Self::parse_at_or_print_error(i, 0, &ParseOptions::default())
+    }
+
+    /// Like [`File::parse_or_print_error`], but for an image embedded `offset` bytes
+    /// into `raw`. Reported positions are relative to `raw`, not the embedded image.
+    pub fn parse_at_or_print_error(raw: Input, offset: usize, options: &ParseOptions) -> Option<Self> {
+        match Self::parse_at_with(raw, offset, options) {
             Ok((_, file)) => Some(file),
             Err(nom::Err::Failure(err)) | Err(nom::Err::Error(err)) => {
                 eprintln!("Parsing failed:");
                 use nom::Offset;
                 for (input, err) in err.errors {
-                    let offset = i.offset(input);
+                    let offset = raw.offset(input);
                     eprintln!("{:?} at position {}:", err, offset);
                     eprintln!("{:>08x}: {:?}", offset, HexDump(input));
                 }
@@ -35,99 +170,776 @@ impl File {
         }
     }
 
+    /// Parses a PE embedded `offset` bytes into `raw`, e.g. inside a memory dump.
+    ///
+    /// File offsets in the headers (such as `pointer_to_raw_data`) are taken
+    /// relative to the start of the embedded image, as they would be had it been
+    /// carved out first.
+    pub fn parse_at(raw: Input, offset: usize) -> Result<Self> {
+        Self::parse_at_with(raw, offset, &ParseOptions::default())
+    }
+
+    pub fn parse_at_with<'a>(raw: Input<'a>, offset: usize, options: &ParseOptions) -> Result<'a, Self> {
+        use nom::{bytes::complete::take, error::context};
+        let (i, _) = context("Offset", take(offset))(raw)?;
+        Self::parse_with(i, options)
+    }
+
+    #[allow(dead_code)]
     pub fn parse(i: Input) -> Result<Self> {
-        let full_input = i;
-        // This need to be done in this ugly way so we can use the value from the header to determine
-        // how many sections to parse.
-        let (i, header) = nom::error::context("Header", header::PeHeader64::parse)(i)?;
+        Self::parse_with(i, &ParseOptions::default())
+    }
 
-        let sec_count = header.number_of_sections() as usize;
-        let _image_base = header.optional_header.windows_header.image_base;
+    pub fn parse_with<'a>(i: Input<'a>, options: &ParseOptions) -> Result<'a, Self> {
+        let full_input = i;
+        let (i, (header, table)) = Self::parse_header_and_section_table(i)?;
 
         // As we know the size of a section header (40 bytes), we can
-        let slices = (&i).chunks(40);
+        let slices = table.chunks_exact(40);
         let mut sections = Vec::new();
-        for slice in slices.take(sec_count) {
-            let (_, sec) = sections::Section::parse(full_input, slice)?;
+        for slice in slices {
+            let (_, sec) = sections::Section::parse(
+                full_input,
+                slice,
+                header.endianness,
+                options.load_section_data,
+            )?;
             sections.push(sec);
         }
 
-        Ok((i, Self { header, sections }))
+        let (_, symbols) = Symbol::parse_table(full_input, &header)?;
+
+        Ok((
+            i,
+            Self {
+                header,
+                sections,
+                symbols,
+                rich_header: RichHeader::find(full_input),
+            },
+        ))
     }
-}
 
+    /// Like [`File::parse`], but for triaging damaged files: a section that fails
+    /// to parse is left out and its error returned alongside, instead of failing
+    /// the whole file. A broken symbol table is likewise treated as empty.
+    ///
+    /// The headers and section table themselves still have to parse.
+    #[allow(dead_code)]
+    pub fn parse_lenient(i: Input) -> Result<(Self, Vec<SectionError>)> {
+        let full_input = i;
+        let (i, (header, table)) = Self::parse_header_and_section_table(i)?;
 
-use std::fmt;
+        let mut sections = Vec::new();
+        let mut errors = Vec::new();
+        for (index, slice) in table.chunks_exact(40).enumerate() {
+            match sections::Section::parse(full_input, slice, header.endianness, true) {
+                Ok((_, sec)) => sections.push(sec),
+                Err(nom::Err::Error(err)) | Err(nom::Err::Failure(err)) => {
+                    errors.push(SectionError {
+                        index,
+                        error: ParseError::new(full_input, err),
+                    })
+                }
+                Err(nom::Err::Incomplete(_)) => errors.push(SectionError {
+                    index,
+                    error: ParseError::incomplete(full_input),
+                }),
+            }
+        }
+
+        let symbols = Symbol::parse_table(full_input, &header).map_or_else(|_| Vec::new(), |(_, s)| s);
+
+        Ok((
+            i,
+            (
+                Self {
+                    header,
+                    sections,
+                    symbols,
+                    rich_header: RichHeader::find(full_input),
+                },
+                errors,
+            ),
+        ))
+    }
+
+    /// This need to be done in this ugly way so we can use the value from the header to determine
+    /// how many sections to parse.
+    fn parse_header_and_section_table(i: Input) -> Result<(PeHeader64, Input)> {
+        use nom::{bytes::complete::take, error::context};
+        let full_input = i;
+        let (i, header) = context("Header", header::PeHeader64::parse)(i)?;
+
+        let sec_count = header.number_of_sections() as usize;
+
+        // The table follows wherever `SizeOfOptionalHeader` says the optional
+        // header ends, which needn't be where the fields we parse do.
+        let table_offset = header.coff_offset + 20 + header.size_of_optional_header as usize;
+        let (table, _) = context("SizeOfOptionalHeader", take(table_offset))(full_input)?;
+        // Taking the whole table up front means a header claiming more sections
+        // than the file holds fails here, rather than yielding a short list.
+        let (_, table) = context("SectionTable", take(sec_count * 40))(table)?;
+        Ok((i, (header, table)))
+    }
+
+    /// The section at position `index` in the section table, if there's one.
+    pub fn section_at(&self, index: usize) -> Option<&Section> {
+        self.sections.get(index)
+    }
+
+    /// Shannon entropy of the whole of `raw`, headers and overlay included,
+    /// in bits per byte (0 to 8).
+    pub fn file_entropy(raw: &[u8]) -> f64 {
+        hash::entropy(raw)
+    }
+
+    /// Every section's protection on one line, e.g. `.text R-X, .data RW-`,
+    /// for a glance at what's writable or executable.
+    #[allow(dead_code)]
+    pub fn permissions_summary(&self) -> String {
+        self.sections
+            .iter()
+            .map(|s| format!("{} {}", s.name, s.permissions()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Returns the section whose virtual range contains `rva`, if any.
+    pub fn section_for_rva(&self, rva: u32) -> Option<&Section> {
+        self.sections.iter().find(|s| s.contains_rva(rva))
+    }
+
+    /// Returns the section whose raw data contains the file offset `offset`, if any,
+    /// e.g. to place a match from a scanner run over the file.
+    #[allow(dead_code)]
+    pub fn section_for_offset(&self, offset: usize) -> Option<&Section> {
+        use std::convert::TryFrom;
+        let offset = u32::try_from(offset).ok()?;
+        self.sections.iter().find(|s| s.contains_offset(offset))
+    }
+
+    /// The sections whose characteristics include all of `flags`, in table order,
+    /// e.g. every executable or every discardable section.
+    #[allow(dead_code)]
+    pub fn sections_with(&self, flags: sections::Characteristics) -> Vec<&Section> {
+        self.sections
+            .iter()
+            .filter(|s| s.characteristics.contains(flags))
+            .collect()
+    }
+
+    /// Returns the section containing the entry point.
+    ///
+    /// An entry point of zero means there is none (common for resource-only DLLs),
+    /// so this returns `None` rather than resolving RVA 0 into the headers.
+    pub fn entry_point_section(&self) -> Option<&Section> {
+        match self.header.optional_header.as_ref()?.entry_point.0 {
+            0 => None,
+            rva => self.section_for_rva(rva),
+        }
+    }
+
+    /// The absolute address of the entry point with the image loaded at its
+    /// preferred base. `None` for object files and when there's no entry point.
+    pub fn entry_point_va(&self) -> Option<u64> {
+        let oh = self.header.optional_header.as_ref()?;
+        match oh.entry_point.0 {
+            0 => None,
+            rva => Some(oh.windows_header.image_base.wrapping_add(u64::from(rva))),
+        }
+    }
+
+    /// Up to `n` bytes of raw data from the entry point on, for matching packer
+    /// and compiler stubs. Cut short at the end of the section's raw data.
+    #[allow(dead_code)]
+    pub fn entry_point_bytes(&self, n: usize) -> Option<&[u8]> {
+        let section = self.entry_point_section().filter(|s| s.is_data_loaded())?;
+        let rva = self.header.optional_header.as_ref()?.entry_point.0;
+        let start = (rva - section.virtual_address.0) as usize;
+        let data = section.data.get(start..)?;
+        Some(&data[..n.min(data.len())])
+    }
+
+    /// Reads `len` bytes at `rva`, resolving it through the section table
+    /// rather than requiring the whole image to be mapped.
+    ///
+    /// Bytes past a section's raw data but within its virtual size read as zero,
+    /// as they would once loaded. Returns `None` if `rva` isn't in any section,
+    /// the range runs past the end of the section it starts in, or the
+    /// section's data wasn't loaded.
+    #[allow(dead_code)]
+    pub fn read_at_rva(&self, rva: u32, len: usize) -> Option<Vec<u8>> {
+        let section = self.section_for_rva(rva).filter(|s| s.is_data_loaded())?;
+        let start = (rva - section.virtual_address.0) as usize;
+        let end = start.checked_add(len)?;
+        if end > section.virtual_size as usize {
+            return None;
+        }
+
+        let mut bytes = vec![0; len];
+        if start < section.data.len() {
+            let backed = &section.data[start..end.min(section.data.len())];
+            bytes[..backed.len()].copy_from_slice(backed);
+        }
+        Some(bytes)
+    }
+
+    /// Reads the NUL-terminated string at `rva`, as used for DLL and symbol names.
+    ///
+    /// Like [`File::read_at_rva`] this stays within the section `rva` is in;
+    /// a string running off the end of the raw data is cut short there.
+    pub fn read_c_string_at_rva(&self, rva: u32) -> Option<String> {
+        self.read_c_string_at_rva_within(rva, usize::MAX)
+    }
+
+    /// Like [`File::read_c_string_at_rva`], but gives up looking for the NUL
+    /// after `max` bytes, for tables of names that may all be unterminated.
+    pub fn read_c_string_at_rva_within(&self, rva: u32, max: usize) -> Option<String> {
+        let section = self.section_for_rva(rva).filter(|s| s.is_data_loaded())?;
+        let bytes = section.data.get((rva - section.virtual_address.0) as usize..)?;
+        let bytes = &bytes[..bytes.len().min(max)];
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        Some(String::from_utf8_lossy(&bytes[..end]).to_string())
+    }
+
+    /// The file offset of the optional header's `CheckSum` field, for tools that
+    /// patch in a recomputed checksum after modifying the image.
+    /// `None` for object files, which have no optional header.
+    #[allow(dead_code)]
+    pub fn checksum_field_offset(&self) -> Option<usize> {
+        // The checksum sits at the same place in PE32 and PE32+ optional headers.
+        const CHECKSUM_OFFSET: usize = 64;
+        self.header.optional_header.as_ref()?;
+        Some(self.header.coff_offset + 20 + CHECKSUM_OFFSET)
+    }
+
+    /// The size of the addresses in thunks and other pointer-sized fields: 4 bytes
+    /// in PE32 images, 8 in PE32+, going by the optional header magic.
+    /// Object files go by their machine instead.
+    pub fn thunk_size(&self) -> usize {
+        match self.optional_header_magic() {
+            Some(OptionalHeader64::PE32_MAGIC) => 4,
+            Some(_) => 8,
+            None => self.header.machine.pointer_size(),
+        }
+    }
+
+    /// The optional header's magic, which tells PE32 and PE32+ images apart.
+    /// `None` for object files, which have no optional header.
+    pub fn optional_header_magic(&self) -> Option<u16> {
+        Some(self.header.optional_header.as_ref()?.magic)
+    }
+
+    /// Whether this is a 64-bit (PE32+) image. `false` for object files.
+    #[allow(dead_code)]
+    pub fn is_pe32_plus(&self) -> bool {
+        self.optional_header_magic() == Some(OptionalHeader64::PE32PLUS_MAGIC)
+    }
+
+    /// `e_lfanew`, the offset of the `PE\0\0` signature from the MS-DOS header.
+    /// `None` for object files, which have neither.
+    pub fn e_lfanew(&self) -> Option<usize> {
+        self.header.coff_offset.checked_sub(4)
+    }
+
+    /// The COFF header's characteristics.
+    #[allow(dead_code)]
+    pub fn characteristics(&self) -> header::Characteristics {
+        self.header.characteristics
+    }
+
+    /// The optional header's DLL characteristics, which despite the name apply
+    /// to executables too. `None` for object files, which have no optional header.
+    #[allow(dead_code)]
+    pub fn dll_characteristics(&self) -> Option<header::DllCharacteristics> {
+        Some(self.header.optional_header.as_ref()?.windows_header.dll_characteristics)
+    }
+
+    /// `Win32VersionValue`, which is reserved and must be zero.
+    /// `None` for object files, which have no optional header.
+    pub fn win32_version_value(&self) -> Option<u32> {
+        Some(self.header.optional_header.as_ref()?.windows_header.win32_version_value)
+    }
+
+    /// `LoaderFlags`, which is reserved and must be zero.
+    /// `None` for object files, which have no optional header.
+    pub fn loader_flags(&self) -> Option<u32> {
+        Some(self.header.optional_header.as_ref()?.windows_header.loader_flags)
+    }
 
-fn display_version<T: fmt::Display>(major: T, minor: T) -> String {
-    format!("{}.{}", major, minor)
+    /// Whether the image carries an Authenticode signature, without parsing
+    /// or validating the PKCS#7 blob itself.
+    #[allow(dead_code)]
+    pub fn is_signed(&self) -> bool {
+        self.header
+            .optional_header
+            .as_ref()
+            .is_some_and(|oh| oh.data_directories.certificate_table.is_present())
+    }
+
+    /// Whether this looks like a kernel-mode driver: a `Native` image that is
+    /// flagged as a WDM driver or imports from the kernel, rather than a native
+    /// user-mode process such as `smss.exe`, which links against `ntdll.dll`.
+    ///
+    /// The subsystem version isn't checked: the linker sets it to the targeted
+    /// Windows version for drivers and user-mode images alike (`10.0`, say, or
+    /// `6.1`), so it tells the two apart no better than the subsystem does.
+    #[allow(dead_code)]
+    pub fn is_kernel_driver(&self) -> bool {
+        let oh = match &self.header.optional_header {
+            Some(oh) if oh.windows_header.subsystem == header::Subsystem::Native => oh,
+            _ => return false,
+        };
+        let dlls: Vec<String> = self
+            .imported_dlls()
+            .iter()
+            .map(|dll| dll.to_lowercase())
+            .collect();
+        if dlls.iter().any(|dll| dll == "ntdll.dll") {
+            return false;
+        }
+        oh.windows_header
+            .dll_characteristics
+            .contains(header::DllCharacteristics::IMAGE_DLLCHARACTERISTICS_WDM_DRIVER)
+            || dlls.iter().any(|dll| KERNEL_DLLS.contains(&dll.as_str()))
+    }
+
+    /// The linker directives (e.g. `/DEFAULTLIB:...`) an object file
+    /// carries in its `.drectve` section.
+    #[allow(dead_code)]
+    pub fn linker_directives(&self) -> Option<String> {
+        let section = self.sections.iter().find(|s| {
+            s.name.as_str() == ".drectve"
+                && s.characteristics
+                    .contains(sections::Characteristics::IMAGE_SCN_LNK_INFO)
+        })?;
+        // Directives are ASCII, unless they start with a UTF-8 BOM.
+        let text = section
+            .data
+            .strip_prefix(&[0xEF, 0xBB, 0xBF])
+            .unwrap_or(&section.data);
+        Some(
+            String::from_utf8_lossy(text)
+                .trim_end_matches(|c: char| c == '\0' || c.is_whitespace())
+                .to_string(),
+        )
+    }
+
+    /// Maps each section name to the SHA-256 of its raw data, for spotting
+    /// which sections changed between two builds.
+    ///
+    /// Repeated names get a `#N` suffix (`.text`, `.text#1`, ...) so no
+    /// section is silently dropped from the map.
+    #[cfg(feature = "hashes")]
+    pub fn section_hashes(&self) -> BTreeMap<String, String> {
+        let mut hashes = BTreeMap::new();
+        for section in &self.sections {
+            let name = section.name.to_string();
+            let mut key = name.clone();
+            let mut n = 0;
+            while hashes.contains_key(&key) {
+                n += 1;
+                key = format!("{}#{}", name, n);
+            }
+            hashes.insert(key, hash::to_hex(&hash::sha256(&section.data)));
+        }
+        hashes
+    }
 }
 
+
+use std::fmt;
+
 impl fmt::Display for File {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.header_report(ReportBuilder::new()))?;
+        if let Some(table) = self.data_directory_table() {
+            write!(f, "\nData Directories: \n{}", table)?;
+        }
+        write!(f, "\nSections: \n{:#?}\n", self.sections)?;
+        let imports = self.imports();
+        if !imports.is_empty() {
+            writeln!(f, "\nImports: ")?;
+            for import in imports {
+                write!(f, "{}", import)?;
+            }
+        }
+        if !self.symbols.is_empty() {
+            write!(f, "\nSymbols: \n{:#?}\n", self.symbols)?;
+        }
+        Ok(())
+    }
+}
 
+#[cfg(test)]
+mod tests {
+    use super::fixtures::*;
+    use super::{File, ParseOptions};
+
+    #[test]
+    fn read_at_rva_stays_within_a_section() {
+        let raw = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xcc; 0x10], CODE)
+            .section(".data", 0x2000, vec![1, 2, 3, 4], DATA)
+            .build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.read_at_rva(0x2001, 2), Some(vec![2, 3]));
+        assert_eq!(file.read_at_rva(0x1000, 0x10), Some(vec![0xcc; 0x10]));
+        // Across the end of .text, even though .data's RVAs aren't far off.
+        assert_eq!(file.read_at_rva(0x100c, 8), None);
+        assert_eq!(file.read_at_rva(0x1ffe, 4), None);
+        assert_eq!(file.read_at_rva(0x5000, 1), None);
+    }
 
-        let coff_header = format!(
-            "\
-            Architecture: {:?}\n\
-            No. of Sections: {}\n\
-            Characteristics: {:?}\n",
-            self.header.machine,
-            self.header.number_of_sections,
-            self.header.characteristics,
+    #[test]
+    fn section_lookup_survives_a_virtual_size_past_4gb() {
+        let mut builder = PeBuilder::default().section(".text", 0x1000, vec![0; 0x10], CODE);
+        builder.sections[0].virtual_size = 0xffff_fff0;
+        let raw = builder.build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.section_for_rva(0xffff_ffff).map(|s| s.name.as_str()), Some(".text"));
+        assert!(file.section_for_rva(0x800).is_none());
+        assert_eq!(file.read_at_rva(0x1000, 2), Some(vec![0, 0]));
+    }
+
+    fn native_image(dlls: &[&str]) -> Vec<u8> {
+        let builder = PeBuilder {
+            subsystem: 1,
+            ..PeBuilder::default()
+        };
+        builder
+            .section(".text", 0x1000, vec![0xc3; 0x10], CODE)
+            .imports(0x2000, dlls, &[BY_ORDINAL | 1], false)
+            .build()
+    }
+
+    #[test]
+    fn drivers_are_native_images_importing_from_the_kernel() {
+        let driver = native_image(&["ntoskrnl.exe", "HAL.dll"]);
+        let (_, file) = File::parse(&driver).unwrap();
+        assert!(file.is_kernel_driver());
+
+        let smss = native_image(&["ntdll.dll"]);
+        let (_, file) = File::parse(&smss).unwrap();
+        assert!(!file.is_kernel_driver());
+
+        let exe = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x10], CODE)
+            .imports(0x2000, &["ntoskrnl.exe"], &[BY_ORDINAL | 1], false)
+            .build();
+        let (_, file) = File::parse(&exe).unwrap();
+        assert!(!file.is_kernel_driver());
+    }
+
+    #[test]
+    fn a_zero_entry_point_is_no_entry_point() {
+        let builder = PeBuilder {
+            // IMAGE_FILE_DLL | IMAGE_FILE_EXECUTABLE_IMAGE | IMAGE_FILE_LARGE_ADDRESS_AWARE
+            characteristics: 0x2022,
+            ..PeBuilder::default()
+        };
+        // A section at RVA 0 mustn't make the headers look like code.
+        let raw = builder.section(".hdr", 0, vec![0; 0x10], CODE).build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert!(file.entry_point_section().is_none());
+        let display = file.to_string();
+        let line = display.lines().find(|l| l.starts_with("Entry Point:")).unwrap();
+        assert!(line.ends_with(" none (RVA 0)"));
+
+        let (_, exe) = File::parse(&simple_image(vec![0xc3])).unwrap();
+        assert_eq!(exe.entry_point_section().map(|s| s.name.as_str()), Some(".text"));
+    }
+
+    #[cfg(feature = "hashes")]
+    #[test]
+    fn identical_files_have_identical_section_hashes() {
+        let build = || {
+            PeBuilder::default()
+                .section(".text", 0x1000, vec![0xc3; 0x10], CODE)
+                .section(".text", 0x2000, vec![0x90; 0x10], CODE)
+                .build()
+        };
+        let (raw, copy) = (build(), build());
+        let (_, file) = File::parse(&raw).unwrap();
+        let (_, other) = File::parse(&copy).unwrap();
+
+        let hashes = file.section_hashes();
+        assert_eq!(hashes, other.section_hashes());
+        assert_eq!(hashes.keys().collect::<Vec<_>>(), [".text", ".text#1"]);
+        assert_ne!(hashes[".text"], hashes[".text#1"]);
+    }
+
+    #[test]
+    fn images_can_be_parsed_at_an_offset() {
+        let image = simple_image(vec![0xc3; 0x10]);
+        let mut raw = vec![0xee; 256];
+        raw.extend_from_slice(&image);
+
+        let (_, file) = File::parse_at(&raw, 256).unwrap();
+        // Section data is found relative to the embedded image, not the buffer.
+        assert_eq!(file.read_at_rva(0x1000, 0x10), Some(vec![0xc3; 0x10]));
+        assert_eq!(file.header.coff_offset, File::parse(&image).unwrap().1.header.coff_offset);
+        assert!(File::parse_at(&raw, raw.len() + 1).is_err());
+    }
+
+    #[test]
+    fn signatures_are_noticed_without_parsing_them() {
+        let unsigned = PeBuilder::default().section(".text", 0x1000, vec![0xc3], CODE);
+        let signed = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3], CODE)
+            .certificate(b"\x30\x82 not really PKCS#7");
+
+        assert!(!File::parse(&unsigned.build()).unwrap().1.is_signed());
+        assert!(File::parse(&signed.build()).unwrap().1.is_signed());
+    }
+
+    #[test]
+    fn linker_directives_are_read_from_drectve() {
+        let info = super::sections::Characteristics::IMAGE_SCN_LNK_INFO;
+        let directives = b"   /DEFAULTLIB:\"LIBCMT\" /DEFAULTLIB:\"OLDNAMES\" \0";
+        let raw = object(0x8664, &[(".text", &[0xc3], CODE), (".drectve", directives, info)]);
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(
+            file.linker_directives().as_deref(),
+            Some("   /DEFAULTLIB:\"LIBCMT\" /DEFAULTLIB:\"OLDNAMES\"")
         );
+        let (_, image) = File::parse(&simple_image(vec![0xc3])).unwrap();
+        assert_eq!(image.linker_directives(), None);
+    }
 
-        let oh = &self.header.optional_header;
-        let wh = &oh.windows_header;
-        let optional_header = format!(
-        "\
-        Linker Version: {}\n\
-        Size of Code: {}KB\n\
-        Size of Init. Data: {}KB\n\
-        Size of Uninit. Data: {}KB\n\
-        Entry Point: 0x{}\n\
-        Base of Code: {}\n\
-        Image Base: 0x{}\n\
-        OS Version: {}\n\
-        Image Version: {}\n\
-        Subsystem: {:?} {}\n\
-        Size of Image: {}KB\n\
-        Size of Headers: {}KB\n\
-        Checksum: {}\n\
-        DLL Characteristics: {:#?}\n\
-        Size of Stack Reverse/Commit: {}KB:{}KB\n\
-        Size of Heap Reverse/Commit: {}KB:{}KB\n\
-        Number of Data Directories: {}\n\
-
-
-        \nSections: \n{:#?}\n\
-        ",
-        display_version(oh.major_linker_version,
-                          oh.minor_linker_version),
-            oh.size_of_code / 1024,
-            oh.size_of_initialized_data / 1024,
-            oh.size_of_uninitialized_data / 1024,
-            oh.entry_point,
-            oh.base_of_code,
-
-            wh.image_base,
-            display_version(wh.major_os_version, wh.minor_os_version),
-            display_version(wh.major_image_version, wh.minor_image_version),
-            wh.subsystem,
-            display_version(wh.major_subsystem_version, wh.minor_subsystem_version),
-            wh.size_of_image / 1024,
-            wh.size_of_headers / 1024,
-            wh.checksum,
-            wh.dll_characteristics,
-            wh.size_of_stack_reserve / 1024,
-            wh.size_of_stack_commit / 1024,
-            wh.size_of_heap_reserve / 1024,
-            wh.size_of_heap_commit / 1024,
-            wh.number_of_rva_and_sizes,
-            self.sections,
+    #[test]
+    fn a_section_table_shorter_than_declared_fails() {
+        let three = [(".text", &[0xc3][..], CODE), (".data", &[1], DATA), (".bss", &[], DATA)];
+        let mut raw = object(0x8664, &three);
+        assert_eq!(File::parse(&raw).unwrap().1.sections.len(), 3);
 
+        raw[2..4].copy_from_slice(&5_u16.to_le_bytes());
+        let err = super::parsed::ParsedFile::parse(raw).err().unwrap();
+        assert!(err.contexts.iter().any(|c| c == "SectionTable"), "{:?}", err.contexts);
+    }
+
+    #[test]
+    fn lenient_parsing_keeps_the_sections_that_parse() {
+        let builder = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x10], CODE)
+            .section(".rdata", 0x2000, vec![1; 0x10], RDATA)
+            .section(".data", 0x3000, vec![2; 0x10], DATA)
+            .section(".tls", 0x4000, vec![3; 0x10], DATA);
+        let mut raw = builder.build();
+        let pointer = builder.section_table_offset() + 40 + 20;
+        put(&mut raw, pointer, &0x7fff_0000_u32.to_le_bytes());
+
+        assert!(File::parse(&raw).is_err());
+        let (_, (file, errors)) = File::parse_lenient(&raw).unwrap();
+        let names: Vec<&str> = file.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, [".text", ".data", ".tls"]);
+        assert_eq!(file.sections[1].data, [2; 0x10]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].index, 1);
+        assert_eq!(errors[0].error.offset, pointer);
+    }
+
+    #[test]
+    fn the_checksum_field_offset_points_at_the_checksum() {
+        let builder = PeBuilder {
+            checksum: 0x1234_5678,
+            ..PeBuilder::default()
+        }
+        .section(".text", 0x1000, vec![0xc3; 0x10], CODE);
+        let raw = builder.build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let offset = file.checksum_field_offset().unwrap();
+        assert_eq!(offset, builder.e_lfanew() + 4 + 20 + 64);
+        assert_eq!(raw[offset..offset + 4], 0x1234_5678_u32.to_le_bytes());
+
+        let raw = object(0x8664, &[(".text", &[0xc3], CODE)]);
+        let (_, file) = File::parse(&raw).unwrap();
+        assert_eq!(file.checksum_field_offset(), None);
+    }
+
+    #[test]
+    fn sections_are_found_by_file_offset() {
+        let builder = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x200], CODE)
+            .section(".data", 0x2000, vec![2; 0x200], DATA);
+        let raw = builder.build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let data = builder.raw_pointers()[1] as usize;
+        let found = file.section_for_offset(data + 0x10).map(|s| s.name.as_str());
+        assert_eq!(found, Some(".data"));
+        assert_eq!(file.section_for_offset(0x100), None);
+        assert_eq!(file.section_for_offset(usize::MAX), None);
+    }
+
+    #[test]
+    fn headers_only_parsing_leaves_section_data_empty() {
+        let raw = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x10], CODE)
+            .section(".data", 0x2000, vec![1; 0x10], DATA)
+            .build();
+        let options = ParseOptions { load_section_data: false };
+        let (_, file) = File::parse_with(&raw, &options).unwrap();
+
+        assert_eq!(file.sections.len(), 2);
+        for section in &file.sections {
+            assert!(section.data.is_empty());
+            assert!(!section.is_data_loaded());
+            assert_eq!(section.size_of_raw_data, 0x10);
+        }
+        assert_eq!(file.read_at_rva(0x2000, 4), None);
+        let (_, file) = File::parse_with(&raw, &ParseOptions::default()).unwrap();
+        assert_eq!(file.sections[1].data, [1; 0x10]);
+    }
+
+    #[test]
+    fn entry_point_bytes_come_from_the_entry_point_on() {
+        let mut code = vec![0xcc; 0x10];
+        code.extend(0..0x20);
+        let raw = PeBuilder::default()
+            .section(".text", 0x1000, code, CODE)
+            .entry_point(0x1010)
+            .build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let expected: Vec<u8> = (0..16).collect();
+        assert_eq!(file.entry_point_bytes(16), Some(&expected[..]));
+        assert_eq!(file.entry_point_bytes(0x100).map(<[u8]>::len), Some(0x20));
+
+        let raw = PeBuilder::default().section(".text", 0x1000, vec![0xc3], CODE).build();
+        let (_, no_entry_point) = File::parse(&raw).unwrap();
+        assert_eq!(no_entry_point.entry_point_bytes(16), None);
+    }
+
+    #[test]
+    fn section_at_is_bounds_checked() {
+        let (_, file) = File::parse(&simple_image(vec![0xc3])).unwrap();
+
+        assert_eq!(file.section_at(0).map(|s| s.name.as_str()), Some(".text"));
+        assert!(file.section_at(1).is_none());
+        assert!(file.section_at(usize::MAX).is_none());
+    }
+
+    #[test]
+    fn the_optional_header_magic_tells_pe32_plus_apart() {
+        let (_, pe64) = File::parse(&simple_image(vec![0xc3])).unwrap();
+        assert_eq!(pe64.optional_header_magic(), Some(0x20b));
+        assert!(pe64.is_pe32_plus());
+
+        let raw = PeBuilder::pe32().section(".text", 0x1000, vec![0xc3], CODE).build();
+        let (_, pe32) = File::parse(&raw).unwrap();
+        assert_eq!(pe32.optional_header_magic(), Some(0x10b));
+        assert!(!pe32.is_pe32_plus());
+
+        let (_, object) = File::parse(&object(0x8664, &[(".text", &[0xc3], CODE)])).unwrap();
+        assert_eq!(object.optional_header_magic(), None);
+        assert!(!object.is_pe32_plus());
+    }
+
+    #[test]
+    fn the_permissions_summary_lists_every_section() {
+        let raw = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x10], CODE)
+            .section(".rdata", 0x2000, vec![0; 0x10], RDATA)
+            .section(".data", 0x3000, vec![0; 0x10], DATA)
+            .section(".reloc", 0x4000, vec![0; 0x10], RDATA)
+            .build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.permissions_summary(), ".text R-X, .rdata R--, .data RW-, .reloc R--");
+    }
+
+    #[test]
+    fn whole_file_entropy_ranges_from_zero_to_eight() {
+        assert_eq!(File::file_entropy(&[0; 0x1000]), 0.0);
+        assert_eq!(File::file_entropy(&[]), 0.0);
+        let every_byte: Vec<u8> = (0..=255).collect();
+        assert!((File::file_entropy(&every_byte) - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn objects_parse_without_an_optional_header() {
+        let raw = object(0x14c, &[(".text", &[0x55, 0xc3], CODE), (".data", &[7; 4], DATA)]);
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.header.size_of_optional_header, 0);
+        assert!(file.header.optional_header.is_none());
+        assert_eq!(file.optional_header_magic(), None);
+        assert!(file.entry_point_section().is_none());
+
+        let names: Vec<&str> = file.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, [".text", ".data"]);
+        assert_eq!(file.sections[0].data, [0x55, 0xc3]);
+    }
+
+    #[test]
+    fn flags_are_read_through_the_shortcuts() {
+        use super::header::{Characteristics, DllCharacteristics};
+
+        let builder = PeBuilder {
+            characteristics: 0x2022,
+            dll_characteristics: 0x0140,
+            ..PeBuilder::default()
+        };
+        let raw = builder.section(".text", 0x1000, vec![0xc3], CODE).build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.characteristics(), file.header.characteristics);
+        assert!(file.characteristics().contains(Characteristics::IMAGE_FILE_DLL));
+        assert_eq!(
+            file.dll_characteristics(),
+            Some(
+                DllCharacteristics::IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE
+                    | DllCharacteristics::IMAGE_DLLCHARACTERISTICS_NX_COMPAT
+            )
         );
 
-        write!(f, "{}{}", coff_header, optional_header)
+        let raw = object(0x8664, &[(".text", &[0xc3], CODE)]);
+        assert_eq!(File::parse(&raw).unwrap().1.dll_characteristics(), None);
+    }
+
+    #[test]
+    fn entry_points_are_rebased_onto_the_image_base() {
+        let (_, file) = File::parse(&simple_image(vec![0xc3])).unwrap();
+        assert_eq!(file.entry_point_va(), Some(0x1_4000_1000));
+        assert!(file.to_string().contains("(.text, VA 0x140001000)"));
+
+        let raw = PeBuilder::pe32()
+            .section(".text", 0x1000, vec![0xc3; 0x20], CODE)
+            .entry_point(0x1010)
+            .build();
+        assert_eq!(File::parse(&raw).unwrap().1.entry_point_va(), Some(0x40_1010));
+
+        let raw = PeBuilder::default().section(".text", 0x1000, vec![0xc3], CODE).build();
+        let (_, file) = File::parse(&raw).unwrap();
+        assert_eq!(file.entry_point_va(), None);
+        assert!(file.to_string().contains("none (RVA 0)"));
+    }
+
+    #[test]
+    fn sections_are_queried_by_characteristics() {
+        use super::sections::Characteristics as C;
+
+        let raw = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3], CODE)
+            .section(".data", 0x2000, vec![1], DATA)
+            .section("INIT", 0x3000, vec![0xc3], CODE | C::IMAGE_SCN_MEM_DISCARDABLE)
+            .section(".reloc", 0x4000, vec![0; 8], RDATA | C::IMAGE_SCN_MEM_DISCARDABLE)
+            .build();
+        let (_, file) = File::parse(&raw).unwrap();
+        let names = |flags| -> Vec<&str> {
+            file.sections_with(flags).iter().map(|s| s.name.as_str()).collect()
+        };
+
+        assert_eq!(names(C::EXECUTE), [".text", "INIT"]);
+        assert_eq!(names(C::IMAGE_SCN_MEM_DISCARDABLE), ["INIT", ".reloc"]);
+        assert_eq!(names(C::EXECUTE | C::IMAGE_SCN_MEM_DISCARDABLE), ["INIT"]);
+        assert_eq!(names(C::EXECUTE | C::WRITE), Vec::<&str>::new());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,216 @@
+use super::util::*;
+use super::File;
+use std::ops::Range;
+
+/// A format recognised at the start of an overlay, typically the payload
+/// of a self-extracting archive or installer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayKind {
+    Zip,
+    SevenZip,
+    Rar,
+    Cab,
+    Nsis,
+    Inno,
+}
+
+impl OverlayKind {
+    /// Identifies a format from the overlay's leading bytes.
+    pub fn sniff(overlay: &[u8]) -> Option<Self> {
+        let signatures: &[(usize, &[u8], Self)] = &[
+            (0, b"PK\x03\x04", Self::Zip),
+            (0, b"7z\xBC\xAF\x27\x1C", Self::SevenZip),
+            (0, b"Rar!\x1A\x07", Self::Rar),
+            (0, b"MSCF", Self::Cab),
+            // NSIS's first header starts with a flags dword before its signature.
+            (4, b"\xEF\xBE\xAD\xDENullsoftInst", Self::Nsis),
+            (0, b"rDlPtS", Self::Inno),
+            (0, b"idska32\x1A", Self::Inno),
+            (0, b"zlb\x1A", Self::Inno),
+        ];
+        signatures
+            .iter()
+            .find(|(at, magic, _)| overlay.get(*at..).is_some_and(|o| o.starts_with(magic)))
+            .map(|&(_, _, kind)| kind)
+    }
+}
+
+impl File {
+    /// The file offset just past the last section's raw data.
+    pub(super) fn raw_data_end(&self) -> Option<usize> {
+        self.sections
+            .iter()
+            .filter(|s| !s.is_empty_on_disk())
+            .map(|s| s.pointer_to_raw_data.0 as usize + s.size_of_raw_data as usize)
+            .max()
+    }
+
+    /// The bytes appended after the last section's raw data, which the loader ignores.
+    ///
+    /// For signed images this includes the certificate table.
+    #[allow(dead_code)]
+    pub fn overlay<'a>(&self, raw: &'a [u8]) -> Option<&'a [u8]> {
+        raw.get(self.raw_data_end()?..).filter(|overlay| !overlay.is_empty())
+    }
+
+    /// The bytes between the last section and the certificate table.
+    ///
+    /// Beyond a little alignment padding this should be empty, as data injected
+    /// here leaves the Authenticode signature valid. `None` if the image is
+    /// unsigned or there's no gap.
+    #[allow(dead_code)]
+    pub fn gap_before_signature<'a>(&self, raw: &'a [u8]) -> Option<&'a [u8]> {
+        let certificates = &self.header.optional_header.as_ref()?.data_directories.certificate_table;
+        if !certificates.is_present() {
+            return None;
+        }
+        // Unlike every other directory, this one holds a file offset rather than an RVA.
+        let start = certificates.virtual_addr.0 as usize;
+        raw.get(self.raw_data_end()?..start)
+            .filter(|gap| !gap.is_empty())
+    }
+
+    /// File ranges between the headers and the end of the last section's raw data
+    /// that no section covers, where data can sit unseen by the loader.
+    ///
+    /// The headers are taken to extend to `SizeOfHeaders` rounded up to the file
+    /// alignment, since the first section can't start before that anyway.
+    pub fn raw_gaps(&self) -> Vec<Range<usize>> {
+        let headers_end = match &self.header.optional_header {
+            Some(oh) => {
+                let wh = &oh.windows_header;
+                align_up(wh.size_of_headers, wh.file_alignment) as usize
+            }
+            None => 0,
+        };
+        let mut ranges: Vec<Range<usize>> = self
+            .sections
+            .iter()
+            .filter(|s| !s.is_empty_on_disk())
+            .map(|s| {
+                let start = s.pointer_to_raw_data.0 as usize;
+                start..start + s.size_of_raw_data as usize
+            })
+            .collect();
+        ranges.sort_by_key(|r| r.start);
+
+        let mut gaps = Vec::new();
+        let mut covered = headers_end;
+        for range in ranges {
+            if range.start > covered {
+                gaps.push(covered..range.start);
+            }
+            covered = covered.max(range.end);
+        }
+        gaps
+    }
+
+    /// Sniffs the overlay for archive and installer formats, to spot self-extractors.
+    #[allow(dead_code)]
+    pub fn overlay_kind(&self, raw: &[u8]) -> Option<OverlayKind> {
+        OverlayKind::sniff(self.overlay(raw)?)
+    }
+
+    /// Offsets in `raw` where another PE seems to start, embedded in a section
+    /// or the overlay as droppers do. Each has an `MZ` whose `e_lfanew` points
+    /// at a `PE\0\0` signature, and can be handed to [`File::parse_at`].
+    #[allow(dead_code)]
+    pub fn embedded_pe_offsets(&self, raw: &[u8]) -> Vec<usize> {
+        let read_u32 = |at: usize| {
+            let b = raw.get(at..at.checked_add(4)?)?;
+            Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+        };
+        // Past the primary image's own DOS header, if it has one.
+        let start = self.header.coff_offset.min(raw.len());
+        (start..raw.len().saturating_sub(1))
+            .filter(|&at| raw[at..].starts_with(b"MZ"))
+            .filter(|&at| {
+                read_u32(at + 0x3c)
+                    .and_then(|lfanew| at.checked_add(lfanew))
+                    .and_then(|pe| raw.get(pe..))
+                    .is_some_and(|pe| pe.starts_with(b"PE\0\0"))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::*;
+    use super::super::header::Machine;
+    use super::*;
+
+    /// An image whose only section's raw data fills exactly one file alignment
+    /// unit, so the overlay starts right after it.
+    fn with_overlay(overlay: &[u8]) -> Vec<u8> {
+        let mut builder = PeBuilder::default().section(".text", 0x1000, vec![0xc3; 0x200], CODE);
+        builder.overlay = overlay.to_vec();
+        builder.build()
+    }
+
+    #[test]
+    fn appended_archives_are_recognised() {
+        let raw = with_overlay(b"PK\x03\x04\x14\x00\x00\x00 rest of the zip");
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.overlay(&raw).map(|o| &o[..4]), Some(&b"PK\x03\x04"[..]));
+        assert_eq!(file.overlay_kind(&raw), Some(OverlayKind::Zip));
+
+        let raw = with_overlay(b"\x00\x00\x00\x00\xEF\xBE\xAD\xDENullsoftInst");
+        assert_eq!(File::parse(&raw).unwrap().1.overlay_kind(&raw), Some(OverlayKind::Nsis));
+
+        let raw = with_overlay(b"");
+        assert_eq!(File::parse(&raw).unwrap().1.overlay(&raw), None);
+    }
+
+    #[test]
+    fn bytes_injected_before_the_signature_are_found() {
+        let mut builder = PeBuilder::default().section(".text", 0x1000, vec![0xc3; 0x200], CODE);
+        builder.overlay = b"injected".to_vec();
+        let builder = builder.certificate(b"pkcs7");
+        let raw = builder.build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.gap_before_signature(&raw), Some(&b"injected"[..]));
+        let signed = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x200], CODE)
+            .certificate(b"pkcs7")
+            .build();
+        assert_eq!(File::parse(&signed).unwrap().1.gap_before_signature(&signed), None);
+    }
+
+    #[test]
+    fn a_gap_between_sections_is_found() {
+        let mut builder = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x200], CODE)
+            .section(".data", 0x2000, vec![1; 0x200], DATA);
+        builder.sections[1].pointer_to_raw_data = Some(0x800);
+        let mut raw = builder.build();
+        put(&mut raw, 0x600, &[0xcc; 0x200]);
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.raw_gaps(), vec![0x600..0x800]);
+        let anomalies: Vec<String> = file.verify().into_iter().map(|a| a.message).collect();
+        let expected = "0x200 bytes at 0x600..0x800 are not part of any section";
+        assert!(anomalies.iter().any(|m| m == expected));
+
+        let (_, file) = File::parse(&with_overlay(b"overlay")).unwrap();
+        assert!(file.raw_gaps().is_empty());
+    }
+
+    #[test]
+    fn a_pe_in_the_overlay_is_found() {
+        let dropped = PeBuilder::pe32().section(".text", 0x1000, vec![0x90; 0x10], CODE).build();
+        let raw = with_overlay(&dropped);
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let offsets = file.embedded_pe_offsets(&raw);
+        assert_eq!(offsets, [0x600]);
+        let (_, inner) = File::parse_at(&raw, offsets[0]).unwrap();
+        assert_eq!(inner.header.machine, Machine::I386);
+
+        // An `MZ` alone, without a PE signature behind it, isn't a candidate.
+        let raw = with_overlay(b"MZ not really an executable");
+        assert_eq!(File::parse(&raw).unwrap().1.embedded_pe_offsets(&raw), Vec::<usize>::new());
+    }
+}
@@ -0,0 +1,205 @@
+use super::overlay::OverlayKind;
+use super::relocations::CoffRelocation;
+use super::{File, ParseError};
+use std::ops::Deref;
+
+/// A [`File`] together with the buffer it was parsed from, so the methods that
+/// need the original bytes (overlay, serialization) don't have to be handed them.
+///
+/// Derefs to the `File` for everything else.
+#[allow(dead_code)]
+pub struct ParsedFile {
+    file: File,
+    raw: Vec<u8>,
+    /// Where the image starts within `raw`, see [`File::parse_at`].
+    offset: usize,
+}
+
+#[allow(dead_code)]
+impl ParsedFile {
+    pub fn parse(raw: Vec<u8>) -> Result<Self, ParseError> {
+        Self::parse_at(raw, 0)
+    }
+
+    pub fn parse_at(raw: Vec<u8>, offset: usize) -> Result<Self, ParseError> {
+        let file = match File::parse_at(&raw, offset) {
+            Ok((_, file)) => file,
+            Err(nom::Err::Error(err)) | Err(nom::Err::Failure(err)) => {
+                return Err(ParseError::new(&raw, err))
+            }
+            Err(nom::Err::Incomplete(_)) => return Err(ParseError::incomplete(&raw)),
+        };
+        Ok(Self { file, raw, offset })
+    }
+
+    /// The bytes of the image itself, without anything before `offset`.
+    pub fn raw(&self) -> &[u8] {
+        &self.raw[self.offset..]
+    }
+
+    pub fn into_inner(self) -> (File, Vec<u8>) {
+        (self.file, self.raw)
+    }
+
+    pub fn overlay(&self) -> Option<&[u8]> {
+        self.file.overlay(self.raw())
+    }
+
+    pub fn overlay_kind(&self) -> Option<OverlayKind> {
+        self.file.overlay_kind(self.raw())
+    }
+
+    pub fn gap_before_signature(&self) -> Option<&[u8]> {
+        self.file.gap_before_signature(self.raw())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.file.to_bytes(self.raw())
+    }
+
+    /// Like [`File::embedded_pe_offsets`], but relative to the whole buffer, so
+    /// each can be handed straight back to [`ParsedFile::parse_at`].
+    pub fn embedded_pe_offsets(&self) -> Vec<usize> {
+        let offsets = self.file.embedded_pe_offsets(self.raw());
+        offsets.into_iter().map(|at| at + self.offset).collect()
+    }
+
+    /// Recomputes the checksum and patches it into the buffer, see
+    /// [`File::checksum_recalc_and_patch`].
+    pub fn checksum_recalc_and_patch(&mut self) -> Option<u32> {
+        self.file.checksum_recalc_and_patch(&mut self.raw[self.offset..])
+    }
+
+    /// The entropy of the image's bytes, see [`File::file_entropy`].
+    pub fn file_entropy(&self) -> f64 {
+        File::file_entropy(self.raw())
+    }
+
+    /// The relocation records of the section at `index`, see
+    /// [`Section::coff_relocations`](super::sections::Section::coff_relocations).
+    pub fn coff_relocations(&self, index: usize) -> Vec<CoffRelocation> {
+        self.section_at(index).map_or_else(Vec::new, |section| {
+            section.coff_relocations(self.raw(), self.header.endianness)
+        })
+    }
+}
+
+impl Deref for ParsedFile {
+    type Target = File;
+
+    fn deref(&self) -> &File {
+        &self.file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::*;
+    use super::super::ParseErrorKind;
+    use super::*;
+
+    #[test]
+    fn file_methods_are_reachable_through_deref() {
+        let raw = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x10], CODE)
+            .imports(0x2000, &["KERNEL32.dll"], &[BY_ORDINAL | 1], false)
+            .build();
+        let parsed = ParsedFile::parse(raw).unwrap();
+
+        assert_eq!(parsed.imphash(), File::parse(parsed.raw()).unwrap().1.imphash());
+        assert!(parsed.imphash().is_some());
+    }
+
+    #[test]
+    fn forwarders_work_on_an_image_at_an_offset() {
+        let image = simple_image(vec![0xc3; 0x10]);
+        let mut raw = vec![0xaa; 0x100];
+        raw.extend_from_slice(&image);
+        let mut parsed = ParsedFile::parse_at(raw.clone(), 0x100).unwrap();
+
+        assert_eq!(parsed.file_entropy(), File::file_entropy(&image));
+        let checksum = parsed.checksum_recalc_and_patch().unwrap();
+        let at = 0x100 + parsed.checksum_field_offset().unwrap();
+        assert_eq!(parsed.into_inner().1[at..at + 4], checksum.to_le_bytes());
+
+        // The same image again, in the overlay of the one at 0x100.
+        raw.extend_from_slice(&image);
+        let parsed = ParsedFile::parse_at(raw, 0x100).unwrap();
+        assert_eq!(parsed.embedded_pe_offsets(), [0x100 + image.len()]);
+        assert_eq!(parsed.coff_relocations(0), []);
+        assert_eq!(parsed.coff_relocations(1), []);
+    }
+
+    #[test]
+    fn failures_are_typed_errors() {
+        let err = ParsedFile::parse(vec![b'M', b'Z']).err().unwrap();
+        assert_eq!(err.kind, Some(ParseErrorKind::TooSmall { at: 0 }));
+        assert!(ParsedFile::parse_at(simple_image(Vec::new()), 0x10_0000).is_err());
+    }
+
+    #[test]
+    fn section_errors_point_at_the_corrupt_field() {
+        let builder = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x10], CODE)
+            .section(".data", 0x2000, vec![1; 0x10], DATA);
+        let mut raw = builder.build();
+        let pointer = builder.section_table_offset() + 40 + 20;
+        put(&mut raw, pointer, &0x00ff_0000_u32.to_le_bytes());
+
+        let error = ParsedFile::parse(raw.clone()).err().unwrap();
+        assert_eq!(error.offset, pointer);
+        assert_eq!(error.contexts[1..3], ["PointerToRawData", "RawData"]);
+        assert_eq!(error.kind, None);
+
+        // Relative to the start of the input, not of the image, when parsed at an offset.
+        let at = 0x30;
+        let mut shifted = vec![0; at];
+        shifted.extend(raw);
+        let error = ParsedFile::parse_at(shifted, at).err().unwrap();
+        assert_eq!(error.offset, at + pointer);
+    }
+
+    #[test]
+    fn a_bad_e_lfanew_is_reported_as_a_missing_signature() {
+        let with_e_lfanew = |e_lfanew: u32| {
+            let mut raw = simple_image(vec![0xc3]);
+            put(&mut raw, 0x3c, &e_lfanew.to_le_bytes());
+            ParsedFile::parse(raw).err().unwrap().kind
+        };
+        let not_found = |at| Some(ParseErrorKind::PeSignatureNotFound { at });
+        let len = simple_image(vec![0xc3]).len() as u32;
+
+        // Past the end of the file, reported at the e_lfanew field itself.
+        assert_eq!(with_e_lfanew(len + 0x100), not_found(0x3c));
+        assert_eq!(with_e_lfanew(u32::MAX), not_found(0x3c));
+        // Inside the file, but not at a signature.
+        assert_eq!(with_e_lfanew(0x20), not_found(0x20));
+
+        // A signature with no room for the COFF header after it.
+        let mut raw = vec![0; 0x40];
+        raw[..2].copy_from_slice(b"MZ");
+        put(&mut raw, 0x3c, &0x40_u32.to_le_bytes());
+        raw.extend_from_slice(b"PE\0\0\x64\x86");
+        let err = ParsedFile::parse(raw).err().unwrap();
+        assert_eq!(err.kind, Some(ParseErrorKind::TooSmall { at: 0x40 }));
+    }
+
+    #[test]
+    fn tiny_buffers_are_too_small() {
+        let kind = |raw: Vec<u8>| ParsedFile::parse(raw).err().unwrap().kind;
+        let too_small = Some(ParseErrorKind::TooSmall { at: 0 });
+
+        assert_eq!(kind(b"MZ\0\0".to_vec()), too_small);
+        // Not a DOS header, and too short for a COFF one.
+        assert_eq!(kind(vec![0x64, 0x86, 1, 0]), too_small);
+
+        // A whole DOS header, whose e_lfanew points at the end of the buffer.
+        let mut dos_header = vec![0; 0x40];
+        dos_header[..2].copy_from_slice(b"MZ");
+        put(&mut dos_header, 0x3c, &0x40_u32.to_le_bytes());
+        let not_found = Some(ParseErrorKind::PeSignatureNotFound { at: 0x3c });
+        assert_eq!(kind(dos_header.clone()), not_found);
+        dos_header.truncate(0x3f);
+        assert_eq!(kind(dos_header), too_small);
+    }
+}
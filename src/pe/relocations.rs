@@ -0,0 +1,324 @@
+use super::constants::*;
+use super::sections::{Characteristics, Section};
+use super::util::*;
+use super::File;
+use nom::number::Endianness;
+
+/// Size of the page RVA and block size at the start of each relocation block.
+const BLOCK_HEADER_SIZE: usize = 8;
+
+/// The most [`File::map_image`] allocates, which a crafted section table can
+/// otherwise push to 4 GiB without any data to back it.
+const MAX_MAPPED_SIZE: u64 = 0x1000_0000;
+
+/// A location the loader patches when the image isn't loaded at its preferred base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BaseRelocation {
+    pub rva: Addr32,
+    /// One of the `IMAGE_REL_BASED_*` values.
+    pub kind: u8,
+}
+
+/// Size of one `IMAGE_RELOCATION` record.
+const COFF_RELOCATION_SIZE: usize = 10;
+
+/// An `IMAGE_RELOCATION` in an object file, which the linker resolves
+/// against a symbol when laying out the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoffRelocation {
+    /// Relative to the start of the section's data.
+    pub virtual_address: u32,
+    /// Index into [`File::symbols`], counting auxiliary records.
+    pub symbol_table_index: u32,
+    /// One of the machine-specific `IMAGE_REL_*` types.
+    pub kind: u16,
+}
+
+impl CoffRelocation {
+    fn parse(endianness: Endianness) -> impl Fn(Input) -> Result<Self> {
+        use nom::{
+            error::context,
+            number::complete::{u16, u32},
+            sequence::tuple,
+        };
+        move |i| {
+            let (i, (virtual_address, symbol_table_index, kind)) = tuple((
+                context("VirtualAddress", u32(endianness)),
+                context("SymbolTableIndex", u32(endianness)),
+                context("Type", u16(endianness)),
+            ))(i)?;
+            Ok((
+                i,
+                Self {
+                    virtual_address,
+                    symbol_table_index,
+                    kind,
+                },
+            ))
+        }
+    }
+}
+
+impl Section {
+    /// Reads the section's relocation records from `raw`, the file it was
+    /// parsed from, in the byte order of its COFF header.
+    ///
+    /// Images have none, their sections' `number_of_relocations` is zero.
+    /// Records running past the end of `raw` are left out.
+    #[allow(dead_code)]
+    pub fn coff_relocations(&self, raw: &[u8], endianness: Endianness) -> Vec<CoffRelocation> {
+        let start = self.pointer_to_relocations.0 as usize;
+        let records = |count: usize| {
+            let end = start.saturating_add(count.saturating_mul(COFF_RELOCATION_SIZE));
+            raw.get(start..end.min(raw.len()))
+                .unwrap_or(&[])
+                .chunks_exact(COFF_RELOCATION_SIZE)
+                .filter_map(|record| CoffRelocation::parse(endianness)(record).ok())
+                .map(|(_, relocation)| relocation)
+                .collect::<Vec<_>>()
+        };
+
+        // With more than 0xffff relocations, the real count is kept in the
+        // first record instead, which isn't a relocation itself.
+        if self.characteristics.contains(Characteristics::IMAGE_SCN_LNK_NRELOC_OVFL)
+            && self.number_of_relocations == 0xffff
+        {
+            let count = match records(1).first() {
+                Some(first) => first.virtual_address as usize,
+                None => return Vec::new(),
+            };
+            return records(count).into_iter().skip(1).collect();
+        }
+        records(self.number_of_relocations as usize)
+    }
+}
+
+impl File {
+    /// Parses the base relocation table (`.reloc`), flattening its per-page blocks.
+    ///
+    /// `IMAGE_REL_BASED_ABSOLUTE` padding entries are left out.
+    #[allow(dead_code)]
+    pub fn base_relocations(&self) -> Vec<BaseRelocation> {
+        let dir = match &self.header.optional_header {
+            Some(oh) => &oh.data_directories.base_relocation_table,
+            None => return Vec::new(),
+        };
+        let bytes = match dir.resolve_into(self) {
+            Some(bytes) => bytes,
+            None => return Vec::new(),
+        };
+
+        let mut relocations = Vec::new();
+        let mut block = &bytes[..];
+        while block.len() >= BLOCK_HEADER_SIZE {
+            let page = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+            let size = u32::from_le_bytes([block[4], block[5], block[6], block[7]]) as usize;
+            // A block can't be smaller than its own header, so this would loop forever.
+            if size < BLOCK_HEADER_SIZE || size > block.len() {
+                break;
+            }
+            for entry in block[BLOCK_HEADER_SIZE..size].chunks_exact(2) {
+                let entry = u16::from_le_bytes([entry[0], entry[1]]);
+                let kind = (entry >> 12) as u8;
+                if kind != IMAGE_REL_BASED_ABSOLUTE {
+                    relocations.push(BaseRelocation {
+                        rva: Addr32(page.wrapping_add((entry & 0xfff) as u32)),
+                        kind,
+                    });
+                }
+            }
+            block = &block[size..];
+        }
+        relocations
+    }
+
+    /// Lays the sections out as the loader would map them, each at its RVA
+    /// and zero-filled up to its virtual size.
+    ///
+    /// The headers aren't part of the model, so the first page is left zeroed.
+    /// The mapping ends with the last section, or at `SizeOfImage` if that's
+    /// sooner, and is `None` when that would still be over [`MAX_MAPPED_SIZE`].
+    #[allow(dead_code)]
+    pub fn map_image(&self) -> Option<Vec<u8>> {
+        let extent = self
+            .sections
+            .iter()
+            .map(|s| s.mem_range().end)
+            .max()
+            .unwrap_or(0);
+        let size = match &self.header.optional_header {
+            Some(oh) => extent.min(u64::from(oh.windows_header.size_of_image)),
+            None => extent,
+        };
+        if size > MAX_MAPPED_SIZE {
+            return None;
+        }
+        let size = size as usize;
+
+        let mut image = vec![0; size];
+        for section in &self.sections {
+            let start = section.virtual_address.0 as usize;
+            let len = section.data.len().min(section.virtual_size as usize);
+            if let Some(dest) = image.get_mut(start..start.saturating_add(len)) {
+                dest.copy_from_slice(&section.data[..len]);
+            }
+        }
+        Some(image)
+    }
+
+    /// Maps the image (see [`File::map_image`]) and applies the base relocations
+    /// as if it were loaded at `new_base`.
+    ///
+    /// Only the `HIGHLOW` fixups of PE32 images and the `DIR64` ones of PE32+
+    /// images are applied, and ones that fall outside the image are skipped.
+    /// `None` when the image is too large to map.
+    #[allow(dead_code)]
+    pub fn rebase(&self, new_base: u64) -> Option<Vec<u8>> {
+        let mut image = self.map_image()?;
+        let image_base = match &self.header.optional_header {
+            Some(oh) => oh.windows_header.image_base,
+            None => return Some(image),
+        };
+        let pe32_plus = self.is_pe32_plus();
+        let delta = new_base.wrapping_sub(image_base);
+
+        for relocation in self.base_relocations() {
+            let at = relocation.rva.0 as usize;
+            match relocation.kind {
+                IMAGE_REL_BASED_HIGHLOW if !pe32_plus => {
+                    if let Some(field) = image.get_mut(at..at + 4) {
+                        let value = u32::from_le_bytes([field[0], field[1], field[2], field[3]]);
+                        field.copy_from_slice(&value.wrapping_add(delta as u32).to_le_bytes());
+                    }
+                }
+                IMAGE_REL_BASED_DIR64 if pe32_plus => {
+                    if let Some(field) = image.get_mut(at..at + 8) {
+                        let mut value = [0; 8];
+                        value.copy_from_slice(field);
+                        let value = u64::from_le_bytes(value).wrapping_add(delta);
+                        field.copy_from_slice(&value.to_le_bytes());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::*;
+    use super::*;
+
+    /// An image whose `.data` holds `0x11223344` at `0x2000` and
+    /// `0x1122334455667788` at `0x2008`, with a `HIGHLOW` fixup for the first
+    /// and a `DIR64` fixup for the second.
+    fn relocated_image(builder: PeBuilder) -> Vec<u8> {
+        let mut data = 0x1122_3344_u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&0x1122_3344_5566_7788_u64.to_le_bytes());
+
+        let mut reloc = 0x2000_u32.to_le_bytes().to_vec();
+        reloc.extend_from_slice(&12_u32.to_le_bytes());
+        let highlow = u16::from(IMAGE_REL_BASED_HIGHLOW) << 12;
+        let dir64 = u16::from(IMAGE_REL_BASED_DIR64) << 12 | 8;
+        for entry in [highlow, dir64] {
+            reloc.extend_from_slice(&entry.to_le_bytes());
+        }
+        let size = reloc.len() as u32;
+        builder
+            .section(".data", 0x2000, data, DATA)
+            .section(".reloc", 0x3000, reloc, RDATA)
+            .directory(DataDirectoryKind::BaseRelocation, 0x3000, size)
+            .build()
+    }
+
+    fn fixed_up(image: &[u8]) -> (u32, u64) {
+        let mut dir64 = [0; 8];
+        dir64.copy_from_slice(&image[0x2008..0x2010]);
+        (
+            u32::from_le_bytes([image[0x2000], image[0x2001], image[0x2002], image[0x2003]]),
+            u64::from_le_bytes(dir64),
+        )
+    }
+
+    #[test]
+    fn pe32_images_apply_only_highlow_fixups() {
+        let raw = relocated_image(PeBuilder::pe32());
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.base_relocations().len(), 2);
+        let image = file.rebase(0x40_0000 + 0x10).unwrap();
+        assert_eq!(fixed_up(&image), (0x1122_3354, 0x1122_3344_5566_7788));
+    }
+
+    #[test]
+    fn pe32_plus_images_apply_only_dir64_fixups() {
+        let raw = relocated_image(PeBuilder::default());
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let image = file.rebase(0x1_4000_0000 + 0x10).unwrap();
+        assert_eq!(fixed_up(&image), (0x1122_3344, 0x1122_3344_5566_7798));
+    }
+
+    #[test]
+    fn mapping_stops_at_the_last_section() {
+        let mut builder = PeBuilder::default().section(".text", 0x1000, vec![0xc3; 0x10], CODE);
+        let raw = builder.build();
+        let (_, file) = File::parse(&raw).unwrap();
+        assert_eq!(file.map_image().map(|image| image.len()), Some(0x1010));
+
+        // Too large to allocate, from a section with no data behind it.
+        builder.sections[0].virtual_size = 0x2000_0000;
+        let raw = builder.build();
+        let (_, file) = File::parse(&raw).unwrap();
+        assert_eq!(file.map_image(), None);
+    }
+
+    /// An AMD64 object whose `.text` has `records` as its relocations, given as
+    /// a count of `number_of_relocations` and the records after the section data.
+    fn relocated_object(
+        number_of_relocations: u16,
+        records: &[(u32, u32, u16)],
+        characteristics: Characteristics,
+    ) -> Vec<u8> {
+        let mut raw = object(0x8664, &[(".text", &[0xe8, 0, 0, 0, 0], characteristics)]);
+        let pointer = raw.len() as u32;
+        put(&mut raw, 20 + 24, &pointer.to_le_bytes());
+        put(&mut raw, 20 + 32, &number_of_relocations.to_le_bytes());
+        for (virtual_address, symbol, kind) in records {
+            raw.extend_from_slice(&virtual_address.to_le_bytes());
+            raw.extend_from_slice(&symbol.to_le_bytes());
+            raw.extend_from_slice(&kind.to_le_bytes());
+        }
+        raw
+    }
+
+    #[test]
+    fn object_sections_have_their_relocations_read() {
+        // IMAGE_REL_AMD64_REL32 against symbols 3 and 5, the second record cut short.
+        let mut raw = relocated_object(3, &[(1, 3, 4), (9, 5, 4)], CODE);
+        raw.truncate(raw.len() - 1);
+        let (_, file) = File::parse(&raw).unwrap();
+        let text = &file.sections[0];
+
+        assert_eq!(text.number_of_relocations, 3);
+        assert_eq!(
+            text.coff_relocations(&raw, file.header.endianness),
+            [CoffRelocation { virtual_address: 1, symbol_table_index: 3, kind: 4 }]
+        );
+    }
+
+    #[test]
+    fn overflowing_relocation_counts_are_read_from_the_first_record() {
+        let records = [(3, 0, 0), (1, 3, 4), (9, 5, 4)];
+        let characteristics = CODE | Characteristics::IMAGE_SCN_LNK_NRELOC_OVFL;
+        let raw = relocated_object(0xffff, &records, characteristics);
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let relocations = file.sections[0].coff_relocations(&raw, file.header.endianness);
+        let targets: Vec<u32> = relocations.iter().map(|r| r.symbol_table_index).collect();
+        assert_eq!(targets, [3, 5]);
+    }
+}
@@ -0,0 +1,276 @@
+use super::constants::DataDirectoryKind;
+use super::File;
+
+/// Lays out `label: value` lines with the values lined up in one column,
+/// as used by `File`'s `Display`.
+#[derive(Debug, Clone, Default)]
+pub struct ReportBuilder {
+    label_width: Option<usize>,
+    value_width: Option<usize>,
+    rows: Vec<(String, String)>,
+}
+
+impl ReportBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pads labels to `width` columns, rather than to the longest label.
+    #[allow(dead_code)]
+    pub fn label_width(mut self, width: usize) -> Self {
+        self.label_width = Some(width);
+        self
+    }
+
+    /// Right-aligns values within `width` columns, so numbers of different
+    /// lengths line up on their last digit. Values are left-aligned by default.
+    #[allow(dead_code)]
+    pub fn value_width(mut self, width: usize) -> Self {
+        self.value_width = Some(width);
+        self
+    }
+
+    pub fn row(mut self, label: &str, value: String) -> Self {
+        self.rows.push((label.to_string(), value));
+        self
+    }
+
+    pub fn build(&self) -> String {
+        let label_width = self.label_width.unwrap_or_else(|| {
+            // Room for the colon after the longest label.
+            self.rows
+                .iter()
+                .map(|(label, _)| label.len() + 1)
+                .max()
+                .unwrap_or(0)
+        });
+        let mut out = String::new();
+        for (label, value) in &self.rows {
+            let label = format!("{}:", label);
+            let line = match self.value_width {
+                Some(width) => {
+                    format!("{:lw$} {:>vw$}", label, value, lw = label_width, vw = width)
+                }
+                None => format!("{:lw$} {}", label, value, lw = label_width),
+            };
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl File {
+    /// Renders the COFF and optional header fields through `report`, whose
+    /// widths can be configured beforehand.
+    pub fn header_report(&self, report: ReportBuilder) -> String {
+        let header = &self.header;
+        let mut report = report
+            .row("Architecture", format!("{:?}", header.machine))
+            .row("No. of Sections", header.number_of_sections.to_string())
+            .row("Characteristics", format!("{:?}", header.characteristics))
+            .row(
+                "Pointer to Symbol Table",
+                format!("{:#x}", header.pointer_to_sym_table.0),
+            )
+            .row("No. of Symbols", header.number_of_symbols.to_string());
+
+        // Object files have no optional header at all.
+        if let Some(oh) = &header.optional_header {
+            let wh = &oh.windows_header;
+            let kb = |size: u64| format!("{}KB", size / 1024);
+            report = report
+                .row(
+                    "Linker Version",
+                    display_version(oh.major_linker_version, oh.minor_linker_version),
+                )
+                .row("Size of Code", kb(oh.size_of_code.into()))
+                .row("Size of Init. Data", kb(oh.size_of_initialized_data.into()))
+                .row(
+                    "Size of Uninit. Data",
+                    kb(oh.size_of_uninitialized_data.into()),
+                )
+                .row("Entry Point", display_entry_point(self))
+                .row("Base of Code", oh.base_of_code.to_string());
+            // Only PE32 has this.
+            if let Some(base_of_data) = oh.base_of_data {
+                report = report.row("Base of Data", base_of_data.to_string());
+            }
+            report = report
+                .row("Image Base", format!("{:#x}", wh.image_base))
+                .row(
+                    "OS Version",
+                    display_version(wh.major_os_version, wh.minor_os_version),
+                )
+                .row(
+                    "Image Version",
+                    display_version(wh.major_image_version, wh.minor_image_version),
+                )
+                .row(
+                    "Subsystem",
+                    format!(
+                        "{:?} {}",
+                        wh.subsystem,
+                        display_version(wh.major_subsystem_version, wh.minor_subsystem_version)
+                    ),
+                )
+                .row("Win32 Version", wh.win32_version_value.to_string())
+                .row("Size of Image", kb(wh.size_of_image_bytes()))
+                .row("Size of Headers", kb(wh.size_of_headers_bytes()))
+                .row("Checksum", wh.checksum.to_string())
+                .row(
+                    "DLL Characteristics",
+                    format!("{:?}", wh.dll_characteristics),
+                )
+                .row(
+                    "Size of Stack Reserve/Commit",
+                    format!(
+                        "{}:{}",
+                        kb(wh.size_of_stack_reserve_bytes()),
+                        kb(wh.size_of_stack_commit_bytes())
+                    ),
+                )
+                .row(
+                    "Size of Heap Reserve/Commit",
+                    format!(
+                        "{}:{}",
+                        kb(wh.size_of_heap_reserve_bytes()),
+                        kb(wh.size_of_heap_commit_bytes())
+                    ),
+                )
+                .row("Loader Flags", format!("{:#x}", wh.loader_flags))
+                .row(
+                    "Number of Data Directories",
+                    wh.number_of_rva_and_sizes.to_string(),
+                );
+        }
+        report.build()
+    }
+}
+
+impl File {
+    /// Renders the data directories as a table of name, RVA, size and the
+    /// section the directory lies in, leaving the rest of an absent entry's row
+    /// blank. `None` for object files.
+    ///
+    /// The certificate table's "RVA" is a file offset, so it has no section.
+    pub fn data_directory_table(&self) -> Option<String> {
+        let oh = self.header.optional_header.as_ref()?;
+        let declared = oh.windows_header.number_of_rva_and_sizes as usize;
+        let mut out = format!("{:<24} {:>10} {:>10}  SECTION\n", "DIRECTORY", "RVA", "SIZE");
+        for kind in DataDirectoryKind::ALL.iter().take(declared) {
+            let dir = oh.data_directories.get(*kind);
+            let line = if dir.virtual_addr.0 == 0 && dir.size == 0 {
+                kind.name().to_string()
+            } else {
+                let section = match kind {
+                    DataDirectoryKind::Certificate => None,
+                    _ => self.section_for_rva(dir.virtual_addr.0),
+                };
+                format!(
+                    "{:<24} {:>#10x} {:>#10x}  {}",
+                    kind.name(),
+                    dir.virtual_addr.0,
+                    dir.size,
+                    section.map_or("", |s| s.name.as_str())
+                )
+            };
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        Some(out)
+    }
+}
+
+fn display_version<T: std::fmt::Display>(major: T, minor: T) -> String {
+    format!("{}.{}", major, minor)
+}
+
+fn display_entry_point(file: &File) -> String {
+    let entry_point = match &file.header.optional_header {
+        Some(oh) if oh.entry_point.0 != 0 => oh.entry_point,
+        _ => return "none (RVA 0)".to_string(),
+    };
+    let va = file.entry_point_va().unwrap_or_default();
+    match file.entry_point_section() {
+        Some(section) => format!("0x{} ({}, VA {:#x})", entry_point, section.name, va),
+        None => format!("0x{} (outside all sections, VA {:#x})", entry_point, va),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::*;
+    use super::*;
+
+    fn large_base_image() -> File {
+        let builder = PeBuilder {
+            image_base: 0xffff_f800_0000_0000,
+            ..PeBuilder::default()
+        };
+        let raw = builder.section(".text", 0x1000, vec![0xc3; 0x10], CODE).build();
+        File::parse(&raw).unwrap().1
+    }
+
+    #[test]
+    fn values_line_up_after_the_longest_label() {
+        let report = large_base_image().header_report(ReportBuilder::new());
+
+        let column = "Size of Stack Reserve/Commit: ".len();
+        for line in report.lines() {
+            let (label, value) = line.split_at(column);
+            assert!(label.trim_end().ends_with(':'), "{:?}", line);
+            assert!(!value.starts_with(' '), "{:?}", line);
+        }
+        assert!(report.contains("0xfffff80000000000\n"));
+    }
+
+    #[test]
+    fn values_can_be_right_aligned() {
+        let report = ReportBuilder::new()
+            .label_width(12)
+            .value_width(18)
+            .row("Image Base", "0xfffff80000000000".to_string())
+            .row("Checksum", "0".to_string())
+            .build();
+
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "Image Base:  0xfffff80000000000");
+        assert_eq!(lines[1], format!("{:13}{:>18}", "Checksum:", "0"));
+    }
+
+    #[test]
+    fn the_directory_table_shows_the_import_row() {
+        let raw = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x10], CODE)
+            .imports(0x2000, &["KERNEL32.dll"], &[BY_ORDINAL | 1], false)
+            .build();
+        let (_, file) = File::parse(&raw).unwrap();
+        let size = file.header.optional_header.as_ref().unwrap().data_directories.import_table.size;
+
+        let table = file.data_directory_table().unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 17);
+        assert_eq!(lines[0], "DIRECTORY                       RVA       SIZE  SECTION");
+        assert_eq!(lines[1], "Export Table");
+        assert_eq!(
+            lines[2],
+            format!("{:<24} {:>10} {:>#10x}  .idata", "Import Table", "0x2000", size)
+        );
+    }
+
+    #[test]
+    fn reserved_and_symbol_fields_are_shown() {
+        let report = large_base_image().header_report(ReportBuilder::new());
+        let value = |label: &str| {
+            let line = report.lines().find(|line| line.starts_with(label)).unwrap();
+            line[label.len() + 1..].trim().to_string()
+        };
+
+        assert_eq!(value("Loader Flags"), "0x0");
+        assert_eq!(value("Win32 Version"), "0");
+        assert_eq!(value("Number of Data Directories"), "16");
+        assert_eq!(value("Pointer to Symbol Table"), "0x0");
+        assert_eq!(value("No. of Symbols"), "0");
+    }
+}
@@ -0,0 +1,462 @@
+use super::constants::*;
+use super::util::*;
+use super::File;
+
+pub fn rt_name(id: u16) -> Option<&'static str> {
+    RT_NAMES.iter().find(|&&(rt, _)| rt == id).map(|&(_, name)| name)
+}
+
+/// Real resource trees are three levels deep (type, name, language),
+/// anything deeper than this is treated as a loop in a crafted file.
+const MAX_DEPTH: usize = 8;
+
+use std::collections::BTreeSet;
+
+/// How a resource directory entry is identified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourceId {
+    Id(u16),
+    /// Decoded from the `IMAGE_RESOURCE_DIR_STRING_U` the entry points at.
+    Name(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ResourceEntry {
+    pub id: ResourceId,
+    pub node: ResourceNode,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ResourceNode {
+    Directory(ResourceDirectory),
+    Data(ResourceData),
+}
+
+/// A leaf of the resource tree, pointing at the resource's bytes.
+#[derive(Debug, PartialEq)]
+pub struct ResourceData {
+    /// Unlike the offsets inside the tree, this is a full RVA.
+    pub rva: Addr32,
+    pub size: u32,
+    pub code_page: u32,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ResourceDirectory {
+    pub entries: Vec<ResourceEntry>,
+}
+
+impl ResourceDirectory {
+    /// Parses the directory at `offset` into `rsrc` (the bytes of the resource data directory),
+    /// following subdirectory offsets recursively.
+    ///
+    /// `visited` holds the offsets of the directories parsed so far. A real tree
+    /// never reaches a directory twice, while a crafted one pointing many entries
+    /// at the same subdirectory would otherwise expand exponentially.
+    fn parse<'a>(
+        rsrc: Input<'a>,
+        offset: usize,
+        depth: usize,
+        visited: &mut BTreeSet<usize>,
+    ) -> Result<'a, Self> {
+        use nom::{
+            bytes::complete::take,
+            error::{context, ContextError, ErrorKind, ParseError, VerboseError},
+            multi::count,
+            number::complete::*,
+            sequence::tuple,
+        };
+        if depth > MAX_DEPTH {
+            return Err(nom::Err::Failure(VerboseError::from_error_kind(
+                rsrc,
+                ErrorKind::TooLarge,
+            )));
+        }
+        if !visited.insert(offset) {
+            return Err(nom::Err::Failure(VerboseError::add_context(
+                rsrc,
+                "SharedDirectory",
+                VerboseError::from_error_kind(rsrc, ErrorKind::Verify),
+            )));
+        }
+
+        let (i, _) = context("DirectoryOffset", take(offset))(rsrc)?;
+        let (i, (_characteristics, _time_date_stamp, _major_version, _minor_version, named, ids)) =
+            tuple((
+                context("Characteristics", le_u32),
+                context("TimeDateStamp", le_u32),
+                context("MajorVersion", le_u16),
+                context("MinorVersion", le_u16),
+                context("NumberOfNamedEntries", le_u16),
+                context("NumberOfIdEntries", le_u16),
+            ))(i)?;
+        let (i, raw_entries) = context(
+            "Entries",
+            count(tuple((le_u32, le_u32)), named as usize + ids as usize),
+        )(i)?;
+
+        let mut entries = Vec::new();
+        for (name, offset_to_data) in raw_entries {
+            let id = if name & 0x8000_0000 != 0 {
+                let offset = (name & 0x7fff_ffff) as usize;
+                let (_, name) = context("Name", |i| parse_name(i, offset))(rsrc)?;
+                ResourceId::Name(name)
+            } else {
+                ResourceId::Id(name as u16)
+            };
+            let target = (offset_to_data & 0x7fff_ffff) as usize;
+            let node = if offset_to_data & 0x8000_0000 != 0 {
+                let (_, dir) = Self::parse(rsrc, target, depth + 1, visited)?;
+                ResourceNode::Directory(dir)
+            } else {
+                let (_, data) = ResourceData::parse(rsrc, target)?;
+                ResourceNode::Data(data)
+            };
+            entries.push(ResourceEntry { id, node });
+        }
+
+        Ok((i, Self { entries }))
+    }
+
+    pub fn find(&self, id: &ResourceId) -> Option<&ResourceEntry> {
+        self.entries.iter().find(|e| &e.id == id)
+    }
+}
+
+/// Reads the length-prefixed UTF-16 string at `offset` into `rsrc`.
+fn parse_name(rsrc: Input, offset: usize) -> Result<String> {
+    use nom::{bytes::complete::take, error::context, multi::length_count, number::complete::le_u16};
+    let (i, _) = context("NameOffset", take(offset))(rsrc)?;
+    let (i, units) = context("NameString", length_count(le_u16, le_u16))(i)?;
+    Ok((i, String::from_utf16_lossy(&units)))
+}
+
+impl ResourceData {
+    fn parse(rsrc: Input, offset: usize) -> Result<Self> {
+        use nom::{bytes::complete::take, error::context, number::complete::*, sequence::tuple};
+        let (i, _) = context("DataEntryOffset", take(offset))(rsrc)?;
+        let (i, (rva, size, code_page, _)) = tuple((
+            context("OffsetToData", Addr32::parse),
+            context("Size", le_u32),
+            context("CodePage", le_u32),
+            context("Reserved", le_u32),
+        ))(i)?;
+        Ok((i, Self { rva, size, code_page }))
+    }
+}
+
+impl ResourceEntry {
+    /// Descends through the first entry of each level until reaching data,
+    /// which for a name-level entry picks its first language.
+    pub fn first_data(&self) -> Option<&ResourceData> {
+        match &self.node {
+            ResourceNode::Data(data) => Some(data),
+            ResourceNode::Directory(dir) => dir.entries.first()?.first_data(),
+        }
+    }
+}
+
+/// A resource's bytes, converted into a standalone file format where one exists.
+#[derive(Debug, PartialEq)]
+pub enum ExtractedResource {
+    /// A `.ico` reassembled from an `RT_GROUP_ICON` and its `RT_ICON` images.
+    Icon(Vec<u8>),
+    /// A `.cur` reassembled from an `RT_GROUP_CURSOR` and its `RT_CURSOR` images.
+    Cursor(Vec<u8>),
+    /// A `.bmp`, i.e. the `RT_BITMAP` DIB with its `BITMAPFILEHEADER` restored.
+    Bitmap(Vec<u8>),
+    Raw(Vec<u8>),
+}
+
+/// The in-resource group directory entries are 14 bytes, ending in the image's
+/// resource id where the file format has a 4-byte offset instead.
+const GROUP_ENTRY_SIZE: usize = 14;
+const FILE_ENTRY_SIZE: usize = 16;
+
+impl File {
+    /// Parses the resource directory tree, if the image has one.
+    pub fn resources(&self) -> Option<ResourceDirectory> {
+        let dir = &self.header.optional_header.as_ref()?.data_directories.resource_table;
+        let rsrc = dir.resolve_into(self)?;
+        ResourceDirectory::parse(&rsrc, 0, 0, &mut BTreeSet::new()).ok().map(|(_, tree)| tree)
+    }
+
+    pub fn resource_bytes(&self, data: &ResourceData) -> Option<Vec<u8>> {
+        self.read_at_rva(data.rva.0, data.size as usize)
+    }
+
+    /// Renders the resource directory as an indented tree, one entry per line,
+    /// type, then name, then language, with the data size at the leaves.
+    pub fn resource_tree(&self) -> Option<String> {
+        let tree = self.resources()?;
+        let mut out = String::new();
+        self.write_resource_tree(&tree, 0, &mut out);
+        Some(out)
+    }
+
+    fn write_resource_tree(&self, dir: &ResourceDirectory, depth: usize, out: &mut String) {
+        for entry in &dir.entries {
+            let label = match &entry.id {
+                ResourceId::Id(id) if depth == 0 => match rt_name(*id) {
+                    Some(name) => name.to_string(),
+                    None => id.to_string(),
+                },
+                ResourceId::Id(id) => id.to_string(),
+                ResourceId::Name(name) => format!("\"{}\"", name),
+            };
+            let indent = "    ".repeat(depth);
+            match &entry.node {
+                ResourceNode::Directory(sub) => {
+                    out.push_str(&format!("{}{}\n", indent, label));
+                    self.write_resource_tree(sub, depth + 1, out);
+                }
+                ResourceNode::Data(data) => {
+                    out.push_str(&format!("{}{}: {:#x} bytes\n", indent, label, data.size));
+                }
+            }
+        }
+    }
+
+    /// Extracts every resource, converting icons, cursors and bitmaps
+    /// into files that can be opened directly.
+    ///
+    /// Individual `RT_ICON`/`RT_CURSOR` images are folded into their groups
+    /// rather than returned on their own.
+    #[allow(dead_code)]
+    pub fn extract_resources(&self) -> Vec<(ResourceId, ResourceId, ExtractedResource)> {
+        let tree = match self.resources() {
+            Some(tree) => tree,
+            None => return Vec::new(),
+        };
+
+        let mut extracted = Vec::new();
+        for type_entry in &tree.entries {
+            let names = match &type_entry.node {
+                ResourceNode::Directory(names) => names,
+                ResourceNode::Data(_) => continue,
+            };
+            for name_entry in &names.entries {
+                let resource = match type_entry.id {
+                    ResourceId::Id(RT_ICON) | ResourceId::Id(RT_CURSOR) => continue,
+                    ResourceId::Id(RT_GROUP_ICON) => {
+                        self.assemble_group(&tree, name_entry, RT_ICON).map(ExtractedResource::Icon)
+                    }
+                    ResourceId::Id(RT_GROUP_CURSOR) => self
+                        .assemble_group(&tree, name_entry, RT_CURSOR)
+                        .map(ExtractedResource::Cursor),
+                    ResourceId::Id(RT_BITMAP) => name_entry
+                        .first_data()
+                        .and_then(|data| self.resource_bytes(data))
+                        .and_then(|dib| bitmap_file(&dib))
+                        .map(ExtractedResource::Bitmap),
+                    _ => name_entry
+                        .first_data()
+                        .and_then(|data| self.resource_bytes(data))
+                        .map(ExtractedResource::Raw),
+                };
+                if let Some(resource) = resource {
+                    extracted.push((type_entry.id.clone(), name_entry.id.clone(), resource));
+                }
+            }
+        }
+        extracted
+    }
+
+    /// The image's first icon group as a `.ico` file, typically the one Explorer shows.
+    #[allow(dead_code)]
+    pub fn icon(&self) -> Option<Vec<u8>> {
+        let tree = self.resources()?;
+        let groups = match &tree.find(&ResourceId::Id(RT_GROUP_ICON))?.node {
+            ResourceNode::Directory(groups) => groups,
+            ResourceNode::Data(_) => return None,
+        };
+        self.assemble_group(&tree, groups.entries.first()?, RT_ICON)
+    }
+
+    /// Rebuilds an `.ico`/`.cur` file from a group resource, whose entries
+    /// reference the individual images by id under `image_type`.
+    fn assemble_group(
+        &self,
+        tree: &ResourceDirectory,
+        group: &ResourceEntry,
+        image_type: u16,
+    ) -> Option<Vec<u8>> {
+        let images = match &tree.find(&ResourceId::Id(image_type))?.node {
+            ResourceNode::Directory(images) => images,
+            ResourceNode::Data(_) => return None,
+        };
+        let header = self.resource_bytes(group.first_data()?)?;
+        let count = u16::from_le_bytes([*header.get(4)?, *header.get(5)?]) as usize;
+        let entries = header.get(6..6 + count * GROUP_ENTRY_SIZE)?;
+
+        let mut out = header[..6].to_vec();
+        let mut blobs = Vec::new();
+        let mut offset = 6 + count * FILE_ENTRY_SIZE;
+        for entry in entries.chunks(GROUP_ENTRY_SIZE) {
+            let id = u16::from_le_bytes([entry[12], entry[13]]);
+            let mut image = self.resource_bytes(images.find(&ResourceId::Id(id))?.first_data()?)?;
+
+            if image_type == RT_CURSOR {
+                // Cursor images carry their hotspot in front of the DIB, whereas
+                // `.cur` keeps it in the directory entry (where `.ico` has planes/bit count).
+                let hotspot = image.get(..4)?.to_vec();
+                image.drain(..4);
+                let width = u16::from_le_bytes([entry[0], entry[1]]);
+                // Cursor heights include the AND mask, so are doubled.
+                let height = u16::from_le_bytes([entry[2], entry[3]]) / 2;
+                out.extend_from_slice(&[width as u8, height as u8, 0, 0]);
+                out.extend_from_slice(&hotspot);
+            } else {
+                out.extend_from_slice(&entry[..8]);
+            }
+            out.extend_from_slice(&(image.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(offset as u32).to_le_bytes());
+            offset += image.len();
+            blobs.push(image);
+        }
+
+        for blob in blobs {
+            out.extend_from_slice(&blob);
+        }
+        Some(out)
+    }
+}
+
+/// Prepends the `BITMAPFILEHEADER` that is stripped from `RT_BITMAP` resources.
+fn bitmap_file(dib: &[u8]) -> Option<Vec<u8>> {
+    use std::convert::TryFrom;
+    const FILE_HEADER_SIZE: u32 = 14;
+    const BI_BITFIELDS: u32 = 3;
+    let u32_at = |at: usize| -> Option<u32> {
+        Some(u32::from_le_bytes([*dib.get(at)?, *dib.get(at + 1)?, *dib.get(at + 2)?, *dib.get(at + 3)?]))
+    };
+
+    let header_size = u32_at(0)?;
+    // The 12-byte BITMAPCOREHEADER has 16-bit dimensions, no compression
+    // or colour count fields, and 3-byte palette entries.
+    let core = header_size < 40;
+    let bit_count_at = if core { 10 } else { 14 };
+    let bit_count = u16::from_le_bytes([*dib.get(bit_count_at)?, *dib.get(bit_count_at + 1)?]);
+    let (compression, colors_used) = if core { (0, 0) } else { (u32_at(16)?, u32_at(32)?) };
+
+    let entry_size = if core { 3 } else { 4 };
+    let palette_colors = match colors_used {
+        0 if bit_count <= 8 => 1 << bit_count,
+        n => n,
+    };
+    let masks = if compression == BI_BITFIELDS && header_size == 40 { 12 } else { 0 };
+    // The sizes come straight from the resource, so may not add up.
+    let pixel_offset = palette_colors
+        .checked_mul(entry_size)?
+        .checked_add(FILE_HEADER_SIZE + masks)?
+        .checked_add(header_size)?;
+    let file_size = u32::try_from(dib.len()).ok()?.checked_add(FILE_HEADER_SIZE)?;
+
+    let mut out = b"BM".to_vec();
+    out.extend_from_slice(&file_size.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&pixel_offset.to_le_bytes());
+    out.extend_from_slice(dib);
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::*;
+    use super::*;
+
+    const RSRC: u32 = 0x2000;
+
+    fn parse(tree: &[(ResId, Res)]) -> File {
+        let raw = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x10], CODE)
+            .resources(RSRC, tree)
+            .build();
+        File::parse(&raw).unwrap().1
+    }
+
+    #[test]
+    fn icon_groups_are_reassembled_into_ico_files() {
+        let image = b"\x28\0\0\0 pretend DIB".to_vec();
+        let mut group = vec![0, 0, 1, 0, 1, 0];
+        // 16x16, 32 bits per pixel, image id 7.
+        group.extend_from_slice(&[16, 16, 0, 0, 1, 0, 32, 0]);
+        group.extend_from_slice(&(image.len() as u32).to_le_bytes());
+        group.extend_from_slice(&7_u16.to_le_bytes());
+        let file = parse(&[resource(RT_ICON, 7, image.clone()), resource(RT_GROUP_ICON, 1, group)]);
+
+        let ico = file.icon().unwrap();
+        assert_eq!(ico[..6], [0, 0, 1, 0, 1, 0]);
+        assert_eq!(ico[6..14], [16, 16, 0, 0, 1, 0, 32, 0]);
+        assert_eq!(ico[14..18], (image.len() as u32).to_le_bytes());
+        assert_eq!(ico[18..22], 22_u32.to_le_bytes());
+        assert_eq!(ico[22..], image[..]);
+    }
+
+    #[test]
+    fn bitmaps_get_their_file_header_back() {
+        let mut dib = 40_u32.to_le_bytes().to_vec();
+        dib.extend_from_slice(&[0; 10]);
+        // 24 bits per pixel, so no palette.
+        dib.extend_from_slice(&24_u16.to_le_bytes());
+        dib.resize(40, 0);
+        dib.extend_from_slice(&[0xff; 12]);
+        let file = parse(&[resource(RT_BITMAP, 1, dib.clone())]);
+
+        let extracted = file.extract_resources();
+        let bmp = match &extracted[0].2 {
+            ExtractedResource::Bitmap(bmp) => bmp,
+            other => panic!("expected a bitmap, got {:?}", other),
+        };
+        assert_eq!(bmp[..2], *b"BM");
+        assert_eq!(bmp[2..6], (dib.len() as u32 + 14).to_le_bytes());
+        assert_eq!(bmp[10..14], 54_u32.to_le_bytes());
+        assert_eq!(bmp[14..], dib[..]);
+    }
+
+    #[test]
+    fn directories_reached_twice_are_refused() {
+        let tree = [resource(RT_RCDATA, 1, vec![1]), resource(RT_RCDATA + 1, 1, vec![2])];
+        let mut rsrc = resource_section(RSRC, &tree);
+        // Point the second type at the first type's name directory.
+        let first = rsrc[20..24].to_vec();
+        rsrc[28..32].copy_from_slice(&first);
+        let size = rsrc.len() as u32;
+        let raw = PeBuilder::default()
+            .section(".rsrc", RSRC, rsrc, RDATA)
+            .directory(DataDirectoryKind::Resource, RSRC, size)
+            .build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.resources(), None);
+        assert_eq!(file.extract_resources(), []);
+    }
+
+    #[test]
+    fn the_tree_names_the_predefined_types() {
+        let file = parse(&[resource(16, 1, vec![0; 0x10]), resource(RT_MANIFEST, 1, vec![0; 0x20])]);
+
+        assert_eq!(
+            file.resource_tree().unwrap(),
+            "RT_VERSION\n    1\n        1033: 0x10 bytes\nRT_MANIFEST\n    1\n        1033: 0x20 bytes\n"
+        );
+        let (_, file) = File::parse(&simple_image(vec![0xc3])).unwrap();
+        assert_eq!(file.resource_tree(), None);
+    }
+
+    #[test]
+    fn string_names_are_decoded() {
+        let data = Res::Dir(vec![(ResId::Id(0x409), Res::Data(b"<html/>".to_vec()))]);
+        let named = (ResId::Name("ABOUT_Ü"), data);
+        let file = parse(&[(ResId::Id(23), Res::Dir(vec![named]))]);
+
+        let tree = file.resources().unwrap();
+        let names = match &tree.entries[0].node {
+            ResourceNode::Directory(names) => names,
+            other => panic!("expected a directory, got {:?}", other),
+        };
+        assert_eq!(tree.entries[0].id, ResourceId::Id(23));
+        assert_eq!(names.entries[0].id, ResourceId::Name("ABOUT_Ü".to_string()));
+        assert!(file.resource_tree().unwrap().contains("    \"ABOUT_Ü\"\n"));
+    }
+}
@@ -0,0 +1,152 @@
+use super::hash::{md5, to_hex};
+use super::util::*;
+use super::File;
+
+const RICH_MARKER: &[u8] = b"Rich";
+const DANS_MARKER: u32 = 0x536e_6144; // "DanS"
+
+/// One tool that contributed to the build, as recorded by the MSVC linker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RichEntry {
+    pub product_id: u16,
+    pub build: u16,
+    /// How many objects this tool produced.
+    pub count: u32,
+}
+
+/// The undocumented "Rich" header the MSVC linker leaves in the DOS stub,
+/// describing the toolchain that built the image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RichHeader {
+    /// The XOR mask applied to every dword of the header.
+    pub key: u32,
+    pub entries: Vec<RichEntry>,
+    /// The masked bytes from `DanS` up to (not including) `Rich`.
+    pub raw: Vec<u8>,
+    /// `raw` with the mask removed.
+    pub clear: Vec<u8>,
+}
+
+fn dword_at(i: Input, at: usize) -> Option<u32> {
+    let b = i.get(at..at + 4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+impl RichHeader {
+    /// Looks for the header between the DOS header and the PE header.
+    pub fn find(i: Input) -> Option<Self> {
+        if !i.starts_with(b"MZ") {
+            return None;
+        }
+        let e_lfanew = dword_at(i, 0x3c)? as usize;
+        let stub = i.get(0x40..e_lfanew.min(i.len()))?;
+
+        let rich = stub.windows(4).rposition(|w| w == RICH_MARKER)?;
+        let key = dword_at(stub, rich + 4)?;
+
+        // The start is only marked by `DanS` once unmasked, so walk back to it.
+        let mut start = rich;
+        loop {
+            start = start.checked_sub(4)?;
+            if dword_at(stub, start)? ^ key == DANS_MARKER {
+                break;
+            }
+        }
+
+        let raw = stub[start..rich].to_vec();
+        let clear: Vec<u8> = raw
+            .chunks(4)
+            .flat_map(|dword| (dword_at(dword, 0).unwrap_or(0) ^ key).to_le_bytes())
+            .collect();
+
+        // `DanS` is followed by three zeroed dwords of padding.
+        let entries = clear
+            .get(16..)
+            .unwrap_or(&[])
+            .chunks_exact(8)
+            .map(|entry| {
+                let comp_id = dword_at(entry, 0).unwrap_or(0);
+                RichEntry {
+                    product_id: (comp_id >> 16) as u16,
+                    build: comp_id as u16,
+                    count: dword_at(entry, 4).unwrap_or(0),
+                }
+            })
+            .collect();
+
+        Some(Self {
+            key,
+            entries,
+            raw,
+            clear,
+        })
+    }
+}
+
+impl File {
+    /// The "richhash": MD5 of the masked Rich header bytes, for clustering
+    /// samples by build environment. `None` when there's no Rich header.
+    #[allow(dead_code)]
+    pub fn rich_hash(&self) -> Option<String> {
+        Some(to_hex(&md5(&self.rich_header.as_ref()?.raw)))
+    }
+
+    /// Like [`File::rich_hash`], but over the unmasked bytes, so it doesn't
+    /// depend on the per-file XOR key.
+    #[allow(dead_code)]
+    pub fn rich_hash_clear(&self) -> Option<String> {
+        Some(to_hex(&md5(&self.rich_header.as_ref()?.clear)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::*;
+    use super::*;
+
+    const KEY: u32 = 0x1f2e_3d4c;
+
+    /// A DOS stub with a Rich header at `0x80`, where the MSVC linker puts it,
+    /// recording two tools.
+    fn rich_stub() -> Vec<u8> {
+        let mut clear = Vec::new();
+        for dword in [DANS_MARKER, 0, 0, 0, 0x0104 << 16 | 30795, 5, 0x0102 << 16 | 30795, 1] {
+            clear.extend_from_slice(&dword.to_le_bytes());
+        }
+        let mut stub = vec![0; 0x40];
+        for dword in clear.chunks(4) {
+            let dword = u32::from_le_bytes([dword[0], dword[1], dword[2], dword[3]]);
+            stub.extend_from_slice(&(dword ^ KEY).to_le_bytes());
+        }
+        stub.extend_from_slice(RICH_MARKER);
+        stub.extend_from_slice(&KEY.to_le_bytes());
+        stub
+    }
+
+    #[test]
+    fn rich_headers_are_decoded_and_hashed() {
+        let builder = PeBuilder {
+            dos_stub: rich_stub(),
+            ..PeBuilder::default()
+        };
+        let raw = builder.section(".text", 0x1000, vec![0xc3], CODE).build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let rich = file.rich_header.as_ref().unwrap();
+        assert_eq!(rich.key, KEY);
+        assert_eq!(
+            rich.entries,
+            [
+                RichEntry { product_id: 0x0104, build: 30795, count: 5 },
+                RichEntry { product_id: 0x0102, build: 30795, count: 1 },
+            ]
+        );
+        // As computed by `hashlib.md5` over the masked and the cleartext
+        // bytes, the latter being what `pefile`'s `get_rich_header_hash` hashes.
+        assert_eq!(file.rich_hash().as_deref(), Some("afe7992196054f43ce5e5c25fa622fd9"));
+        assert_eq!(file.rich_hash_clear().as_deref(), Some("c433cfbda488b496eea0598ea1231eea"));
+
+        let (_, plain) = File::parse(&simple_image(vec![0xc3])).unwrap();
+        assert_eq!(plain.rich_hash(), None);
+    }
+}
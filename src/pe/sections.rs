@@ -1,5 +1,6 @@
 use super::util::*;
 use bitflags::*;
+use nom::number::Endianness;
 use std::ops::Range;
 
 bitflags! {
@@ -71,7 +72,10 @@ bitflags! {
 }
 
 
-impl_parse_for_enumflags!(Characteristics, le_u32);
+impl_parse_for_enumflags!(endian Characteristics, u32);
+
+/// Where `PointerToRawData` sits in a section table entry.
+const POINTER_TO_RAW_DATA_OFFSET: usize = 20;
 
 
 
@@ -83,6 +87,10 @@ pub struct Section {
     /// there is no terminating null.
     pub name: SectionName,
 
+    /// The name field as stored, for writing the entry back unchanged: `name`
+    /// drops anything after the first NUL and replaces invalid UTF-8.
+    pub raw_name: [u8; 8],
+
     /// The total size of the section when loaded into memory
     /// If this value is greater than SizeOfRawData
     pub virtual_size: u32,
@@ -130,31 +138,71 @@ pub struct Section {
 }
 
 impl Section {
-    pub fn parse<'a>(full_input: Input<'_>, i: Input<'a>) -> Result<'a, Self> {
+    /// Parses a section table entry, in the byte order of the COFF header.
+    ///
+    /// Raw data that lies outside `full_input` is an error rather than a panic,
+    /// so a single corrupt entry can be reported and skipped. Without `load_data`
+    /// it is still checked, but `data` is left empty.
+    pub fn parse<'a>(
+        full_input: Input<'a>,
+        i: Input<'a>,
+        endianness: Endianness,
+        load_data: bool,
+    ) -> Result<'a, Self> {
         use nom::{
             bytes::complete::{ take, tag },
-            error::context,
+            error::{context, ContextError, ErrorKind, ParseError, VerboseError},
             sequence::tuple,
-            number::complete::*,
+            number::complete::{u16, u32},
         };
+        let entry = i;
         let (i,(raw_name, virtual_size, virtual_address, size_of_raw_data,
         pointer_to_raw_data, pointer_to_relocations, _,
         number_of_relocations, _, characteristics)) = tuple((
 
             context("Name", take(8_usize)),
-            context("VirtualSize", le_u32),
-            context("VirtualAddress", Addr32::parse),
-            context("SizeOfRawData", le_u32),
-            context("PointerToRawData", Addr32::parse),
-            context("PointerToRelocations", Addr32::parse),
+            context("VirtualSize", u32(endianness)),
+            context("VirtualAddress", Addr32::parse_endian(endianness)),
+            context("SizeOfRawData", u32(endianness)),
+            context("PointerToRawData", Addr32::parse_endian(endianness)),
+            context("PointerToRelocations", Addr32::parse_endian(endianness)),
             context("PointerToLinenumbers", tag(&[0, 0, 0, 0])),
-            context("NumberOfRelocations", le_u16),
+            context("NumberOfRelocations", u16(endianness)),
             context("NumberOfLinenumbers", tag(&[0, 0])),
-            context("Characteristics", Characteristics::parse),
+            context("Characteristics", Characteristics::parse_endian(endianness)),
             ))(i)?;
-        let name: SectionName = String::from_utf8_lossy(raw_name).trim().to_string().into();
+        // Windows stops at the first NUL, anything after it in the field is ignored.
+        let end = raw_name.iter().position(|&b| b == 0).unwrap_or(raw_name.len());
+        let name: SectionName = String::from_utf8_lossy(&raw_name[..end]).to_string().into();
+        let mut stored_name = [0; 8];
+        stored_name.copy_from_slice(raw_name);
+
+        // Sections without raw data, such as `.bss`, may have any pointer, as it
+        // isn't used, so it's not even bounds-checked.
+        let data = match size_of_raw_data {
+            0 => Vec::new(),
+            size => {
+                let start = pointer_to_raw_data.0 as usize;
+                let end = start.checked_add(size as usize);
+                let data = match end.and_then(|end| full_input.get(start..end)) {
+                    Some(data) => data,
+                    // Reporting the position of the data itself would point past
+                    // the end of the file, so blame the entry's pointer instead.
+                    None => {
+                        let field = &entry[POINTER_TO_RAW_DATA_OFFSET..];
+                        let err = VerboseError::from_error_kind(field, ErrorKind::Eof);
+                        let err = VerboseError::add_context(field, "PointerToRawData", err);
+                        return Err(nom::Err::Error(VerboseError::add_context(
+                            field, "RawData", err,
+                        )));
+                    }
+                };
+                if load_data { data.to_vec() } else { Vec::new() }
+            }
+        };
         let result = Self {
             name,
+            raw_name: stored_name,
             virtual_size,
             virtual_address,
             size_of_raw_data,
@@ -164,22 +212,187 @@ impl Section {
             number_of_relocations,
             number_of_line_numbers: 0,
             characteristics,
-            data: full_input[pointer_to_raw_data.into()..][..size_of_raw_data as usize].to_vec(),
+            data,
         };
         Ok((i, result))
     }
 
 
-    /// Range where the segment would be in virtual memory.
+    /// Range where the segment would be in virtual memory, as RVAs. In 64 bits,
+    /// as a crafted `virtual_size` can take the end past 4GB.
+    #[allow(dead_code)]
+    pub fn mem_range(&self) -> Range<u64> {
+        let start = u64::from(self.virtual_address.0);
+        start..start + u64::from(self.virtual_size)
+    }
+
+    /// Whether the section's virtual range runs past the end of the 32-bit RVA space.
+    pub fn wraps_address_space(&self) -> bool {
+        self.mem_range().end > 1 << 32
+    }
+
+    /// Whether `rva` falls in the section's virtual range. Unlike going through
+    /// [`Section::mem_range`] this can't overflow on a crafted `virtual_size`.
+    pub fn contains_rva(&self, rva: u32) -> bool {
+        let start = self.virtual_address.0;
+        rva >= start && u64::from(rva) < u64::from(start) + u64::from(self.virtual_size)
+    }
+
+    /// Whether the file offset `offset` falls in the section's raw data.
+    pub fn contains_offset(&self, offset: u32) -> bool {
+        let start = self.pointer_to_raw_data.0;
+        offset >= start && u64::from(offset) < u64::from(start) + u64::from(self.size_of_raw_data)
+    }
+
+    /// Whether the section has no raw data in the file at all, in which case
+    /// `pointer_to_raw_data` is meaningless and `data` is empty.
+    pub fn is_empty_on_disk(&self) -> bool {
+        self.size_of_raw_data == 0
+    }
+
+    /// Whether [`Section::data`] holds the section's raw data, which it doesn't
+    /// when the file was parsed without
+    /// [`load_section_data`](super::ParseOptions::load_section_data).
+    pub fn is_data_loaded(&self) -> bool {
+        self.data.len() as u64 == u64::from(self.size_of_raw_data)
+    }
+
+    /// Whether the section holds only uninitialized data, like `.bss`, which the
+    /// loader zero-fills rather than reading from the file.
+    #[allow(dead_code)]
+    pub fn is_uninitialized(&self) -> bool {
+        use Characteristics as C;
+        self.characteristics.contains(C::IMAGE_SCN_CNT_UNINITIALIZED_DATA)
+            && !self
+                .characteristics
+                .intersects(C::IMAGE_SCN_CNT_CODE | C::IMAGE_SCN_CNT_INITIALIZED_DATA)
+    }
+
+    /// `len` bytes of the section's raw data from `start`, relative to the
+    /// start of the section, or `None` if that runs past the end of it.
     #[allow(dead_code)]
-    pub fn mem_range(&self) -> Range<Addr32> {
-        self.virtual_address..(self.virtual_address + Addr32::from(self.virtual_size))
+    pub fn data_slice(&self, start: usize, len: usize) -> Option<&[u8]> {
+        self.data.get(start..start.checked_add(len)?)
     }
 
-    /// Range where the segment would be in virtual memory.
+    /// The section's raw data, read from `raw` (the image it was parsed from)
+    /// instead of [`Section::data`], so files parsed without
+    /// [`load_section_data`](super::ParseOptions::load_section_data) can still
+    /// get at it one section at a time.
+    ///
+    /// Cut short if it runs past the end of `raw`.
     #[allow(dead_code)]
-    pub fn file_range(&self) -> Range<Addr32> {
-        self.pointer_to_raw_data ..(self.pointer_to_raw_data + Addr32::from(self.size_of_raw_data))
+    pub fn read_data<'a>(&self, raw: &'a [u8]) -> &'a [u8] {
+        if self.is_empty_on_disk() {
+            return &[];
+        }
+        let start = (self.pointer_to_raw_data.0 as usize).min(raw.len());
+        let end = start.saturating_add(self.size_of_raw_data as usize).min(raw.len());
+        &raw[start..end]
+    }
+
+    /// Serializes the 40-byte section table entry in the byte order `e`, that
+    /// of the file's COFF header.
+    pub fn header_bytes(&self, e: Endianness) -> Vec<u8> {
+        let mut out = Vec::with_capacity(40);
+        out.extend_from_slice(&self.raw_name);
+        put_u32(&mut out, self.virtual_size, e);
+        put_u32(&mut out, self.virtual_address.0, e);
+        put_u32(&mut out, self.size_of_raw_data, e);
+        put_u32(&mut out, self.pointer_to_raw_data.0, e);
+        put_u32(&mut out, self.pointer_to_relocations.0, e);
+        put_u32(&mut out, self.pointer_to_line_numbers, e);
+        put_u16(&mut out, self.number_of_relocations, e);
+        put_u16(&mut out, self.number_of_line_numbers, e);
+        put_u32(&mut out, self.characteristics.bits(), e);
+        out
+    }
+
+    /// Replaces the section's raw data, zero-padding it to `file_alignment`
+    /// and updating `size_of_raw_data` to match.
+    ///
+    /// `virtual_size` only ever grows, so any uninitialized tail stays mapped.
+    /// This doesn't move other sections, see [`File::set_section_data`](super::File::set_section_data).
+    pub fn set_data(&mut self, mut data: Vec<u8>, file_alignment: u32) {
+        self.virtual_size = self.virtual_size.max(data.len() as u32);
+        data.resize(align_up(data.len() as u32, file_alignment) as usize, 0);
+        self.size_of_raw_data = data.len() as u32;
+        self.data = data;
+    }
+
+    /// The memory protection as an `RWX`-style string, e.g. `R-X` for code.
+    pub fn permissions(&self) -> String {
+        [
+            (Characteristics::READ, "R"),
+            (Characteristics::WRITE, "W"),
+            (Characteristics::EXECUTE, "X"),
+        ]
+        .iter()
+        .map(|&(flag, letter)| {
+            if self.characteristics.contains(flag) {
+                letter
+            } else {
+                "-"
+            }
+        })
+        .collect()
+    }
+
+    /// The first `max` bytes of the raw data (all of it if `None`) as a YARA
+    /// hex string, e.g. `{ 4D 5A 90 00 }`.
+    pub fn yara_hex(&self, max: Option<usize>) -> String {
+        let len = max.map_or(self.data.len(), |max| max.min(self.data.len()));
+        let bytes: Vec<String> = self.data[..len].iter().map(|b| format!("{:02X}", b)).collect();
+        format!("{{ {} }}", bytes.join(" "))
+    }
+
+    /// CRC-32 of the raw data, for spotting changed sections much faster than hashing them.
+    pub fn crc32(&self) -> u32 {
+        super::hash::crc32(&self.data)
+    }
+
+    /// Shannon entropy of the raw data, in bits per byte (0 to 8).
+    pub fn entropy(&self) -> f64 {
+        super::hash::entropy(&self.data)
+    }
+
+    /// A cheap packing triage metric from 0 to 1, without running a compressor.
+    ///
+    /// Despite the name, high values mean the data is already compressed or
+    /// encrypted (and so won't shrink further), low values mean it's plain code or text.
+    #[allow(dead_code)]
+    pub fn estimated_compressibility(&self) -> f64 {
+        self.entropy() / 8.0
+    }
+
+    /// Describes contradictory characteristics, which compilers never emit
+    /// and so are a telltale sign of hand-crafted headers.
+    pub fn flag_conflicts(&self) -> Vec<&'static str> {
+        use Characteristics as C;
+        let has = |flags: C| self.characteristics.contains(flags);
+
+        let mut conflicts = Vec::new();
+        if has(C::IMAGE_SCN_CNT_CODE) && !has(C::EXECUTE) {
+            conflicts.push("contains code but is not executable");
+        }
+        if has(C::IMAGE_SCN_CNT_INITIALIZED_DATA | C::IMAGE_SCN_CNT_UNINITIALIZED_DATA) {
+            conflicts.push("contains both initialized and uninitialized data");
+        }
+        if has(C::IMAGE_SCN_CNT_CODE | C::IMAGE_SCN_CNT_UNINITIALIZED_DATA) {
+            conflicts.push("contains both code and uninitialized data");
+        }
+        if has(C::WRITE) && !has(C::READ) {
+            conflicts.push("is writable but not readable");
+        }
+        conflicts
+    }
+
+    /// Range the section's raw data occupies in the file, in 64 bits like
+    /// [`Section::mem_range`].
+    #[allow(dead_code)]
+    pub fn file_range(&self) -> Range<u64> {
+        let start = u64::from(self.pointer_to_raw_data.0);
+        start..start + u64::from(self.size_of_raw_data)
     }
 }
 
@@ -188,27 +401,148 @@ impl fmt::Debug for Section {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "{:?} | mem {:?} | file {:?} | {} |",
+            "{:?} | mem {:08x}..{:08x} | file {:08x}..{:08x} | {} |",
             self.name,
-            self.mem_range(),
-            self.file_range(),
+            self.mem_range().start,
+            self.mem_range().end,
+            self.file_range().start,
+            self.file_range().end,
             // the default Debug formatter is
             // on the verbose side, let's print something like `RWX` instead
-            &[
-                (Characteristics::READ, "R"),
-                (Characteristics::WRITE, "W"),
-                (Characteristics::EXECUTE, "X")
-            ]
-                .iter()
-                .map(|&(flag, letter)| {
-                    if self.characteristics.contains(flag) {
-                        letter
-                    } else {
-                        "-"
-                    }
-                })
-                .collect::<Vec<_>>()
-                .join(""),
+            self.permissions(),
         )
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::*;
+    use super::super::{File, ParseOptions};
+    use super::*;
+
+    fn conflicts(characteristics: Characteristics) -> Vec<&'static str> {
+        let raw = object(0x8664, &[(".text", &[0xc3], characteristics)]);
+        let (_, file) = File::parse(&raw).unwrap();
+        file.sections[0].flag_conflicts()
+    }
+
+    #[test]
+    fn contradictory_flags_are_described() {
+        use Characteristics as C;
+        assert_eq!(conflicts(CODE), Vec::<&str>::new());
+        assert_eq!(
+            conflicts(C::IMAGE_SCN_CNT_CODE | C::READ),
+            ["contains code but is not executable"]
+        );
+        assert_eq!(
+            conflicts(C::IMAGE_SCN_CNT_INITIALIZED_DATA | C::IMAGE_SCN_CNT_UNINITIALIZED_DATA),
+            ["contains both initialized and uninitialized data"]
+        );
+        assert_eq!(conflicts(C::WRITE), ["is writable but not readable"]);
+    }
+
+    #[test]
+    fn data_slices_are_bounds_checked() {
+        let raw = object(0x8664, &[(".data", &[1, 2, 3, 4], DATA)]);
+        let (_, file) = File::parse(&raw).unwrap();
+        let section = &file.sections[0];
+
+        assert_eq!(section.data_slice(1, 2), Some(&[2, 3][..]));
+        assert_eq!(section.data_slice(4, 0), Some(&[][..]));
+        assert_eq!(section.data_slice(3, 2), None);
+        assert_eq!(section.data_slice(usize::MAX, 2), None);
+    }
+
+    #[test]
+    fn names_end_at_the_first_nul() {
+        let mut raw = object(0x8664, &[(".data", &[1], DATA)]);
+        raw[20..28].copy_from_slice(b"a\0b\0\0\0\0\0");
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.sections[0].name.as_str(), "a");
+        assert_eq!(file.sections[0].raw_name, *b"a\0b\0\0\0\0\0");
+    }
+
+    #[test]
+    fn random_data_estimates_as_less_compressible_than_text() {
+        let text = b"The quick brown fox jumps over the lazy dog. ".repeat(20);
+        let mut state = 0x2545_f491_u32;
+        let random: Vec<u8> = (0..0x1000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        let raw = object(0x8664, &[(".rdata", &text, RDATA), (".packed", &random, RDATA)]);
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let text = file.sections[0].estimated_compressibility();
+        let random = file.sections[1].estimated_compressibility();
+        assert!(text > 0.0 && text < 0.6, "{}", text);
+        assert!(random > 0.95 && random <= 1.0, "{}", random);
+    }
+
+    #[test]
+    fn crc32_matches_the_standard_check_value() {
+        let raw = object(0x8664, &[(".rdata", b"123456789", RDATA), (".bss", &[], DATA)]);
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.sections[0].crc32(), 0xcbf4_3926);
+        assert_eq!(file.sections[1].crc32(), 0);
+    }
+
+    #[test]
+    fn read_data_matches_the_eagerly_loaded_data() {
+        let raw = object(
+            0x8664,
+            &[(".text", &[0xc3; 0x30], CODE), (".bss", &[], DATA), (".data", &[1, 2, 3], DATA)],
+        );
+        let (_, eager) = File::parse(&raw).unwrap();
+        let options = ParseOptions { load_section_data: false };
+        let (_, lazy) = File::parse_with(&raw, &options).unwrap();
+
+        for (eager, lazy) in eager.sections.iter().zip(&lazy.sections) {
+            assert!(lazy.data.is_empty());
+            assert_eq!(lazy.read_data(&raw), &eager.data[..]);
+        }
+        // Data running off the end of the buffer is cut short.
+        assert_eq!(lazy.sections[2].read_data(&raw[..raw.len() - 1]), [1, 2]);
+    }
+
+    #[test]
+    fn yara_hex_strings_are_braced_and_truncated() {
+        let raw = object(0x8664, &[(".text", &[0x4d, 0x5a, 0x90, 0x0a], CODE)]);
+        let (_, file) = File::parse(&raw).unwrap();
+        let section = &file.sections[0];
+
+        assert_eq!(section.yara_hex(None), "{ 4D 5A 90 0A }");
+        assert_eq!(section.yara_hex(Some(2)), "{ 4D 5A }");
+        assert_eq!(section.yara_hex(Some(100)), "{ 4D 5A 90 0A }");
+    }
+
+    #[test]
+    fn bss_has_no_data_on_disk() {
+        let bss = Characteristics::IMAGE_SCN_CNT_UNINITIALIZED_DATA | DATA;
+        let mut builder = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x10], CODE)
+            .section(".bss", 0x2000, Vec::new(), bss);
+        builder.sections[1].virtual_size = 0x800;
+        let mut raw = builder.build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let section = &file.sections[1];
+        assert!(section.is_empty_on_disk());
+        assert!(section.is_uninitialized());
+        assert!(section.data.is_empty());
+        assert!(!file.sections[0].is_empty_on_disk());
+        assert!(!file.sections[0].is_uninitialized());
+
+        // The pointer isn't looked at when there's nothing to read.
+        let pointer = builder.section_table_offset() + 40 + 20;
+        put(&mut raw, pointer, &0xffff_0000_u32.to_le_bytes());
+        let (_, file) = File::parse(&raw).unwrap();
+        assert!(file.sections[1].data.is_empty());
+    }
 }
\ No newline at end of file
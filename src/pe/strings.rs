@@ -0,0 +1,100 @@
+use super::File;
+
+fn is_printable(b: u8) -> bool {
+    (0x20..0x7f).contains(&b) || b == b'\t'
+}
+
+/// Runs of at least `min_len` printable ASCII characters, with their offsets.
+fn ascii_runs(data: &[u8], min_len: usize) -> Vec<(String, usize)> {
+    let mut runs = Vec::new();
+    let mut start = None;
+    // One past the end, so a run reaching the end of `data` is closed too.
+    for at in 0..=data.len() {
+        match (data.get(at).copied().filter(|&b| is_printable(b)), start) {
+            (Some(_), None) => start = Some(at),
+            (None, Some(s)) => {
+                if at - s >= min_len {
+                    runs.push((String::from_utf8_lossy(&data[s..at]).to_string(), s));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    runs
+}
+
+/// Runs of at least `min_len` printable ASCII characters encoded as UTF-16LE,
+/// which is how most strings in Windows binaries are stored.
+fn utf16_runs(data: &[u8], min_len: usize) -> Vec<(String, usize)> {
+    let mut runs = Vec::new();
+    // Strings can start at either byte parity.
+    for parity in 0..2 {
+        let units: Vec<&[u8]> = data.get(parity..).unwrap_or(&[]).chunks_exact(2).collect();
+        let mut start = None;
+        for at in 0..=units.len() {
+            let printable = units.get(at).is_some_and(|u| u[1] == 0 && is_printable(u[0]));
+            match (printable, start) {
+                (true, None) => start = Some(at),
+                (false, Some(s)) => {
+                    if at - s >= min_len {
+                        let text = units[s..at].iter().map(|u| u[0] as char).collect();
+                        runs.push((text, parity + s * 2));
+                    }
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+    runs
+}
+
+impl File {
+    /// Extracts printable ASCII and UTF-16LE strings of at least `min_len`
+    /// characters from every section's raw data, with the RVA each starts at,
+    /// in RVA order.
+    #[allow(dead_code)]
+    pub fn strings(&self, min_len: usize) -> Vec<(String, u32)> {
+        // A zero length would match between every pair of bytes.
+        let min_len = min_len.max(1);
+        let mut strings = Vec::new();
+        for section in &self.sections {
+            let mut runs = ascii_runs(&section.data, min_len);
+            runs.extend(utf16_runs(&section.data, min_len));
+            strings.extend(
+                runs.into_iter()
+                    .map(|(s, offset)| (s, section.virtual_address.0.wrapping_add(offset as u32))),
+            );
+        }
+        strings.sort_by_key(|&(_, rva)| rva);
+        strings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::*;
+    use super::*;
+
+    #[test]
+    fn ascii_and_utf16_strings_are_found_with_their_rvas() {
+        let mut rdata = b"\x01\x02hello world\0ab\0".to_vec();
+        rdata.resize(0x21, 0xff);
+        for c in "Kernel".bytes() {
+            rdata.extend_from_slice(&[c, 0]);
+        }
+        rdata.push(0xff);
+        let raw = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x10], CODE)
+            .section(".rdata", 0x2000, rdata, RDATA)
+            .build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(
+            file.strings(4),
+            [("hello world".to_string(), 0x2002), ("Kernel".to_string(), 0x2021)]
+        );
+        assert_eq!(file.strings(2)[1], ("ab".to_string(), 0x200e));
+    }
+}
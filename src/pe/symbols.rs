@@ -0,0 +1,152 @@
+use super::header::PeHeader64;
+use super::util::*;
+use nom::number::Endianness;
+
+/// Size of one record in the COFF symbol table, auxiliary records included.
+const SYMBOL_SIZE: usize = 18;
+
+/// An entry in the COFF symbol table.
+///
+/// Executables normally have none (COFF debugging information is deprecated),
+/// but object files rely on it for linking.
+#[derive(PartialEq)]
+pub struct Symbol {
+    pub name: String,
+
+    /// Meaning depends on `section_number` and `storage_class`,
+    /// typically the offset of the symbol within its section.
+    pub value: u32,
+
+    /// One-based index into the section table,
+    /// or zero/negative for undefined, absolute and debug symbols.
+    pub section_number: i16,
+
+    pub symbol_type: u16,
+
+    pub storage_class: u8,
+
+    /// The number of auxiliary records following this one,
+    /// which aren't symbols and are skipped over.
+    pub number_of_aux_symbols: u8,
+}
+
+impl Symbol {
+    /// Parses the symbol table described by the header, resolving long names
+    /// through the string table that directly follows it.
+    pub fn parse_table<'a>(full_input: Input<'a>, header: &PeHeader64) -> Result<'a, Vec<Self>> {
+        use nom::{bytes::complete::take, error::context};
+
+        let count = header.number_of_symbols as usize;
+        if header.pointer_to_sym_table.0 == 0 || count == 0 {
+            return Ok((full_input, Vec::new()));
+        }
+
+        let (i, _) = context("PointerToSymbolTable", take(header.pointer_to_sym_table.0))(full_input)?;
+        let (string_table, table) =
+            context("SymbolTable", take(count.saturating_mul(SYMBOL_SIZE)))(i)?;
+
+        let mut symbols = Vec::new();
+        let mut records = table.chunks(SYMBOL_SIZE);
+        while let Some(record) = records.next() {
+            let (_, symbol) = Self::parse(record, string_table, header.endianness)?;
+            for _ in 0..symbol.number_of_aux_symbols {
+                records.next();
+            }
+            symbols.push(symbol);
+        }
+        Ok((string_table, symbols))
+    }
+
+    fn parse<'a>(i: Input<'a>, string_table: Input<'_>, endianness: Endianness) -> Result<'a, Self> {
+        use nom::{
+            bytes::complete::take,
+            error::context,
+            number::complete::{i16, le_u8, u16, u32},
+            sequence::tuple,
+        };
+        let (i, (raw_name, value, section_number, symbol_type, storage_class, number_of_aux_symbols)) =
+            tuple((
+                context("Name", take(8_usize)),
+                context("Value", u32(endianness)),
+                context("SectionNumber", i16(endianness)),
+                context("Type", u16(endianness)),
+                context("StorageClass", le_u8),
+                context("NumberOfAuxSymbols", le_u8),
+            ))(i)?;
+
+        // Names longer than 8 bytes are stored as four zero bytes followed
+        // by an offset into the string table (which counts its own size field).
+        let name_bytes = if raw_name[..4] == [0, 0, 0, 0] {
+            let (_, offset) = u32(endianness)(&raw_name[4..])?;
+            string_table.get(offset as usize..).unwrap_or(&[])
+        } else {
+            raw_name
+        };
+        let end = name_bytes.iter().position(|&b| b == 0).unwrap_or(name_bytes.len());
+        let name = String::from_utf8_lossy(&name_bytes[..end]).to_string();
+
+        Ok((
+            i,
+            Self {
+                name,
+                value,
+                section_number,
+                symbol_type,
+                storage_class,
+                number_of_aux_symbols,
+            },
+        ))
+    }
+}
+
+use std::fmt;
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} | value {:08x} | section {} | class {} |",
+            self.name, self.value, self.section_number, self.storage_class
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::*;
+    use super::super::File;
+
+    /// A symbol record, with `name` either inline or `/offset` into the string table.
+    fn record(name: [u8; 8], value: u32, section_number: i16, aux: u8) -> Vec<u8> {
+        let mut record = name.to_vec();
+        record.extend_from_slice(&value.to_le_bytes());
+        record.extend_from_slice(&section_number.to_le_bytes());
+        // IMAGE_SYM_DTYPE_FUNCTION, IMAGE_SYM_CLASS_EXTERNAL
+        record.extend_from_slice(&[0x20, 0, 2, aux]);
+        record
+    }
+
+    #[test]
+    fn object_files_parse_without_an_optional_header() {
+        let mut raw = object(0x8664, &[(".text", &[0xc3; 4], CODE)]);
+        raw[4..8].copy_from_slice(&0x6000_0000_u32.to_le_bytes());
+
+        let symbols_at = raw.len() as u32;
+        raw.extend(record(name("main"), 0, 1, 1));
+        // The auxiliary record, which isn't a symbol of its own.
+        raw.extend_from_slice(&[0xaa; 18]);
+        let mut long_name = [0; 8];
+        long_name[4..].copy_from_slice(&4_u32.to_le_bytes());
+        raw.extend(record(long_name, 2, 1, 0));
+        let strings = b"a_rather_long_name\0";
+        raw.extend_from_slice(&(4 + strings.len() as u32).to_le_bytes());
+        raw.extend_from_slice(strings);
+        raw[8..12].copy_from_slice(&symbols_at.to_le_bytes());
+        raw[12..16].copy_from_slice(&3_u32.to_le_bytes());
+
+        let (_, file) = File::parse(&raw).unwrap();
+        assert!(file.header.optional_header.is_none());
+        assert_eq!(file.header.time_date_stamp, 0x6000_0000);
+        let names: Vec<_> = file.symbols.iter().map(|s| (s.name.as_str(), s.value)).collect();
+        assert_eq!(names, [("main", 0), ("a_rather_long_name", 2)]);
+    }
+}
@@ -18,6 +18,54 @@ macro_rules! impl_parse_for_enum {
             }
         }
     };
+    // For fields of the COFF header, whose byte order follows the machine type.
+    (endian $type: ident, $number_parser: ident) => {
+        impl $type {
+            pub fn parse_endian<'a>(
+                endian: nom::number::Endianness,
+            ) -> impl Fn(Input<'a>) -> Result<'a, Self> {
+                move |i| {
+                    use nom::{
+                        combinator::map_res,
+                        error::{context, ErrorKind},
+                        number::complete::$number_parser,
+                    };
+                    let parser = map_res($number_parser(endian), |x| {
+                        Self::try_from(x).map_err(|_| ErrorKind::Alt)
+                    });
+                    context(stringify!($type), parser)(i)
+                }
+            }
+        }
+    };
+}
+
+/// Implements `FromStr` for a fieldless enum from its variant names, matched
+/// case-insensitively, for command line filters.
+#[macro_export]
+macro_rules! impl_from_str_for_enum {
+    ($type: ident, [$($variant: ident),* $(,)?]) => {
+        impl std::str::FromStr for $type {
+            type Err = String;
+
+            fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+                const VARIANTS: &[(&str, $type)] = &[$((stringify!($variant), $type::$variant)),*];
+                VARIANTS
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(s))
+                    .map(|&(_, variant)| variant)
+                    .ok_or_else(|| {
+                        let names: Vec<&str> = VARIANTS.iter().map(|&(name, _)| name).collect();
+                        format!(
+                            "unknown {} {:?}, expected one of: {}",
+                            stringify!($type),
+                            s,
+                            names.join(", ")
+                        )
+                    })
+            }
+        }
+    };
 }
 
 #[macro_export]
@@ -42,13 +90,74 @@ macro_rules! impl_parse_for_enumflags {
             }
         }
     };
+    // For fields of the COFF header, whose byte order follows the machine type.
+    (endian $type: ident, $number_parser: ident) => {
+        impl $type {
+            pub fn parse_endian<'a>(
+                endian: nom::number::Endianness,
+            ) -> impl Fn(Input<'a>) -> Result<'a, Self> {
+                move |i| {
+                    use nom::{
+                        combinator::map_res,
+                        error::{context, ErrorKind},
+                        number::complete::$number_parser,
+                    };
+                    let parser = map_res($number_parser(endian), |x| {
+                        Self::from_bits(x).ok_or(ErrorKind::Alt)
+                    });
+                    context(stringify!($type), parser)(i)
+                }
+            }
+        }
+    };
 }
 
 use derive_more::*;
 
+/// Rounds `value` up to a multiple of `alignment`, treating an alignment of zero as none.
+pub fn align_up(value: u32, alignment: u32) -> u32 {
+    match alignment {
+        0 => value,
+        a => value.div_ceil(a).wrapping_mul(a),
+    }
+}
+
+/// Appends `value` to `out` in the byte order `endian`, for writing back
+/// what was parsed with nom's `u16(endian)`.
+pub fn put_u16(out: &mut Vec<u8>, value: u16, endian: nom::number::Endianness) {
+    match endian {
+        nom::number::Endianness::Big => out.extend_from_slice(&value.to_be_bytes()),
+        _ => out.extend_from_slice(&value.to_le_bytes()),
+    }
+}
+
+/// Like [`put_u16`], for what was parsed with `u32(endian)`.
+pub fn put_u32(out: &mut Vec<u8>, value: u32, endian: nom::number::Endianness) {
+    match endian {
+        nom::number::Endianness::Big => out.extend_from_slice(&value.to_be_bytes()),
+        _ => out.extend_from_slice(&value.to_le_bytes()),
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct HexDump<'a>(pub &'a [u8]);
 
+impl<'a> HexDump<'a> {
+    /// The bytes as `offset | hex | ascii` lines of 16, for callers that render
+    /// them one at a time. Bytes outside printable ASCII show as `.`.
+    #[allow(dead_code)]
+    pub fn lines(&self) -> impl Iterator<Item = String> + 'a {
+        self.0.chunks(16).enumerate().map(|(n, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|x| format!("{:02x}", x)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&x| if x.is_ascii_graphic() || x == b' ' { x as char } else { '.' })
+                .collect();
+            format!("{:08x} | {:<47} | {}", n * 16, hex.join(" "), ascii)
+        })
+    }
+}
+
 use std::fmt;
 impl<'a> fmt::Debug for HexDump<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -80,17 +189,30 @@ impl fmt::Display for Addr {
     }
 }
 
+// Unlike `Display`, these honour flags like `#` and widths, as for the bare integer.
+impl fmt::LowerHex for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
+impl fmt::UpperHex for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::UpperHex::fmt(&self.0, f)
+    }
+}
+
 // This will come in handy when serializing
-impl Into<u64> for Addr {
-    fn into(self) -> u64 {
-        self.0
+impl From<Addr> for u64 {
+    fn from(x: Addr) -> Self {
+        x.0
     }
 }
 
 // This will come in handy when indexing / sub-slicing slices
-impl Into<usize> for Addr {
-    fn into(self) -> usize {
-        self.0 as usize
+impl From<Addr> for usize {
+    fn from(x: Addr) -> Self {
+        x.0 as usize
     }
 }
 
@@ -108,6 +230,19 @@ impl From<Addr32> for Addr {
     }
 }
 
+// Narrowing, so this hands back the raw value if it doesn't fit in 32 bits
+// rather than silently truncating it.
+impl std::convert::TryFrom<Addr> for Addr32 {
+    type Error = u64;
+
+    fn try_from(x: Addr) -> std::result::Result<Self, Self::Error> {
+        match u32::try_from(x.0) {
+            Ok(n) => Ok(Self(n)),
+            Err(_) => Err(x.0),
+        }
+    }
+}
+
 impl Addr {
     pub fn parse(i: Input) -> Result<Self> {
         use nom::{combinator::map, number::complete::le_u64};
@@ -115,7 +250,7 @@ impl Addr {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Add, Sub)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Add, Sub)]
 pub struct Addr32(pub u32);
 
 impl fmt::Debug for Addr32 {
@@ -130,17 +265,29 @@ impl fmt::Display for Addr32 {
     }
 }
 
+impl fmt::LowerHex for Addr32 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
+impl fmt::UpperHex for Addr32 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::UpperHex::fmt(&self.0, f)
+    }
+}
+
 // This will come in handy when serializing
-impl Into<u32> for Addr32 {
-    fn into(self) -> u32 {
-        self.0
+impl From<Addr32> for u32 {
+    fn from(x: Addr32) -> Self {
+        x.0
     }
 }
 
 // This will come in handy when indexing / sub-slicing slices
-impl Into<usize> for Addr32 {
-    fn into(self) -> usize {
-        self.0 as usize
+impl From<Addr32> for usize {
+    fn from(x: Addr32) -> Self {
+        x.0 as usize
     }
 }
 
@@ -156,6 +303,11 @@ impl Addr32 {
         use nom::{combinator::map, number::complete::le_u32};
         map(le_u32, From::from)(i)
     }
+
+    pub fn parse_endian<'a>(endian: nom::number::Endianness) -> impl Fn(Input<'a>) -> Result<'a, Self> {
+        use nom::{combinator::map, number::complete::u32};
+        move |i| map(u32(endian), From::from)(i)
+    }
 }
 
 /// SectionName is a simple type that allows nicer display
@@ -166,15 +318,27 @@ pub struct SectionName {
     name: String,
 }
 
+impl SectionName {
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+}
+
 impl fmt::Debug for SectionName {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:>08}", self.name)
     }
 }
 
-impl Into<String> for SectionName {
-    fn into(self) -> String {
-        self.name
+impl fmt::Display for SectionName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl From<SectionName> for String {
+    fn from(s: SectionName) -> Self {
+        s.name
     }
 }
 
@@ -188,3 +352,44 @@ impl From<String> for SectionName {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn narrowing_an_addr_checks_the_range() {
+        assert_eq!(Addr32::try_from(Addr(0xffff_ffff)), Ok(Addr32(0xffff_ffff)));
+        assert_eq!(Addr32::try_from(Addr(0x1_0000_0000)), Err(0x1_0000_0000));
+        assert_eq!(Addr::from(Addr32(0x1000)), Addr(0x1000));
+    }
+
+    #[test]
+    fn addresses_format_as_hex_like_their_integers() {
+        assert_eq!(format!("{:X}", Addr32(0xabc)), "ABC");
+        assert_eq!(format!("{:x}", Addr32(0xabc)), "abc");
+        assert_eq!(format!("{:#010x}", Addr32(0xabc)), "0x00000abc");
+        assert_eq!(format!("{:X}", Addr(0x1_4000_1000)), "140001000");
+        assert_eq!(format!("{:#x}", Addr(0xffff_f800_0000_0000)), "0xfffff80000000000");
+    }
+
+    #[test]
+    fn hex_dump_lines_cover_sixteen_bytes_each() {
+        let mut data: Vec<u8> = (0x20..0x50).collect();
+        data[0] = 0;
+        data[47] = 0xff;
+
+        let lines: Vec<String> = HexDump(&data).lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "00000000 | 00 21 22 23 24 25 26 27 28 29 2a 2b 2c 2d 2e 2f | .!\"#$%&'()*+,-./",
+                "00000010 | 30 31 32 33 34 35 36 37 38 39 3a 3b 3c 3d 3e 3f | 0123456789:;<=>?",
+                "00000020 | 40 41 42 43 44 45 46 47 48 49 4a 4b 4c 4d 4e ff | @ABCDEFGHIJKLMN.",
+            ]
+        );
+        let short: Vec<String> = HexDump(b"MZ").lines().collect();
+        assert_eq!(short, [format!("00000000 | {:<47} | MZ", "4d 5a")]);
+    }
+}
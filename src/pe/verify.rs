@@ -0,0 +1,566 @@
+use super::header::{Characteristics, OptionalHeader64, DOS_HEADER_SIZE};
+use super::sections::{Characteristics as SectionCharacteristics, Section};
+use super::util::*;
+use super::File;
+use std::fmt;
+use std::ops::Range;
+
+/// The Windows loader refuses images with more sections than this.
+const MAX_SECTIONS: u16 = 96;
+
+/// Gaps between sections' raw data at least this large are reported by `verify`.
+const LARGE_RAW_GAP: usize = 0x200;
+
+/// Executable sections with less raw data than this are reported by `verify`.
+const TINY_CODE_SECTION: u32 = 64;
+
+/// Overlapping sections past this many are summed up in a single anomaly, so a
+/// crafted table of thousands of them doesn't produce as many messages.
+const MAX_OVERLAP_REPORTS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Unusual, but seen in legitimate binaries.
+    Warning,
+    /// Breaks the spec, so either the loader rejects it or it was crafted.
+    Error,
+}
+
+/// A structural oddity found by [`File::verify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Anomaly {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Anomaly {
+    fn warning(message: String) -> Self {
+        Self { severity: Severity::Warning, message }
+    }
+
+    fn error(message: String) -> Self {
+        Self { severity: Severity::Error, message }
+    }
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "[{}] {}", severity, self.message)
+    }
+}
+
+/// Pairs of sections whose non-empty `range`s overlap, found by sweeping over
+/// them in order of where they start. Each section is paired with the one
+/// reaching furthest among those starting before it, so it's reported once
+/// however many others it overlaps.
+fn overlapping_pairs(
+    sections: &[Section],
+    range: impl Fn(&Section) -> Range<u64>,
+) -> Vec<(&Section, &Section)> {
+    let mut sorted: Vec<&Section> = sections.iter().filter(|s| !range(s).is_empty()).collect();
+    sorted.sort_by_key(|s| range(s).start);
+
+    let mut pairs = Vec::new();
+    let mut furthest: Option<&Section> = None;
+    for section in sorted {
+        match furthest {
+            Some(prev) if range(section).start < range(prev).end => {
+                pairs.push((prev, section));
+                if range(section).end > range(prev).end {
+                    furthest = Some(section);
+                }
+            }
+            _ => furthest = Some(section),
+        }
+    }
+    pairs
+}
+
+fn push_overlaps(
+    anomalies: &mut Vec<Anomaly>,
+    pairs: &[(&Section, &Section)],
+    anomaly: fn(String) -> Anomaly,
+    what: &str,
+) {
+    for (a, b) in pairs.iter().take(MAX_OVERLAP_REPORTS) {
+        anomalies.push(anomaly(format!("sections {} and {} {}", a.name, b.name, what)));
+    }
+    if pairs.len() > MAX_OVERLAP_REPORTS {
+        anomalies.push(anomaly(format!(
+            "{} more sections {}",
+            pairs.len() - MAX_OVERLAP_REPORTS,
+            what
+        )));
+    }
+}
+
+impl File {
+    /// Describes what's odd about where the entry point lives, if anything.
+    ///
+    /// Compilers put it in an executable, read-only code section, so anything else
+    /// suggests a packer (which typically appends its stub as the last section)
+    /// or a hand-crafted header. DLLs may legitimately have no entry point.
+    pub fn entry_point_anomaly(&self) -> Option<String> {
+        let oh = self.header.optional_header.as_ref()?;
+        let entry_point = oh.entry_point.0;
+        if entry_point == 0 {
+            let is_dll = self.header.characteristics.contains(Characteristics::IMAGE_FILE_DLL);
+            return (!is_dll).then(|| "executable has no entry point".to_string());
+        }
+
+        let section = match self.entry_point_section() {
+            Some(section) => section,
+            None if entry_point >= oh.windows_header.size_of_image => {
+                return Some(format!("entry point {:#x} is beyond SizeOfImage", entry_point))
+            }
+            None => return Some(format!("entry point {:#x} is outside all sections", entry_point)),
+        };
+        if section.characteristics.contains(SectionCharacteristics::WRITE) {
+            Some(format!("entry point is in writable section {}", section.name))
+        } else if !section.characteristics.contains(SectionCharacteristics::EXECUTE) {
+            Some(format!("entry point is in non-executable section {}", section.name))
+        } else if self.sections.len() > 1 && self.sections.last().is_some_and(|last| std::ptr::eq(last, section)) {
+            Some(format!("entry point is in the last section, {}", section.name))
+        } else {
+            None
+        }
+    }
+
+    /// Sections mapped over the headers, i.e. starting below `SizeOfHeaders`,
+    /// which lets a crafted image hide data there or rewrite its own headers at load.
+    ///
+    /// Always empty for object files, which aren't mapped.
+    pub fn sections_overlapping_headers(&self) -> Vec<&Section> {
+        let size_of_headers = match &self.header.optional_header {
+            Some(oh) => oh.windows_header.size_of_headers,
+            None => return Vec::new(),
+        };
+        self.sections
+            .iter()
+            .filter(|s| s.virtual_address.0 < size_of_headers)
+            .collect()
+    }
+
+    /// Executable sections with fewer than `threshold` bytes of raw data, like the
+    /// stub sections of loaders that unpack or download their real code at runtime.
+    pub fn tiny_code_sections(&self, threshold: u32) -> Vec<&Section> {
+        self.sections
+            .iter()
+            .filter(|s| {
+                s.characteristics.intersects(
+                    SectionCharacteristics::EXECUTE | SectionCharacteristics::IMAGE_SCN_CNT_CODE,
+                )
+            })
+            .filter(|s| s.size_of_raw_data < threshold)
+            .collect()
+    }
+
+    /// A quick yes/no pre-filter for whether the loader could plausibly run this,
+    /// much cheaper than [`File::verify`]: it's marked as an executable image,
+    /// has between 1 and the loader's maximum number of sections, its entry point
+    /// lies in a section (or it's a DLL without one), and the optional header's
+    /// bitness matches the machine's.
+    #[allow(dead_code)]
+    pub fn is_plausibly_loadable(&self) -> bool {
+        let header = &self.header;
+        let oh = match &header.optional_header {
+            Some(oh) => oh,
+            None => return false,
+        };
+        let is_dll = header.characteristics.contains(Characteristics::IMAGE_FILE_DLL);
+        let entry_point_ok = match oh.entry_point.0 {
+            0 => is_dll,
+            _ => self.entry_point_section().is_some(),
+        };
+        header.characteristics.contains(Characteristics::IMAGE_FILE_EXECUTABLE_IMAGE)
+            && (1..=MAX_SECTIONS).contains(&header.number_of_sections)
+            && entry_point_ok
+            && self.thunk_size() == header.machine.pointer_size()
+    }
+
+    /// Runs every structural check at once, rather than calling each validator separately.
+    pub fn verify(&self) -> Vec<Anomaly> {
+        let mut anomalies = Vec::new();
+        self.verify_coff_header(&mut anomalies);
+        self.verify_sections(&mut anomalies);
+        if let Some(oh) = &self.header.optional_header {
+            self.verify_optional_header(oh, &mut anomalies);
+        }
+        anomalies
+    }
+
+    fn verify_coff_header(&self, anomalies: &mut Vec<Anomaly>) {
+        let header = &self.header;
+        // Only a PE header placed after the DOS header leaves room for the stub.
+        if let Some(e_lfanew) = self.e_lfanew().filter(|&at| at < DOS_HEADER_SIZE) {
+            anomalies.push(Anomaly::error(format!(
+                "e_lfanew {:#x} puts the PE header inside the DOS header, which ends at {:#x}",
+                e_lfanew, DOS_HEADER_SIZE
+            )));
+        }
+        if header.number_of_sections > MAX_SECTIONS {
+            anomalies.push(Anomaly::error(format!(
+                "{} sections declared, the loader allows at most {}",
+                header.number_of_sections, MAX_SECTIONS
+            )));
+        }
+    }
+
+    fn verify_sections(&self, anomalies: &mut Vec<Anomaly>) {
+        let in_memory = overlapping_pairs(&self.sections, Section::mem_range);
+        push_overlaps(anomalies, &in_memory, Anomaly::error, "overlap in memory");
+        let on_disk = overlapping_pairs(&self.sections, Section::file_range);
+        push_overlaps(anomalies, &on_disk, Anomaly::warning, "share raw data");
+
+        for section in &self.sections {
+            if section.wraps_address_space() {
+                anomalies.push(Anomaly::error(format!(
+                    "section {} wraps the address space: {:#x} bytes from RVA {:#x}",
+                    section.name, section.virtual_size, section.virtual_address.0
+                )));
+            }
+            if section.characteristics.contains(
+                SectionCharacteristics::WRITE | SectionCharacteristics::EXECUTE,
+            ) {
+                anomalies.push(Anomaly::warning(format!(
+                    "section {} is both writable and executable",
+                    section.name
+                )));
+            }
+            for conflict in section.flag_conflicts() {
+                anomalies.push(Anomaly::warning(format!("section {} {}", section.name, conflict)));
+            }
+        }
+
+        for gap in self.raw_gaps().into_iter().filter(|gap| gap.len() >= LARGE_RAW_GAP) {
+            anomalies.push(Anomaly::warning(format!(
+                "{:#x} bytes at {:#x}..{:#x} are not part of any section",
+                gap.len(),
+                gap.start,
+                gap.end
+            )));
+        }
+
+        // Objects routinely have a section per small function.
+        let tiny_code = match self.header.optional_header {
+            Some(_) => self.tiny_code_sections(TINY_CODE_SECTION),
+            None => Vec::new(),
+        };
+        for section in tiny_code {
+            anomalies.push(Anomaly::warning(format!(
+                "executable section {} has only {:#x} bytes of raw data",
+                section.name, section.size_of_raw_data
+            )));
+        }
+    }
+
+    fn verify_optional_header(&self, oh: &OptionalHeader64, anomalies: &mut Vec<Anomaly>) {
+        let header = &self.header;
+        let wh = &oh.windows_header;
+
+        let pe32_plus = self.is_pe32_plus();
+        if self.thunk_size() != header.machine.pointer_size() {
+            anomalies.push(Anomaly::error(format!(
+                "{:?} machine type with a {} optional header magic",
+                header.machine,
+                if pe32_plus { "PE32+" } else { "PE32" }
+            )));
+        }
+        if pe32_plus
+            && header
+                .characteristics
+                .contains(Characteristics::IMAGE_FILE_32BIT_MACHINE)
+        {
+            anomalies.push(Anomaly::warning(
+                "IMAGE_FILE_32BIT_MACHINE set on a PE32+ image".to_string(),
+            ));
+        }
+
+        if let Err(e) = wh.validate_alignment() {
+            anomalies.push(Anomaly::error(e.to_string()));
+        }
+
+        if let Some(end) = self
+            .sections
+            .iter()
+            .map(|s| s.virtual_address.0.wrapping_add(s.virtual_size))
+            .max()
+        {
+            let expected = align_up(end, wh.section_alignment);
+            if wh.size_of_image != expected {
+                anomalies.push(Anomaly::warning(format!(
+                    "SizeOfImage is {:#x} but the sections end at {:#x}",
+                    wh.size_of_image, expected
+                )));
+            }
+        }
+
+        for section in self.sections_overlapping_headers() {
+            anomalies.push(Anomaly::error(format!(
+                "section {} at {:#x} overlaps the headers, which end at {:#x}",
+                section.name, section.virtual_address.0, wh.size_of_headers
+            )));
+        }
+
+        if let Some(message) = self.entry_point_anomaly() {
+            // An entry point the loader can't run from at all is worse than a suspicious one.
+            anomalies.push(match self.entry_point_section() {
+                Some(_) => Anomaly::warning(message),
+                None => Anomaly::error(message),
+            });
+        }
+
+        let capacity = oh.data_directory_capacity(header.size_of_optional_header);
+        if wh.number_of_rva_and_sizes > capacity {
+            anomalies.push(Anomaly::error(format!(
+                "{} data directories declared, but SizeOfOptionalHeader leaves room for {}",
+                wh.number_of_rva_and_sizes, capacity
+            )));
+        }
+
+        if self.timestamp_consistency() == Some(false) {
+            anomalies.push(Anomaly::warning(format!(
+                "debug directory timestamps don't match the COFF header's {:#x}",
+                header.time_date_stamp
+            )));
+        }
+
+        // Packers sometimes stash values in these.
+        for (name, value) in &[
+            ("Win32VersionValue", self.win32_version_value()),
+            ("LoaderFlags", self.loader_flags()),
+        ] {
+            if let Some(value) = value.filter(|&v| v != 0) {
+                anomalies.push(Anomaly::warning(format!(
+                    "reserved {} is {:#x}, it must be zero",
+                    name, value
+                )));
+            }
+        }
+        for (name, dir) in &[
+            ("Architecture", &oh.data_directories.architecture),
+            ("Reserved", &oh.data_directories.reserved),
+        ] {
+            if dir.virtual_addr.0 != 0 || dir.size != 0 {
+                anomalies.push(Anomaly::warning(format!(
+                    "reserved {} data directory is {:#x}+{:#x}, it must be zero",
+                    name, dir.virtual_addr.0, dir.size
+                )));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::*;
+    use super::*;
+
+    fn messages(raw: &[u8]) -> Vec<String> {
+        let (_, file) = File::parse(raw).unwrap();
+        file.verify().into_iter().map(|a| a.message).collect()
+    }
+
+    #[test]
+    fn reports_several_anomalies_at_once() {
+        let mut builder = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x200], CODE | SectionCharacteristics::WRITE)
+            .section(".data", 0x1100, vec![0; 0x200], DATA)
+            .entry_point(0x1250);
+        builder.section_alignment = 0x300;
+        let found = messages(&builder.build());
+
+        for expected in [
+            "sections .text and .data overlap in memory",
+            "section .text is both writable and executable",
+            "entry point is in writable section .data",
+            "SectionAlignment 0x300 is not a power of two",
+        ] {
+            assert!(found.iter().any(|m| m == expected), "{:?} not in {:?}", expected, found);
+        }
+    }
+
+    #[test]
+    fn overlap_reports_are_capped() {
+        let mut builder = PeBuilder::default();
+        for _ in 0..200 {
+            builder = builder.section(".text", 0x1000, vec![0; 0x10], CODE);
+        }
+        // All sharing one block of raw data too.
+        for section in &mut builder.sections {
+            section.pointer_to_raw_data = Some(0x400);
+        }
+        let found = messages(&builder.build());
+
+        let in_memory: Vec<_> = found.iter().filter(|m| m.ends_with("overlap in memory")).collect();
+        assert_eq!(in_memory.len(), MAX_OVERLAP_REPORTS + 1);
+        assert_eq!(in_memory.last().unwrap().as_str(), "191 more sections overlap in memory");
+        let shared = found.iter().filter(|m| m.ends_with("share raw data")).count();
+        assert_eq!(shared, MAX_OVERLAP_REPORTS + 1);
+    }
+
+    #[test]
+    fn adjacent_sections_do_not_overlap() {
+        let raw = PeBuilder::default()
+            .section(".text", 0x1000, vec![0; 0x1000], CODE)
+            .section(".data", 0x2000, vec![0; 0x10], DATA)
+            .build();
+        assert!(!messages(&raw).iter().any(|m| m.contains("overlap") || m.contains("share")));
+    }
+
+    #[test]
+    fn reserved_fields_must_be_zero() {
+        let mut builder = PeBuilder::default().section(".text", 0x1000, vec![0xc3; 0x200], CODE);
+        builder.loader_flags = 0x1;
+        let raw = builder.build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.loader_flags(), Some(1));
+        assert_eq!(file.win32_version_value(), Some(0));
+        let found = messages(&raw);
+        assert!(found.iter().any(|m| m == "reserved LoaderFlags is 0x1, it must be zero"));
+        assert!(!found.iter().any(|m| m.contains("Win32VersionValue")));
+    }
+
+    fn entry_point_anomaly(builder: PeBuilder) -> Option<String> {
+        let (_, file) = File::parse(&builder.build()).unwrap();
+        file.entry_point_anomaly()
+    }
+
+    #[test]
+    fn entry_points_outside_plain_code_are_flagged() {
+        let image = || {
+            PeBuilder::default()
+                .section(".text", 0x1000, vec![0xc3; 0x200], CODE)
+                .section(".data", 0x2000, vec![0; 0x200], DATA)
+                .section(".rsrc", 0x3000, vec![0; 0x200], RDATA)
+        };
+        assert_eq!(entry_point_anomaly(image().entry_point(0x1000)), None);
+        assert_eq!(
+            entry_point_anomaly(image().entry_point(0x2010)).as_deref(),
+            Some("entry point is in writable section .data")
+        );
+        assert_eq!(
+            entry_point_anomaly(image().entry_point(0x3010)).as_deref(),
+            Some("entry point is in non-executable section .rsrc")
+        );
+        assert_eq!(
+            entry_point_anomaly(image().entry_point(0x9000)).as_deref(),
+            Some("entry point 0x9000 is beyond SizeOfImage")
+        );
+        assert_eq!(
+            entry_point_anomaly(image().entry_point(0)).as_deref(),
+            Some("executable has no entry point")
+        );
+        let packed = image().section(".packed", 0x4000, vec![0xc3; 0x200], CODE);
+        assert_eq!(
+            entry_point_anomaly(packed.entry_point(0x4000)).as_deref(),
+            Some("entry point is in the last section, .packed")
+        );
+    }
+
+    #[test]
+    fn a_section_at_rva_zero_overlaps_the_headers() {
+        let raw = PeBuilder::default()
+            .section(".hdr", 0, vec![0; 0x200], DATA)
+            .section(".text", 0x1000, vec![0xc3; 0x200], CODE)
+            .build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let overlapping: Vec<&str> =
+            file.sections_overlapping_headers().iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(overlapping, [".hdr"]);
+        let expected = "section .hdr at 0x0 overlaps the headers, which end at 0x400";
+        assert!(messages(&raw).iter().any(|m| m == expected));
+    }
+
+    #[test]
+    fn tiny_code_sections_are_flagged() {
+        let raw = PeBuilder::default()
+            .section(".stub", 0x1000, vec![0xc3; 0x10], CODE)
+            .section(".text", 0x2000, vec![0xc3; 0x200], CODE)
+            .section(".tiny", 0x3000, vec![0; 0x10], DATA)
+            .build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let tiny: Vec<&str> = file.tiny_code_sections(64).iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(tiny, [".stub"]);
+        assert!(file.tiny_code_sections(16).is_empty());
+        let expected = "executable section .stub has only 0x10 bytes of raw data";
+        assert!(messages(&raw).iter().any(|m| m == expected));
+    }
+
+    #[test]
+    fn plausibly_loadable_images() {
+        let loadable = |raw: &[u8]| File::parse(raw).unwrap().1.is_plausibly_loadable();
+
+        assert!(loadable(&simple_image(vec![0xc3])));
+        // Headers only, without even a section for the entry point to be in.
+        assert!(!loadable(&PeBuilder::default().entry_point(0x1000).build()));
+        // A PE32 optional header for a 64-bit machine.
+        let mismatched = PeBuilder {
+            pe32: true,
+            ..PeBuilder::default()
+        }
+        .section(".text", 0x1000, vec![0xc3], CODE)
+        .entry_point(0x1000);
+        assert!(!loadable(&mismatched.build()));
+        assert!(!loadable(&object(0x8664, &[(".text", &[0xc3], CODE)])));
+    }
+
+    #[test]
+    fn a_pe_header_inside_the_dos_header_is_flagged() {
+        let builder = PeBuilder::default().section(".text", 0x1000, vec![0xc3; 0x10], CODE);
+        let mut raw = builder.build();
+        // Move the headers up to 0x10, where `e_lfanew` itself lands in `BaseOfCode`.
+        let headers = raw[builder.e_lfanew()..builder.section_table_offset() + 40].to_vec();
+        raw[0x10..0x10 + headers.len()].copy_from_slice(&headers);
+        put(&mut raw, 0x3c, &0x10_u32.to_le_bytes());
+
+        let (_, file) = File::parse(&raw).unwrap();
+        assert_eq!(file.e_lfanew(), Some(0x10));
+        assert_eq!(file.sections[0].name.as_str(), ".text");
+        let expected = "e_lfanew 0x10 puts the PE header inside the DOS header, which ends at 0x40";
+        assert!(messages(&raw).iter().any(|m| m == expected));
+
+        let raw = builder.build();
+        assert!(!messages(&raw).iter().any(|m| m.starts_with("e_lfanew")));
+    }
+
+    #[test]
+    fn directories_past_the_optional_header_are_not_read() {
+        let builder = PeBuilder {
+            number_of_rva_and_sizes: 0x40,
+            size_of_optional_header: Some(112 + 8 * 16),
+            ..PeBuilder::default()
+        };
+        let raw = builder.section(".text", 0x1000, vec![0xc3; 0x10], CODE).build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        let oh = file.header.optional_header.as_ref().unwrap();
+        assert_eq!(oh.windows_header.number_of_rva_and_sizes, 0x40);
+        assert!(oh.data_directories.extra.is_empty());
+        assert_eq!(file.sections[0].name.as_str(), ".text");
+        let expected = "64 data directories declared, but SizeOfOptionalHeader leaves room for 16";
+        assert!(messages(&raw).iter().any(|m| m == expected));
+    }
+
+    #[test]
+    fn only_executables_need_an_entry_point() {
+        let image = |characteristics| PeBuilder {
+            characteristics,
+            ..PeBuilder::default().section(".text", 0x1000, vec![0xc3; 0x200], CODE)
+        };
+        // IMAGE_FILE_DLL | IMAGE_FILE_EXECUTABLE_IMAGE | IMAGE_FILE_LARGE_ADDRESS_AWARE
+        assert_eq!(entry_point_anomaly(image(0x2022)), None);
+        assert!(!messages(&image(0x2022).build()).iter().any(|m| m.contains("no entry point")));
+
+        let exe = entry_point_anomaly(image(0x22));
+        assert_eq!(exe.as_deref(), Some("executable has no entry point"));
+        assert_eq!(entry_point_anomaly(image(0x2022).entry_point(0x1000)), None);
+    }
+}
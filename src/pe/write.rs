@@ -0,0 +1,376 @@
+use super::header::{Characteristics, DllCharacteristics, OptionalHeader64, WindowsFields};
+use super::constants::DataDirectoryKind;
+use super::util::*;
+use super::sections::Section;
+use super::File;
+use std::convert::TryFrom;
+use nom::number::Endianness;
+
+/// Copies `bytes` into `out` at `at`, growing `out` if needed.
+fn write_at(out: &mut Vec<u8>, at: usize, bytes: &[u8]) {
+    if out.len() < at + bytes.len() {
+        out.resize(at + bytes.len(), 0);
+    }
+    out[at..at + bytes.len()].copy_from_slice(bytes);
+}
+
+/// The PE image checksum, as computed by `CheckSumMappedFile`: a ones'-complement
+/// style sum of the file's 16-bit words, with the checksum field itself read as
+/// zero, plus the file's length.
+fn image_checksum(raw: &[u8], checksum_at: usize) -> u32 {
+    // Zeroing the field rather than skipping its words keeps this right when
+    // an odd `e_lfanew` leaves the field misaligned.
+    let mut raw = raw.to_vec();
+    if let Some(field) = raw.get_mut(checksum_at..checksum_at.saturating_add(4)) {
+        field.fill(0);
+    }
+
+    let mut sum: u64 = 0;
+    for word in raw.chunks(2) {
+        // An odd trailing byte counts as the low byte of a final word.
+        sum += u64::from(word[0]) | u64::from(*word.get(1).unwrap_or(&0)) << 8;
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum = (sum & 0xffff) + (sum >> 16);
+    (sum as u32).wrapping_add(raw.len() as u32)
+}
+
+impl File {
+    #[allow(dead_code)]
+    pub fn set_characteristic(&mut self, flag: Characteristics, on: bool) {
+        self.header.characteristics.set(flag, on);
+    }
+
+    /// Does nothing for object files, which have no optional header to hold these.
+    #[allow(dead_code)]
+    pub fn set_dll_characteristic(&mut self, flag: DllCharacteristics, on: bool) {
+        if let Some(oh) = &mut self.header.optional_header {
+            oh.windows_header.dll_characteristics.set(flag, on);
+        }
+    }
+
+    /// Replaces a section's raw data, moving the raw data of the sections
+    /// after it along if it no longer fits, and growing `size_of_image` if its
+    /// virtual size now reaches further.
+    ///
+    /// Sections are never moved in memory, so growing one past the next
+    /// section's virtual address is left to the caller (`verify` reports it).
+    /// Returns `None`, changing nothing, if there is no section at `index` or
+    /// the new layout doesn't fit in 32 bits.
+    #[allow(dead_code)]
+    pub fn set_section_data(&mut self, index: usize, data: Vec<u8>) -> Option<()> {
+        let (file_alignment, section_alignment) = match &self.header.optional_header {
+            Some(oh) => (
+                oh.windows_header.file_alignment,
+                oh.windows_header.section_alignment,
+            ),
+            // Object files have no alignment requirements for raw data.
+            None => (1, 1),
+        };
+
+        // The header values may be crafted, so everything is checked before
+        // anything is changed.
+        let section = self.sections.get(index)?;
+        let len = u32::try_from(data.len()).ok()?;
+        let padded = Some(align_up(len, file_alignment)).filter(|&padded| padded >= len)?;
+        let old_end = section.pointer_to_raw_data.0.checked_add(section.size_of_raw_data)?;
+        let new_end = section.pointer_to_raw_data.0.checked_add(padded)?;
+        let mem_end = section.virtual_address.0.checked_add(section.virtual_size.max(len))?;
+        let shift = align_up(new_end.saturating_sub(old_end), file_alignment);
+        let moves = |n: usize, other: &Section| {
+            n != index && shift != 0 && !other.is_empty_on_disk() && other.pointer_to_raw_data.0 >= old_end
+        };
+        for (n, other) in self.sections.iter().enumerate() {
+            if moves(n, other) {
+                other.pointer_to_raw_data.0.checked_add(shift)?;
+            }
+        }
+
+        self.sections[index].set_data(data, file_alignment);
+        for (n, other) in self.sections.iter_mut().enumerate() {
+            if moves(n, other) {
+                other.pointer_to_raw_data.0 += shift;
+            }
+        }
+
+        if let Some(oh) = &mut self.header.optional_header {
+            let wh = &mut oh.windows_header;
+            wh.size_of_image = wh.size_of_image.max(align_up(mem_end, section_alignment));
+        }
+        Some(())
+    }
+
+    /// Recomputes the checksum over `raw`, the image's bytes after whatever edits
+    /// were made to them, and writes it into the `CheckSum` field.
+    ///
+    /// Returns the new checksum, or `None` for object files or if `raw` is too
+    /// short to hold the field.
+    #[allow(dead_code)]
+    pub fn checksum_recalc_and_patch(&self, raw: &mut [u8]) -> Option<u32> {
+        let at = self.checksum_field_offset()?;
+        if raw.len() < at + 4 {
+            return None;
+        }
+        let checksum = image_checksum(raw, at);
+        raw[at..at + 4].copy_from_slice(&checksum.to_le_bytes());
+        Some(checksum)
+    }
+
+    /// Serializes the file back into a PE, using `raw` (the buffer it was parsed from)
+    /// as a template for everything the model doesn't cover, like the DOS stub.
+    ///
+    /// Headers and section data are written from the model, so changes made
+    /// through the setters persist. The overlay (including the certificate table)
+    /// is carried over unchanged after the last section, wherever that now ends.
+    #[allow(dead_code)]
+    pub fn to_bytes(&self, raw: &[u8]) -> Vec<u8> {
+        let coff_offset = self.header.coff_offset;
+        let optional_header_offset = coff_offset + 20;
+        let section_table_offset =
+            optional_header_offset + self.header.size_of_optional_header as usize;
+        let size_of_headers = self
+            .header
+            .optional_header
+            .as_ref()
+            .map_or(0, |oh| oh.windows_header.size_of_headers as usize);
+        let headers_len = size_of_headers.max(section_table_offset + self.sections.len() * 40);
+
+        // Where the sections ended before any were moved, from the table in `raw`.
+        let read_u32 = |at: usize| {
+            let b = raw.get(at..at + 4)?;
+            let b = [b[0], b[1], b[2], b[3]];
+            Some(match self.header.endianness {
+                Endianness::Big => u32::from_be_bytes(b),
+                _ => u32::from_le_bytes(b),
+            })
+        };
+        let original_end = (0..self.sections.len())
+            .filter_map(|n| {
+                let entry = section_table_offset + n * 40;
+                let size = read_u32(entry + 16)?;
+                let pointer = read_u32(entry + 20)?;
+                (size != 0).then(|| pointer as usize + size as usize)
+            })
+            .max()
+            .unwrap_or(0)
+            .max(headers_len);
+
+        // Starting from everything up to there keeps the padding between sections.
+        let mut out = raw[..original_end.min(raw.len())].to_vec();
+        out.resize(headers_len.max(out.len()), 0);
+
+        write_at(&mut out, coff_offset, &self.header.coff_bytes());
+        if let Some(oh) = &self.header.optional_header {
+            let mut bytes = oh.to_bytes();
+            bytes.truncate(self.header.size_of_optional_header as usize);
+            write_at(&mut out, optional_header_offset, &bytes);
+        }
+        for (n, section) in self.sections.iter().enumerate() {
+            write_at(&mut out, section_table_offset + n * 40, &section.header_bytes(self.header.endianness));
+        }
+        for section in self.sections.iter().filter(|s| !s.is_empty_on_disk()) {
+            write_at(&mut out, section.pointer_to_raw_data.into(), &section.data);
+        }
+
+        let new_end = self.raw_data_end().unwrap_or(0).max(headers_len);
+        out.resize(new_end, 0);
+        let overlay = raw.get(original_end..).unwrap_or(&[]);
+        if new_end != original_end {
+            self.move_certificate_table(&mut out, original_end, new_end);
+        }
+        out.extend_from_slice(overlay);
+        out
+    }
+
+    /// Points the certificate table directory in `out` at where the overlay it
+    /// lives in moved to, as unlike other directories it holds a file offset.
+    fn move_certificate_table(&self, out: &mut Vec<u8>, original_end: usize, new_end: usize) {
+        let oh = match &self.header.optional_header {
+            Some(oh) if oh.data_directories.certificate_table.is_present() => oh,
+            _ => return,
+        };
+        let data_directories_offset =
+            OptionalHeader64::data_directories_offset(oh.base_of_data.is_some());
+        let certificate_table_offset =
+            data_directories_offset + DataDirectoryKind::Certificate.index() * 8;
+        let offset = oh.data_directories.certificate_table.virtual_addr.0 as usize;
+        if offset < original_end
+            || (self.header.size_of_optional_header as usize) < certificate_table_offset + 8
+        {
+            return;
+        }
+        let moved = (offset - original_end + new_end) as u32;
+        write_at(
+            out,
+            self.header.coff_offset + 20 + certificate_table_offset,
+            &moved.to_le_bytes(),
+        );
+    }
+}
+
+/// Toggles for the exploit mitigations the loader enables based on `DllCharacteristics`,
+/// for hardening tools. Serialize with [`File::to_bytes`] to persist them.
+#[allow(dead_code)]
+impl WindowsFields {
+    /// ASLR: lets the loader relocate the image, which needs a `.reloc` section to work.
+    pub fn set_aslr(&mut self, on: bool) {
+        self.dll_characteristics
+            .set(DllCharacteristics::IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE, on);
+    }
+
+    /// 64-bit ASLR, only effective together with [`WindowsFields::set_aslr`].
+    pub fn set_high_entropy_va(&mut self, on: bool) {
+        self.dll_characteristics
+            .set(DllCharacteristics::IMAGE_DLLCHARACTERISTICS_HIGH_ENTROPY_VA, on);
+    }
+
+    /// DEP: marks the image NX compatible.
+    pub fn set_dep(&mut self, on: bool) {
+        self.dll_characteristics
+            .set(DllCharacteristics::IMAGE_DLLCHARACTERISTICS_NX_COMPAT, on);
+    }
+
+    /// Control Flow Guard, which also needs the guard tables in the load config.
+    pub fn set_cfg(&mut self, on: bool) {
+        self.dll_characteristics
+            .set(DllCharacteristics::IMAGE_DLLCHARACTERISTICS_GUARD_CF, on);
+    }
+
+    /// Requires the image to be signed for it to load.
+    pub fn set_force_integrity(&mut self, on: bool) {
+        self.dll_characteristics
+            .set(DllCharacteristics::IMAGE_DLLCHARACTERISTICS_FORCE_INTEGRITY, on);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::*;
+    use super::*;
+
+    /// An image whose section names have bytes after their NUL, or aren't UTF-8.
+    fn odd_names() -> PeBuilder {
+        let mut builder = PeBuilder::default()
+            .section(".text", 0x1000, vec![0xc3; 0x10], CODE)
+            .section(".data", 0x2000, vec![1; 0x10], DATA)
+            .section(".rsrc", 0x3000, vec![2; 0x10], RDATA);
+        builder.sections[0].name = *b".text\0ab";
+        builder.sections[1].name = *b"\xff\xfe.dat\0\0";
+        builder.overlay = b"overlay".to_vec();
+        builder
+    }
+
+    #[test]
+    fn unmodified_images_are_written_back_byte_for_byte() {
+        let raw = odd_names().build();
+        let (_, file) = File::parse(&raw).unwrap();
+
+        assert_eq!(file.sections[0].name.as_str(), ".text");
+        assert_eq!(file.to_bytes(&raw), raw);
+    }
+
+    #[test]
+    fn section_names_survive_replacing_data() {
+        let builder = odd_names();
+        let raw = builder.build();
+        let (_, mut file) = File::parse(&raw).unwrap();
+        file.set_section_data(0, vec![0x90; 0x300]).unwrap();
+        let written = file.to_bytes(&raw);
+
+        let table = builder.section_table_offset();
+        assert_eq!(&written[table..table + 8], b".text\0ab");
+        assert_eq!(&written[table + 40..table + 48], b"\xff\xfe.dat\0\0");
+        assert!(written.ends_with(b"overlay"));
+        let (_, reparsed) = File::parse(&written).unwrap();
+        assert_eq!(reparsed.sections[0].data[..0x300], [0x90; 0x300][..]);
+    }
+
+    #[test]
+    fn characteristics_set_through_the_model_persist() {
+        let raw = simple_image(vec![0xc3; 0x10]);
+        let (_, mut file) = File::parse(&raw).unwrap();
+        file.set_dll_characteristic(DllCharacteristics::IMAGE_DLLCHARACTERISTICS_GUARD_CF, true);
+        file.set_characteristic(Characteristics::IMAGE_FILE_DLL, true);
+
+        let (_, reparsed) = File::parse(&file.to_bytes(&raw)).unwrap();
+        let windows_header = reparsed.header.optional_header.unwrap().windows_header;
+        let guard_cf = DllCharacteristics::IMAGE_DLLCHARACTERISTICS_GUARD_CF;
+        assert!(windows_header.dll_characteristics.contains(guard_cf));
+        assert!(reparsed.header.characteristics.contains(Characteristics::IMAGE_FILE_DLL));
+    }
+
+    #[test]
+    fn growing_a_section_moves_the_ones_after_it() {
+        let raw = odd_names().build();
+        let (_, mut file) = File::parse(&raw).unwrap();
+        let data_at = file.sections[1].pointer_to_raw_data.0;
+        file.set_section_data(0, vec![0x90; 0x300]).unwrap();
+        assert_eq!(file.sections[0].size_of_raw_data, 0x400);
+
+        let (_, reparsed) = File::parse(&file.to_bytes(&raw)).unwrap();
+        let moved_to = reparsed.sections[1].pointer_to_raw_data.0;
+        assert!(moved_to > data_at && moved_to >= 0x400 + 0x400);
+        assert_eq!(moved_to % 0x200, 0);
+        assert_eq!(reparsed.sections[1].data, [1; 0x10]);
+        assert_eq!(reparsed.sections[2].data, [2; 0x10]);
+        assert!(File::parse(&raw).unwrap().1.set_section_data(3, Vec::new()).is_none());
+    }
+
+    #[test]
+    fn enabling_dep_persists_through_to_bytes() {
+        let builder = PeBuilder {
+            // IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE
+            dll_characteristics: 0x40,
+            ..PeBuilder::default()
+        };
+        let raw = builder.section(".text", 0x1000, vec![0xc3; 0x10], CODE).build();
+        let (_, mut file) = File::parse(&raw).unwrap();
+        let windows_header = &mut file.header.optional_header.as_mut().unwrap().windows_header;
+        assert!(!windows_header
+            .dll_characteristics
+            .contains(DllCharacteristics::IMAGE_DLLCHARACTERISTICS_NX_COMPAT));
+        windows_header.set_dep(true);
+
+        let written = file.to_bytes(&raw);
+        let (_, file) = File::parse(&written).unwrap();
+        let flags = file.header.optional_header.unwrap().windows_header.dll_characteristics;
+        assert_eq!(
+            flags,
+            DllCharacteristics::IMAGE_DLLCHARACTERISTICS_NX_COMPAT
+                | DllCharacteristics::IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE
+        );
+        assert_eq!(written.len(), raw.len());
+    }
+
+    #[test]
+    fn patching_a_zeroed_checksum_writes_the_right_value() {
+        let mut raw = simple_image(vec![0xc3; 0x10]);
+        let (_, file) = File::parse(&raw).unwrap();
+        let at = file.checksum_field_offset().unwrap();
+        assert_eq!(raw[at..at + 4], [0; 4]);
+
+        // Worked out independently, by summing the fixture's words.
+        assert_eq!(file.checksum_recalc_and_patch(&mut raw), Some(0xc415));
+        assert_eq!(raw[at..at + 4], 0xc415_u32.to_le_bytes());
+        // The field reads as zero while summing, so patching again changes nothing.
+        assert_eq!(file.checksum_recalc_and_patch(&mut raw), Some(0xc415));
+        assert_eq!(file.checksum_recalc_and_patch(&mut raw[..at + 2]), None);
+    }
+
+    #[test]
+    fn non_standard_directory_counts_round_trip() {
+        for count in [10, 18] {
+            let mut builder = PeBuilder {
+                number_of_rva_and_sizes: count,
+                ..PeBuilder::default()
+            };
+            builder.directories.extend([(0x1111, 0x11), (0x2222, 0x22)]);
+            let raw = builder.section(".text", 0x1000, vec![0xc3; 0x10], CODE).build();
+            let (_, file) = File::parse(&raw).unwrap();
+
+            let oh = file.header.optional_header.as_ref().unwrap();
+            assert_eq!(oh.windows_header.number_of_rva_and_sizes, count);
+            assert_eq!(file.to_bytes(&raw), raw, "{} directories", count);
+        }
+    }
+}
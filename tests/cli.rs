@@ -0,0 +1,112 @@
+//! Runs the built `squige` binary over images written to a scratch directory.
+
+use std::{fs, path::PathBuf, process::Command};
+
+/// A one-section PE32 image for `machine`, built by hand since the crate's
+/// fixtures aren't visible from integration tests.
+fn tiny_pe(machine: u16, subsystem: u16, time_date_stamp: u32) -> Vec<u8> {
+    let mut raw = vec![0; 0x400];
+    let put16 = |raw: &mut Vec<u8>, at: usize, v: u16| {
+        raw[at..at + 2].copy_from_slice(&v.to_le_bytes())
+    };
+    let put32 = |raw: &mut Vec<u8>, at: usize, v: u32| {
+        raw[at..at + 4].copy_from_slice(&v.to_le_bytes())
+    };
+
+    raw[..2].copy_from_slice(b"MZ");
+    put32(&mut raw, 0x3c, 0x40);
+    raw[0x40..0x44].copy_from_slice(b"PE\0\0");
+
+    let coff = 0x44;
+    put16(&mut raw, coff, machine);
+    put16(&mut raw, coff + 2, 1);
+    put32(&mut raw, coff + 4, time_date_stamp);
+    put16(&mut raw, coff + 16, 0xe0);
+    put16(&mut raw, coff + 18, 0x0102);
+
+    let oh = coff + 20;
+    put16(&mut raw, oh, 0x10b);
+    put32(&mut raw, oh + 16, 0x1000);
+    put32(&mut raw, oh + 28, 0x40_0000);
+    put32(&mut raw, oh + 32, 0x1000);
+    put32(&mut raw, oh + 36, 0x200);
+    put16(&mut raw, oh + 40, 6);
+    put16(&mut raw, oh + 48, 6);
+    put32(&mut raw, oh + 56, 0x2000);
+    put32(&mut raw, oh + 60, 0x200);
+    put16(&mut raw, oh + 68, subsystem);
+    put32(&mut raw, oh + 92, 16);
+
+    let section = oh + 0xe0;
+    raw[section..section + 5].copy_from_slice(b".text");
+    put32(&mut raw, section + 8, 0x200);
+    put32(&mut raw, section + 12, 0x1000);
+    put32(&mut raw, section + 16, 0x200);
+    put32(&mut raw, section + 20, 0x200);
+    put32(&mut raw, section + 36, 0x6000_0020);
+
+    raw[0x200] = 0xc3;
+    raw
+}
+
+/// A fresh directory for one test's inputs.
+fn scratch_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("squige-{}-{}", test, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn squige(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_squige")).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn summary_table_has_a_row_per_file_and_carries_on_past_failures() {
+    let dir = scratch_dir("summary");
+    let gui = dir.join("gui.exe");
+    let console = dir.join("console.exe");
+    let text = dir.join("notes.txt");
+    fs::write(&gui, tiny_pe(0x14c, 2, 0x5f00_0000)).unwrap();
+    fs::write(&console, tiny_pe(0x14c, 3, 0x6000_0000)).unwrap();
+    fs::write(&text, b"not a PE").unwrap();
+
+    let paths = [gui.to_str().unwrap(), text.to_str().unwrap(), console.to_str().unwrap()];
+    let mut args = vec!["--summary-table"];
+    args.extend_from_slice(&paths);
+    let stdout = squige(&args);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", stdout);
+    assert!(lines[0].starts_with("FILE"));
+    assert!(lines[1].starts_with(paths[0]) && lines[1].contains("0x5f000000"));
+    assert!(lines[2].starts_with(&format!("{}: ", paths[1])));
+    assert!(lines[3].starts_with(paths[2]) && lines[3].contains("0x60000000"));
+
+    // The columns line up, whatever the width of each value.
+    let column = |line: &str| line.find("0x").unwrap();
+    assert_eq!(column(lines[1]), column(lines[3]));
+    assert_eq!(column(lines[1]), lines[0].find("TIMESTAMP").unwrap());
+}
+
+#[test]
+fn headers_prints_just_the_selected_fields() {
+    let dir = scratch_dir("fields");
+    let path = dir.join("gui.exe");
+    fs::write(&path, tiny_pe(0x14c, 2, 0x5f00_0000)).unwrap();
+    let path = path.to_str().unwrap();
+
+    let stdout = squige(&["headers", "--fields", "machine,subsystem", path]);
+    assert_eq!(stdout, "I386\nWindowsGui\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_squige"))
+        .args(["headers", "--fields", "machine,colour", path])
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("unknown field colour, expected one of: machine, "), "{}", stderr);
+}